@@ -8,6 +8,7 @@ extern crate serde;
 
 use std::collections::{HashMap, HashSet};
 use std::iter::FromIterator;
+use std::sync::Arc;
 
 #[cfg(feature = "serde")]
 use serde::de;
@@ -43,14 +44,21 @@ pub enum FolderTreeError {
 // However... consider using a BTreeMap for the `nodes` list, and then tree.walk(path) becomes
 // trivial with BTreeMap.range. We could also change it to
 // `nodes: BTreeMap<FolderPath, (T, HashSet<String>)>`
-#[derive(Debug, Eq, PartialEq, Clone)]
+//
+// Each node is kept behind an `Arc`, so that cloning a tree is cheap, and a node is only copied
+// when it's changed while shared with another clone.
+#[derive(Debug, Eq, PartialEq)]
 pub struct FolderTree<T> {
-  nodes: HashMap<FolderPath, (T, HashSet<String>)>,
+  nodes: HashMap<FolderPath, Arc<(T, HashSet<String>)>>,
+}
+
+impl<T> Clone for FolderTree<T> {
+  fn clone(&self) -> FolderTree<T> { FolderTree { nodes: self.nodes.clone() } }
 }
 
 impl<T> Default for FolderTree<T>
 where
-  T: Default,
+  T: Default + Clone,
 {
   fn default() -> FolderTree<T> {
     let root: T = Default::default();
@@ -61,9 +69,40 @@ where
 impl<T> FolderTree<T> {
   pub fn new(root: T) -> FolderTree<T> {
     let path = FolderPath::root();
-    FolderTree { nodes: HashMap::from_iter(vec![(path.clone(), (root, HashSet::new()))]) }
+    let root = Arc::new((root, HashSet::new()));
+    FolderTree { nodes: HashMap::from_iter(vec![(path.clone(), root)]) }
+  }
+
+  pub fn get(&self, path: &FolderPath) -> Result<&T, FolderTreeError> {
+    Ok(&self.get_data(path)?.0)
+  }
+
+  pub fn get_children(&self, path: &FolderPath) -> Result<&HashSet<String>, FolderTreeError> {
+    Ok(&self.get_data(path)?.1)
+  }
+
+  fn get_data(&self, path: &FolderPath) -> Result<&(T, HashSet<String>), FolderTreeError> {
+    self
+      .nodes
+      .get(path)
+      .map(|data| &**data)
+      .ok_or_else(|| FolderTreeError::FolderNotFound(path.clone()).into())
   }
 
+  /// Iterate paths to all folders below the given one.
+  pub fn walk_paths<'a>(&'a self, parent: &FolderPath) -> ::std::vec::IntoIter<&FolderPath> {
+    let parent: FolderPath = parent.clone();
+
+    let all_nodes = self.nodes.keys().filter(move |p| p.is_child_of(&parent));
+    let mut all_nodes = all_nodes.collect::<Vec<_>>();
+    all_nodes.sort();
+    all_nodes.into_iter()
+  }
+}
+
+/// Methods which change the tree. Nodes may need to be copied, if they're shared with another clone
+/// of the tree.
+impl<T: Clone> FolderTree<T> {
   /// Make a child folder.
   /// Returns an error if the child already exists.
   pub fn make_folder(
@@ -77,14 +116,11 @@ impl<T> FolderTree<T> {
       }
       pdata.1.insert(new_child);
     }
-    self.nodes.insert(new_full_path.clone(), (node, HashSet::new()));
+    self.nodes.insert(new_full_path.clone(), Arc::new((node, HashSet::new())));
     Ok(new_full_path)
   }
 
-  pub fn make_folders(&mut self, path: &FolderPath, node: T)
-  where
-    T: Clone,
-  {
+  pub fn make_folders(&mut self, path: &FolderPath, node: T) {
     let mut cur_path = FolderPath::root();
     for seg in &path.0 {
       let child_path = cur_path.child(seg.clone());
@@ -98,18 +134,10 @@ impl<T> FolderTree<T> {
     }
   }
 
-  pub fn get(&self, path: &FolderPath) -> Result<&T, FolderTreeError> {
-    Ok(&self.get_data(path)?.0)
-  }
-
   pub fn get_mut(&mut self, path: &FolderPath) -> Result<&mut T, FolderTreeError> {
     Ok(&mut self.get_data_mut(path)?.0)
   }
 
-  pub fn get_children(&self, path: &FolderPath) -> Result<&HashSet<String>, FolderTreeError> {
-    Ok(&self.get_data(path)?.1)
-  }
-
   /// Remove a folder node. The folder must not have any children. The node data for the folder
   /// will be returned.
   pub fn remove(&mut self, path: &FolderPath) -> Result<T, FolderTreeError> {
@@ -118,8 +146,9 @@ impl<T> FolderTree<T> {
     }
     match path.up() {
       Some((parent, child)) => {
-        self.nodes.get_mut(&parent).expect("Parent must exist").1.remove(&child);
-        Ok(self.nodes.remove(path).expect("Folder must exist if it had children.").0)
+        self.get_data_mut(&parent).expect("Parent must exist").1.remove(&child);
+        let data = self.nodes.remove(path).expect("Folder must exist if it had children.");
+        Ok(Arc::try_unwrap(data).unwrap_or_else(|data| (*data).clone()).0)
       }
       None => return Err(FolderTreeError::CannotRemoveRoot),
     }
@@ -135,7 +164,7 @@ impl<T> FolderTree<T> {
           return Err(FolderTreeError::FolderExists(new_path));
         }
         {
-          let mut_data = self.nodes.get_mut(&parent).expect("Parent must exist.");
+          let mut_data = self.get_data_mut(&parent).expect("Parent must exist.");
           mut_data.1.remove(&basename);
           mut_data.1.insert(new_name.clone());
         }
@@ -175,40 +204,27 @@ impl<T> FolderTree<T> {
           self.nodes.insert(new_path, path_data);
         }
 
-        self.nodes.get_mut(&old_parent).expect("Parent node must exist").1.remove(&basename);
-        self.nodes.get_mut(new_parent).expect("Target directory must exist").1.insert(basename);
+        self.get_data_mut(&old_parent).expect("Parent node must exist").1.remove(&basename);
+        self.get_data_mut(new_parent).expect("Target directory must exist").1.insert(basename);
         Ok(())
       }
       None => return Err(FolderTreeError::CannotMoveRoot),
     }
   }
 
-  fn get_data(&self, path: &FolderPath) -> Result<&(T, HashSet<String>), FolderTreeError> {
-    self.nodes.get(path).ok_or_else(|| FolderTreeError::FolderNotFound(path.clone()).into())
-  }
-
   fn get_data_mut(
     &mut self, path: &FolderPath,
   ) -> Result<&mut (T, HashSet<String>), FolderTreeError> {
-    self.nodes.get_mut(path).ok_or_else(|| FolderTreeError::FolderNotFound(path.clone()).into())
-  }
-
-  /// Iterate paths to all folders below the given one.
-  pub fn walk_paths<'a>(&'a self, parent: &FolderPath) -> ::std::vec::IntoIter<&FolderPath> {
-    let parent: FolderPath = parent.clone();
-
-    let all_nodes = self.nodes.keys().filter(move |p| p.is_child_of(&parent));
-    let mut all_nodes = all_nodes.collect::<Vec<_>>();
-    all_nodes.sort();
-    all_nodes.into_iter()
+    self
+      .nodes
+      .get_mut(path)
+      .map(Arc::make_mut)
+      .ok_or_else(|| FolderTreeError::FolderNotFound(path.clone()).into())
   }
 
   /// Extract a subtree from a FolderPath
   // Our representation requires this to be O(n) which kinda sucks, but it probably doesn't matter
-  pub fn subtree(&self, path: &FolderPath) -> Result<FolderTree<T>, FolderTreeError>
-  where
-    T: Clone,
-  {
+  pub fn subtree(&self, path: &FolderPath) -> Result<FolderTree<T>, FolderTreeError> {
     let folder = self.get(path)?;
     let mut new_tree = FolderTree::new(folder.clone());
     for sub_path in self.walk_paths(path).cloned().collect::<Vec<_>>() {
@@ -226,10 +242,7 @@ impl<T> FolderTree<T> {
 
   pub fn copy_from_tree(
     &mut self, target: &FolderPath, other: &FolderTree<T>,
-  ) -> Result<(), FolderTreeError>
-  where
-    T: Clone,
-  {
+  ) -> Result<(), FolderTreeError> {
     for path in other.walk_paths(&FolderPath::root()) {
       let cur_path = target.descendant(path.0.clone());
       let (parent, child) = cur_path.up().unwrap();
@@ -400,7 +413,7 @@ struct DeserializeHelper<T> {
 }
 
 #[cfg(feature = "serde")]
-impl<T> DeserializeHelper<T> {
+impl<T: Clone> DeserializeHelper<T> {
   fn into_folder_tree(self) -> FolderTree<T> {
     let mut paths: Vec<(FolderPath, T)> = vec![];
     self.serialize_tree(FolderPath::root(), &mut paths);
@@ -431,7 +444,7 @@ impl<T> DeserializeHelper<T> {
 }
 
 #[cfg(feature = "serde")]
-impl<'de, T: de::Deserialize<'de>> de::Deserialize<'de> for FolderTree<T> {
+impl<'de, T: de::Deserialize<'de> + Clone> de::Deserialize<'de> for FolderTree<T> {
   fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
   where
     D: de::Deserializer<'de>,
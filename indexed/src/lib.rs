@@ -1,10 +1,12 @@
 #[cfg(feature = "serde")]
 extern crate serde;
 
+use std::collections::hash_map;
 use std::collections::HashMap;
 use std::fmt;
 use std::hash;
 use std::iter::FromIterator;
+use std::sync::Arc;
 
 #[cfg(feature = "serde")]
 use serde::de;
@@ -20,14 +22,17 @@ pub trait DeriveKey {
 }
 
 /// A `HashMap` which uses keys intrinsic to values with the `DeriveKey` trait.
+///
+/// Values are stored behind `Arc`s, so cloning the map doesn't clone any of the values; a value is
+/// only copied when it's mutated while shared with another clone of the map.
 #[derive(Eq, PartialEq)]
 pub struct IndexedHashMap<V: DeriveKey> {
-  data: HashMap<<V as DeriveKey>::KeyType, V>,
+  data: HashMap<<V as DeriveKey>::KeyType, Arc<V>>,
 }
 
 impl<V> Clone for IndexedHashMap<V>
 where
-  V: DeriveKey + Clone,
+  V: DeriveKey,
   <V as DeriveKey>::KeyType: Clone,
 {
   fn clone(&self) -> Self { IndexedHashMap { data: self.data.clone() } }
@@ -58,7 +63,7 @@ where
   where
     S: ser::Serializer,
   {
-    self.data.serialize(s)
+    s.collect_map(self.data.iter().map(|(k, v)| (k, &**v)))
   }
 }
 
@@ -73,7 +78,7 @@ where
     D: de::Deserializer<'de>,
   {
     let hm: HashMap<<V as DeriveKey>::KeyType, V> = de::Deserialize::deserialize(deserializer)?;
-    Ok(IndexedHashMap { data: hm })
+    Ok(IndexedHashMap { data: hm.into_iter().map(|(k, v)| (k, Arc::new(v))).collect() })
   }
 }
 
@@ -85,45 +90,55 @@ where
   where
     T: IntoIterator<Item = V>,
   {
-    IndexedHashMap { data: iter.into_iter().map(|v| (v.derive_key(), v)).collect() }
+    IndexedHashMap { data: iter.into_iter().map(|v| (v.derive_key(), Arc::new(v))).collect() }
   }
 }
 
+/// An iterator over the values of an `IndexedHashMap`.
+pub struct Values<'a, K, V> {
+  inner: hash_map::Values<'a, K, Arc<V>>,
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+  type Item = &'a V;
+  fn next(&mut self) -> Option<&'a V> { self.inner.next().map(|v| &**v) }
+  fn size_hint(&self) -> (usize, Option<usize>) { self.inner.size_hint() }
+}
+
+impl<'a, K, V> ExactSizeIterator for Values<'a, K, V> {}
+
 impl<'a, V: DeriveKey> IntoIterator for &'a IndexedHashMap<V> {
   type Item = &'a V;
-  type IntoIter = ::std::collections::hash_map::Values<'a, <V as DeriveKey>::KeyType, V>;
-  fn into_iter(self) -> Self::IntoIter { self.data.values() }
+  type IntoIter = Values<'a, <V as DeriveKey>::KeyType, V>;
+  fn into_iter(self) -> Self::IntoIter { Values { inner: self.data.values() } }
 }
 
 impl<V: DeriveKey> IndexedHashMap<V> {
   pub fn new() -> IndexedHashMap<V> { IndexedHashMap { data: HashMap::new() } }
 
-  pub fn iter(&self) -> ::std::collections::hash_map::Values<<V as DeriveKey>::KeyType, V> {
-    self.into_iter()
-  }
+  pub fn iter(&self) -> Values<<V as DeriveKey>::KeyType, V> { self.into_iter() }
 
-  pub fn keys(&self) -> ::std::collections::hash_map::Keys<<V as DeriveKey>::KeyType, V> {
-    self.data.keys()
-  }
+  pub fn keys(&self) -> hash_map::Keys<<V as DeriveKey>::KeyType, Arc<V>> { self.data.keys() }
 
-  pub fn insert(&mut self, v: V) -> Option<V> { self.data.insert(v.derive_key(), v) }
+  /// Insert a value, returning the value that had the same key, if any.
+  pub fn insert(&mut self, v: V) -> Option<Arc<V>> { self.data.insert(v.derive_key(), Arc::new(v)) }
 
   pub fn get<'a, Q: ?Sized>(&'a self, k: &Q) -> Option<&'a V>
   where
     <V as DeriveKey>::KeyType: ::std::borrow::Borrow<Q>,
     Q: hash::Hash + Eq,
   {
-    self.data.get(k)
+    self.data.get(k).map(|v| &**v)
   }
 
-  pub fn remove(&mut self, k: &<V as DeriveKey>::KeyType) -> Option<V> { self.data.remove(k) }
+  pub fn remove(&mut self, k: &<V as DeriveKey>::KeyType) -> Option<Arc<V>> {
+    self.data.remove(k)
+  }
   pub fn contains_key<'a>(&'a self, k: &<V as DeriveKey>::KeyType) -> bool {
     self.data.contains_key(k)
   }
 
-  pub fn values(&self) -> ::std::collections::hash_map::Values<<V as DeriveKey>::KeyType, V> {
-    self.data.values()
-  }
+  pub fn values(&self) -> Values<<V as DeriveKey>::KeyType, V> { self.into_iter() }
 
   pub fn len(&self) -> usize { self.data.len() }
 
@@ -137,14 +152,17 @@ impl<V: DeriveKey> IndexedHashMap<V> {
   // - If we want to handle panics without leaving the collection in an inconsistent state, we'll
   //   need to explicitly catch them.
   // TODO: BORROW the key?
+  //
+  // If the value is shared with another clone of this map, it's copied first.
   pub fn mutate<F>(&mut self, k: &<V as DeriveKey>::KeyType, f: F) -> Option<()>
   where
+    V: Clone,
     F: FnOnce(&mut V) -> (),
   {
     match self.data.remove(k) {
       Some(mut thing) => {
-        f(&mut thing);
-        self.insert(thing);
+        f(Arc::make_mut(&mut thing));
+        self.data.insert(thing.derive_key(), thing);
         Some(())
       }
       None => None,
@@ -152,8 +170,8 @@ impl<V: DeriveKey> IndexedHashMap<V> {
   }
 
   pub fn try_insert(&mut self, v: V) -> Option<()> {
-    if let ::std::collections::hash_map::Entry::Vacant(entry) = self.data.entry(v.derive_key()) {
-      entry.insert(v);
+    if let hash_map::Entry::Vacant(entry) = self.data.entry(v.derive_key()) {
+      entry.insert(Arc::new(v));
       Some(())
     } else {
       None
@@ -165,7 +183,7 @@ impl<V: DeriveKey> IndexedHashMap<V> {
 mod test {
   use *;
 
-  #[derive(Eq, PartialEq, Debug)]
+  #[derive(Clone, Eq, PartialEq, Debug)]
   struct TestObj {
     name: String,
     num: u8,
@@ -183,4 +201,14 @@ mod test {
     hm.mutate(&"Bob".to_string(), |b| b.name = "Bab".to_string());
     assert_eq!(hm.get("Bab").unwrap(), &TestObj { name: "Bab".to_string(), num: 37 });
   }
+
+  #[test]
+  fn mutate_doesnt_affect_clones() {
+    let mut hm = IndexedHashMap::new();
+    hm.insert(TestObj { name: "Bob".to_string(), num: 37 });
+    let old = hm.clone();
+    hm.mutate(&"Bob".to_string(), |b| b.num = 38);
+    assert_eq!(hm.get("Bob").unwrap().num, 38);
+    assert_eq!(old.get("Bob").unwrap().num, 37);
+  }
 }
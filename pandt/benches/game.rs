@@ -0,0 +1,59 @@
+//! Benchmarks for changing a game with a large map in it.
+
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use pandt::types::*;
+
+const SAMPLE_GAME: &str = include_str!("../../ptrpi/sample_games/samplegame.yaml");
+
+/// The sample game, with one of its scenes blown up to 200x200 squares and holding 100 creatures.
+fn big_game() -> (Game, SceneID, Vec<CreatureID>) {
+  let app: App = serde_yaml::from_str(SAMPLE_GAME).expect("sample game must parse");
+  let mut game = app.current_game;
+  let scene_id = game.scenes.keys().cloned().min().expect("sample game must have a scene");
+  let template = game.creatures.iter().next().expect("sample game must have a creature").clone();
+
+  let terrain: Terrain =
+    (0..200).flat_map(|x| (0..200).map(move |y| Point3::new(x * 100, y * 100, 0))).collect();
+  let mut cids = vec![];
+  for idx in 0..100 {
+    let mut creature = template.clone();
+    creature.id = CreatureID::gen();
+    cids.push(creature.id);
+    game.creatures.insert(creature);
+    let pos = Point3::new(idx % 10 * 100, idx / 10 * 100, 0);
+    game.scenes.mutate(&scene_id, |s| {
+      s.creatures.insert(cids[idx as usize], (pos, Visibility::AllPlayers));
+    });
+  }
+  game.scenes.mutate(&scene_id, |s| s.terrain = Arc::new(terrain));
+  (game, scene_id, cids)
+}
+
+fn apply_logs(c: &mut Criterion) {
+  let (game, scene_id, cids) = big_game();
+
+  c.bench_function("clone game", |b| b.iter(|| black_box(&game).clone()));
+
+  let step = GameLog::SetCreaturePos(scene_id, cids[0], Point3::new(1000, 1000, 0));
+  c.bench_function("move a creature", |b| b.iter(|| black_box(&game).apply_log(&step).unwrap()));
+
+  let fireball: Vec<GameLog> = cids[..20]
+    .iter()
+    .map(|&cid| GameLog::CreatureLog(cid, CreatureLog::Damage(HP(1), vec![1])))
+    .collect();
+  c.bench_function("fireball hitting 20 creatures", |b| {
+    b.iter(|| {
+      let mut change = black_box(&game).change();
+      for log in &fireball {
+        change = change.apply(log).unwrap();
+      }
+      change
+    })
+  });
+}
+
+criterion_group!(benches, apply_logs);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+//! Benchmarks for movement on a large map.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use pandt::types::*;
+
+/// A 200x200 square map with a wall down the middle, which has a gap at one end.
+fn walled_map() -> Terrain {
+  (0..200)
+    .flat_map(|x| (0..200).map(move |y| (x, y)))
+    .filter(|&(x, y)| x != 100 || y == 199)
+    .map(|(x, y)| Point3::new(x * 100, y * 100, 0))
+    .collect()
+}
+
+fn movement(c: &mut Criterion) {
+  let terrain = walled_map();
+  let volume = Volume::AABB(AABB { x: u32cm(100), y: u32cm(100), z: u32cm(100) });
+  let start = Point3::new(9000, 0, 0);
+
+  c.bench_function("accessible points with 30m of movement", |b| {
+    b.iter(|| TileSystem::Realistic.get_all_accessible(start, black_box(&terrain), volume, u32cm(3000)))
+  });
+
+  let dest = Point3::new(11000, 0, 0);
+  c.bench_function("path around a wall", |b| {
+    b.iter(|| {
+      TileSystem::Realistic.find_path(start, u32cm(100_000), black_box(&terrain), volume, dest)
+    })
+  });
+}
+
+criterion_group!(benches, movement);
+criterion_main!(benches);
@@ -1,8 +1,13 @@
-use std::collections::VecDeque;
-use std::path::Path;
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+use std::path::PathBuf;
 
 use error_chain::bail;
+use foldertree::FolderPath;
+use ptstorage::Storage;
+use rand::{rngs::StdRng, SeedableRng};
 
+use crate::journal;
 use crate::types::*;
 
 // random misplaced notes
@@ -34,116 +39,741 @@ use crate::types::*;
 // probably don't want to just accept a modify Game back from the client...
 // But maybe that's okay actually, we would only be sending it to the GM.
 
+/// How many logs to keep in each snapshot. The whole history is kept, so this just determines how
+/// often a checkpoint of the game is taken, and so how many logs a rollback has to replay.
 const LOGS_PER_SNAP: usize = 300;
-const SNAPSHOTS: usize = 2;
+
+pub fn random_rng_seed() -> u64 { rand::random() }
+
+/// FNV-1a, which (unlike `DefaultHasher`) hashes the same way in every release of Rust, so a
+/// command rolls the same dice whichever build of the server performs it.
+fn stable_hash(s: &str) -> u64 {
+  s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+    (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+  })
+}
+
+pub fn initial_branches() -> Vec<Branch> { vec![Branch::default()] }
+
+fn is_navigation(log: &GameLog) -> bool {
+  matches!(*log, GameLog::Rollback(..) | GameLog::SwitchBranch(..) | GameLog::Undo | GameLog::Redo)
+}
+
+/// The result of editing a log in the history; see `App::rewrite_history`.
+struct EditedHistory {
+  game: Game,
+  snapshots: VecDeque<(Game, Vec<GameLog>)>,
+  commands: Vec<(usize, usize)>,
+  invalidated: Vec<InvalidatedLog>,
+}
+
+/// The game as of the end of some history, or None if there is no history.
+fn head(snapshots: &VecDeque<(Game, Vec<GameLog>)>) -> Result<Option<Game>, GameError> {
+  match snapshots.back() {
+    Some((baseline, logs)) => {
+      let mut game = baseline.clone();
+      for log in logs {
+        game = game.apply_log(log)?;
+      }
+      Ok(Some(game))
+    }
+    None => Ok(None),
+  }
+}
+
+/// The game after the first `log_idx` logs of a snapshot in a history from before branches were
+/// added, where a rollback was recorded as a `GameLog::Rollback` in the middle of the logs.
+fn legacy_game_at(
+  snapshots: &VecDeque<(Game, Vec<GameLog>)>, snapshot_idx: usize, log_idx: usize,
+) -> Result<Game, GameError> {
+  let (baseline, logs) = snapshots
+    .get(snapshot_idx)
+    .filter(|(_, logs)| log_idx <= logs.len())
+    .ok_or_else(|| GameError::HistoryNotFound(snapshot_idx, log_idx))?;
+  let mut game = baseline.clone();
+  for (idx, log) in logs[..log_idx].iter().enumerate() {
+    game = match *log {
+      // A rollback can only go back to somewhere earlier in the history.
+      GameLog::Rollback(s_idx, l_idx) if (s_idx, l_idx) < (snapshot_idx, idx) => {
+        legacy_game_at(snapshots, s_idx, l_idx)?
+      }
+      GameLog::Rollback(s_idx, l_idx) => bail!(GameError::HistoryNotFound(s_idx, l_idx)),
+      _ => game.apply_log(log)?,
+    };
+  }
+  Ok(game)
+}
 
 impl App {
   pub fn new(g: Game) -> Self {
-    let snapshots = VecDeque::with_capacity(SNAPSHOTS);
-    App { current_game: g, snapshots: snapshots }
+    App {
+      current_game: g,
+      snapshots: VecDeque::new(),
+      branches: initial_branches(),
+      current_branch: 0,
+      commands: vec![],
+      undone: 0,
+      rng_seed: random_rng_seed(),
+      rng_counter: 0,
+      journal_id: 0,
+      journal_path: None,
+      pending: HashMap::new(),
+    }
+  }
+
+  /// The RNG to use for the next command. It's seeded from both `rng_seed` and `rng_counter`, so
+  /// every command gets its own stream of random numbers, and re-performing a command from the
+  /// same state will roll exactly the same dice.
+  ///
+  /// Commands from players are also seeded with the player's ID, so that a command which is left
+  /// pending (and so doesn't advance `rng_counter`) won't roll the same dice as whatever the GM
+  /// does next.
+  fn command_rng(&self, player_id: Option<&PlayerID>) -> StdRng {
+    let mut seed = <StdRng as SeedableRng>::Seed::default();
+    seed[..8].copy_from_slice(&self.rng_seed.to_le_bytes());
+    seed[8..16].copy_from_slice(&self.rng_counter.to_le_bytes());
+    if let Some(player_id) = player_id {
+      seed[16..24].copy_from_slice(&stable_hash(&player_id.0).to_le_bytes());
+    }
+    StdRng::from_seed(seed)
   }
+
   pub fn perform_command(
-    &mut self, cmd: GameCommand, saved_game_path: &Path, module_path: Option<&Path>,
+    &mut self, cmd: GameCommand, storage: &dyn Storage,
   ) -> Result<(&Game, Vec<GameLog>), GameError> {
-    match cmd {
-      GameCommand::Rollback(ref snapshot_idx, ref log_idx) => {
-        let newgame = self.rollback_to(*snapshot_idx, *log_idx)?;
-        self.current_game = newgame;
-        let log = GameLog::Rollback(*snapshot_idx, *log_idx);
-        self.snapshots.back_mut().unwrap().1.push(log.clone());
-        Ok((&self.current_game, vec![log]))
+    let approved = match cmd {
+      GameCommand::ApprovePendingCommand(ref player_id) => Some(player_id.clone()),
+      _ => None,
+    };
+    let (game, logs) = match cmd {
+      GameCommand::Rollback(snapshot_idx, log_idx) => {
+        self.navigate(GameLog::Rollback(snapshot_idx, log_idx))?
+      }
+      GameCommand::SwitchBranch(branch) => self.navigate(GameLog::SwitchBranch(branch))?,
+      GameCommand::Undo => self.navigate(GameLog::Undo)?,
+      GameCommand::Redo => self.navigate(GameLog::Redo)?,
+      GameCommand::EditLog(snapshot_idx, log_idx, log) => {
+        let invalidated = self.edit_log(snapshot_idx, log_idx, log.clone())?;
+        let log = GameLog::EditLog { snapshot_idx, log_idx, log: Box::new(log), invalidated };
+        return Ok((&self.current_game, vec![log]));
+      }
+      GameCommand::ApprovePendingCommand(ref player_id) => {
+        let pending = self
+          .pending
+          .get(player_id)
+          .ok_or_else(|| GameError::NoPendingCommand(player_id.clone()))?;
+        // The game may have changed since the command was performed, so its logs are applied to
+        // the current game instead of taking the game it resulted in.
+        let mut game = self.current_game.clone();
+        for log in &pending.change.logs {
+          game = game.apply_log(log)?;
+        }
+        (game, pending.change.logs.clone())
+      }
+      GameCommand::RejectPendingCommand(player_id) => {
+        self.pending.remove(&player_id).ok_or(GameError::NoPendingCommand(player_id))?;
+        return Ok((&self.current_game, vec![]));
+      }
+      GameCommand::EditPendingCommand(player_id, cmd) => {
+        if !self.pending.contains_key(&player_id) {
+          bail!(GameError::NoPendingCommand(player_id));
+        }
+        // The new command is still the player's, so it's held to what they could have done.
+        self.authorize_player_command(&player_id, &cmd)?;
+        let mut rng = self.command_rng(Some(&player_id));
+        let change = self.current_game.perform_command((*cmd).clone(), &mut rng, storage)?;
+        self.pending.insert(player_id, PendingCommand { command: *cmd, change });
+        return Ok((&self.current_game, vec![]));
       }
       _ => {
-        let (game, logs) =
-          self.current_game.perform_command(cmd.clone(), saved_game_path, module_path)?.done();
+        let mut rng = self.command_rng(None);
+        self.current_game.perform_command(cmd, &mut rng, storage)?.done()
+      }
+    };
+    let logs = self.record(game, logs)?;
+    if let Some(player_id) = approved {
+      self.pending.remove(&player_id);
+    }
+    Ok((&self.current_game, logs))
+  }
+
+  /// Perform a command on behalf of a player. Depending on the game's `VettingPolicy`, the command
+  /// may be left pending until the GM approves or rejects it; in that case the game is unchanged
+  /// and no logs are returned.
+  pub fn perform_player_command(
+    &mut self, player_id: PlayerID, cmd: GameCommand, storage: &dyn Storage,
+  ) -> Result<(&Game, Vec<GameLog>), GameError> {
+    self.authorize_player_command(&player_id, &cmd)?;
+    if self.pending.contains_key(&player_id) {
+      bail!(GameError::PendingCommandExists(player_id));
+    }
+    let mut rng = self.command_rng(Some(&player_id));
+    let change = self.current_game.perform_command(cmd.clone(), &mut rng, storage)?;
+    if self.needs_vetting(&change.logs) {
+      self.pending.insert(player_id, PendingCommand { command: cmd, change });
+      return Ok((&self.current_game, vec![]));
+    }
+    let (game, logs) = change.done();
+    let logs = self.record(game, logs)?;
+    Ok((&self.current_game, logs))
+  }
+
+  /// Check that a player is allowed to perform a command at all. Players can register and chat as
+  /// themselves, keep notes in their own folder, and act with the creatures they control. In a
+  /// scene that's in combat they can only act on their turn, with the combat commands, so that
+  /// they use up the turn's movement. Everything else (including teleporting creatures with
+  /// `SetCreaturePos`) is up to the GM.
+  fn authorize_player_command(
+    &self, player_id: &PlayerID, cmd: &GameCommand,
+  ) -> Result<(), GameError> {
+    use self::GameCommand::*;
+    let as_self = |pid: &PlayerID| {
+      if pid == player_id {
+        Ok(())
+      } else {
+        Err(GameError::NotThatPlayer(player_id.clone(), pid.clone()))
+      }
+    };
+    if let RegisterPlayer(ref pid) = *cmd {
+      return as_self(pid);
+    }
+    let game = &self.current_game;
+    let controls = |cid: CreatureID| self.check_controls(player_id, cid);
+    let notes = FolderPath::from_vec(vec!["Players".to_string(), player_id.0.clone()]);
+    match *cmd {
+      ChatFromPlayer(ref pid, _) => as_self(pid),
+      CreateFolder(ref path) | CreateNote(ref path, _) | EditNote(ref path, ..)
+        if *path == notes =>
+      {
+        Ok(())
+      }
+      ActCreature(scene_id, cid, ..) | PathCreature(scene_id, cid, _) => {
+        if game.current_combat.as_ref().map_or(false, |combat| combat.scene == scene_id) {
+          bail!(GameError::SceneInCombat(scene_id));
+        }
+        controls(cid)
+      }
+      AttributeCheck(cid, _)
+      | TransferItem { from: InventoryOwner::Creature(cid), .. }
+      | RemoveItem { owner: InventoryOwner::Creature(cid), .. } => controls(cid),
+      CombatAct(..) | PathCurrentCombatCreature(..) | Done => {
+        let combat = game.current_combat.as_ref().ok_or(GameError::NotInCombat)?;
+        controls(combat.current_creature_id())
+      }
+      _ => Err(GameError::GMOnlyCommand(cmd.clone())),
+    }
+  }
+
+  fn check_controls(&self, player_id: &PlayerID, cid: CreatureID) -> Result<(), GameError> {
+    if self.current_game.get_player(player_id)?.creatures.contains(&cid) {
+      Ok(())
+    } else {
+      Err(GameError::PlayerDoesntControlCreature(player_id.clone(), cid))
+    }
+  }
+
+  /// Leave out the creatures that a player can't see in a scene.
+  fn visible_creatures(
+    &self, player_id: &PlayerID, scene_id: SceneID, cids: Vec<CreatureID>,
+  ) -> Result<Vec<CreatureID>, GameError> {
+    let scene = self.current_game.scene_for_player(player_id, scene_id)?;
+    Ok(cids.into_iter().filter(|cid| scene.creatures.contains_key(cid)).collect())
+  }
+
+  /// Does a player command that produced these logs need to be approved by the GM?
+  fn needs_vetting(&self, logs: &[GameLog]) -> bool {
+    let game = &self.current_game;
+    match game.vetting_policy {
+      VettingPolicy::Never => false,
+      VettingPolicy::AllCommands => true,
+      VettingPolicy::ActionsOnNPCs => logs.iter().any(|log| match *log {
+        GameLog::CreatureLog(cid, _) => !game.players.iter().any(|p| p.creatures.contains(&cid)),
+        _ => false,
+      }),
+    }
+  }
 
+  /// Journal the logs from a command and commit them.
+  fn record(&mut self, game: Game, logs: Vec<GameLog>) -> Result<Vec<GameLog>, GameError> {
+    // The journal is written first, so that a command is never applied without being recorded.
+    if let Some(ref path) = self.journal_path {
+      journal::append(path, &logs)?;
+    }
+    self.commit(game, logs.clone());
+    Ok(logs)
+  }
+
+  /// Work out the game that a log which moves around in the history (a Rollback, SwitchBranch,
+  /// Undo or Redo) will lead to, without changing anything yet.
+  fn navigate(&self, log: GameLog) -> Result<(Game, Vec<GameLog>), GameError> {
+    let game = match log {
+      GameLog::Rollback(snapshot_idx, log_idx) => self.rollback_to(snapshot_idx, log_idx)?,
+      GameLog::SwitchBranch(branch) => {
+        if branch == self.current_branch {
+          // This just redoes everything that was undone.
+          match self.undone {
+            0 => self.current_game.clone(),
+            _ => head(&self.snapshots)?.expect("There's history to redo"),
+          }
+        } else {
+          let branch = self.branches.get(branch).ok_or(GameError::BranchNotFound(branch))?;
+          head(&branch.snapshots)?.ok_or_else(|| GameError::BuggyProgram("Empty branch".into()))?
+        }
+      }
+      GameLog::Undo => {
+        if self.undone >= self.commands.len() {
+          bail!(GameError::NothingToUndo);
+        }
+        let (snapshot_idx, log_idx) = self.commands[self.commands.len() - self.undone - 1];
+        self.rollback_to(snapshot_idx, log_idx)?
+      }
+      GameLog::Redo => match self.undone {
+        0 => bail!(GameError::NothingToRedo),
+        1 => head(&self.snapshots)?.expect("There's history to redo"),
+        undone => {
+          let (snapshot_idx, log_idx) = self.commands[self.commands.len() - undone + 1];
+          self.rollback_to(snapshot_idx, log_idx)?
+        }
+      },
+      _ => bail!(GameError::BuggyProgram(format!("Can't navigate with {:?}", log))),
+    };
+    Ok((game, vec![log]))
+  }
+
+  /// Record the logs produced by a single command in the history, and make `game` (the result of
+  /// that command) the current game.
+  ///
+  /// Logs that navigate the history (see `navigate`) aren't recorded in any branch; instead they
+  /// change which branch is current, or how much of it has been undone.
+  fn commit(&mut self, game: Game, logs: Vec<GameLog>) {
+    match *logs.as_slice() {
+      [GameLog::Rollback(snapshot_idx, log_idx)] => self.fork(snapshot_idx, log_idx),
+      [GameLog::SwitchBranch(branch)] => self.switch_branch(branch),
+      [GameLog::Undo] => self.undone += 1,
+      [GameLog::Redo] => self.undone -= 1,
+      _ => {
+        self.split_off_undone();
+        self.rng_counter += 1;
         if self.snapshots.is_empty()
           || self.snapshots.back().unwrap().1.len() + logs.len() > LOGS_PER_SNAP
         {
           self.snapshots.push_back((self.current_game.clone(), Vec::with_capacity(LOGS_PER_SNAP)));
         }
+        // Commands that didn't do anything aren't worth undoing.
+        if !logs.is_empty() {
+          let log_idx = self.snapshots.back().unwrap().1.len();
+          self.commands.push((self.snapshots.len() - 1, log_idx));
+        }
+        self.snapshots.back_mut().unwrap().1.extend(logs);
+      }
+    }
+    self.current_game = game;
+  }
+
+  /// Start a new branch containing the current branch's history up to the given point, and make
+  /// it current. The point must already have been validated with `rollback_to`.
+  fn fork(&mut self, snapshot_idx: usize, log_idx: usize) {
+    let mut snapshots: VecDeque<_> =
+      self.snapshots.iter().take(snapshot_idx + 1).cloned().collect();
+    snapshots.back_mut().unwrap().1.truncate(log_idx);
+    let commands = self.commands.iter().cloned().filter(|&c| c < (snapshot_idx, log_idx)).collect();
+    let parent = BranchPoint { branch: self.current_branch, snapshot_idx, log_idx };
+    self.branches.push(Branch { parent: Some(parent), ..Default::default() });
+    self.switch_branch(self.branches.len() - 1);
+    self.snapshots = snapshots;
+    self.commands = commands;
+  }
+
+  /// Make another branch current, storing the history of the current one in `branches`. The
+  /// branch must exist.
+  ///
+  /// Anything that was undone in the old branch is forgotten about: its history still contains
+  /// the undone commands, and switching back to it will start from the end of them.
+  fn switch_branch(&mut self, branch: usize) {
+    self.undone = 0;
+    if branch == self.current_branch {
+      return;
+    }
+    let snapshots = mem::take(&mut self.branches[branch].snapshots);
+    let commands = mem::take(&mut self.branches[branch].commands);
+    let old = &mut self.branches[self.current_branch];
+    old.snapshots = mem::replace(&mut self.snapshots, snapshots);
+    old.commands = mem::replace(&mut self.commands, commands);
+    self.current_branch = branch;
+  }
+
+  /// When a new command is performed after some have been undone, move the undone commands out to
+  /// a branch of their own, so that they aren't lost.
+  fn split_off_undone(&mut self) {
+    if self.undone == 0 {
+      return;
+    }
+    let keep = self.commands.len() - self.undone;
+    let (snapshot_idx, log_idx) = self.commands[keep];
+    self.branches.push(Branch {
+      parent: Some(BranchPoint { branch: self.current_branch, snapshot_idx, log_idx }),
+      snapshots: self.snapshots.clone(),
+      commands: self.commands.clone(),
+    });
+    self.snapshots.truncate(snapshot_idx + 1);
+    self.snapshots.back_mut().unwrap().1.truncate(log_idx);
+    self.commands.truncate(keep);
+    self.undone = 0;
+  }
+
+  /// Replace the log at the given point in the current branch's history, and re-apply everything
+  /// that happened after it. Any later logs that can no longer be applied are dropped from the
+  /// history and returned. Commands that were undone are not re-applied.
+  ///
+  /// The history from before the edit (including anything undone) is kept as a new branch, so
+  /// the edit can be reverted by switching back to it.
+  pub fn edit_log(
+    &mut self, snapshot_idx: usize, log_idx: usize, log: GameLog,
+  ) -> Result<Vec<InvalidatedLog>, GameError> {
+    let edited = self.rewrite_history(snapshot_idx, log_idx, &log)?;
+    let invalidated = edited.invalidated.clone();
+    if let Some(ref path) = self.journal_path {
+      let log = GameLog::EditLog {
+        snapshot_idx,
+        log_idx,
+        log: Box::new(log),
+        invalidated: invalidated.clone(),
+      };
+      journal::append(path, &[log])?;
+    }
+    self.replace_history(snapshot_idx, log_idx, edited);
+    Ok(invalidated)
+  }
+
+  /// Work out what the current branch's history would be if the log at the given point were
+  /// `new_log`, without changing anything yet.
+  fn rewrite_history(
+    &self, snapshot_idx: usize, log_idx: usize, new_log: &GameLog,
+  ) -> Result<EditedHistory, GameError> {
+    if is_navigation(new_log) || matches!(*new_log, GameLog::EditLog { .. }) {
+      bail!(GameError::CannotEditLog(new_log.clone()));
+    }
+    // Where the undone commands (if any) start.
+    let end = match self.undone {
+      0 => (self.snapshots.len(), 0),
+      undone => self.commands[self.commands.len() - undone],
+    };
+    if (snapshot_idx, log_idx) >= end {
+      bail!(GameError::HistoryNotFound(snapshot_idx, log_idx));
+    }
+    let mut game = self.rollback_to(snapshot_idx, log_idx)?.apply_log(new_log)?;
+
+    let mut snapshots: VecDeque<_> =
+      self.snapshots.iter().take(snapshot_idx + 1).cloned().collect();
+    {
+      let logs = &mut snapshots.back_mut().unwrap().1;
+      logs.truncate(log_idx);
+      logs.push(new_log.clone());
+    }
+    let mut commands: Vec<_> =
+      self.commands.iter().cloned().filter(|&c| c <= (snapshot_idx, log_idx)).collect();
+
+    // Group the later logs by the command that produced them, so that they can be committed again
+    // in the same way. The first group is the rest of the edited log's own command. A command's
+    // logs are never split between snapshots, so each snapshot starts a new group.
+    let mut groups = vec![(false, vec![])];
+    for (s_idx, (_, logs)) in self.snapshots.iter().enumerate().skip(snapshot_idx) {
+      for (l_idx, log) in logs.iter().enumerate() {
+        if (s_idx, l_idx) <= (snapshot_idx, log_idx) || (s_idx, l_idx) >= end {
+          continue;
+        }
+        let is_command = self.commands.binary_search(&(s_idx, l_idx)).is_ok();
+        if is_command || (l_idx == 0 && s_idx > snapshot_idx) {
+          groups.push((is_command, vec![]));
+        }
+        groups.last_mut().unwrap().1.push((s_idx, l_idx, log));
+      }
+    }
 
-        for _ in 0..(self.snapshots.len().saturating_sub(SNAPSHOTS)) {
-          println!("There are {} too many snapshots", self.snapshots.len() - SNAPSHOTS);
-          self.snapshots.pop_front();
+    let mut invalidated = vec![];
+    for (group_idx, (is_command, logs)) in groups.into_iter().enumerate() {
+      if group_idx > 0 && snapshots.back().unwrap().1.len() + logs.len() > LOGS_PER_SNAP {
+        snapshots.push_back((game.clone(), Vec::with_capacity(LOGS_PER_SNAP)));
+      }
+      let start = (snapshots.len() - 1, snapshots.back().unwrap().1.len());
+      for (s_idx, l_idx, log) in logs {
+        match game.apply_log(log) {
+          Ok(new_game) => {
+            game = new_game;
+            snapshots.back_mut().unwrap().1.push(log.clone());
+          }
+          Err(e) => invalidated.push(InvalidatedLog {
+            snapshot_idx: s_idx,
+            log_idx: l_idx,
+            log: log.clone(),
+            error: e.to_string(),
+          }),
         }
+      }
+      if is_command && snapshots.back().unwrap().1.len() > start.1 {
+        commands.push(start);
+      }
+    }
+    Ok(EditedHistory { game, snapshots, commands, invalidated })
+  }
 
-        self.snapshots.back_mut().unwrap().1.extend(logs.clone());
-        self.current_game = game;
-        Ok((&self.current_game, logs))
+  /// Make an edited history the current branch's, moving the old history out to a new branch.
+  fn replace_history(&mut self, snapshot_idx: usize, log_idx: usize, edited: EditedHistory) {
+    let parent = BranchPoint { branch: self.current_branch, snapshot_idx, log_idx };
+    self.branches.push(Branch {
+      parent: Some(parent),
+      snapshots: mem::replace(&mut self.snapshots, edited.snapshots),
+      commands: mem::replace(&mut self.commands, edited.commands),
+    });
+    self.undone = 0;
+    self.current_game = edited.game;
+  }
+
+  /// Describe all of the branches of history.
+  pub fn list_branches(&self) -> Vec<BranchInfo> {
+    self
+      .branches
+      .iter()
+      .enumerate()
+      .map(|(idx, branch)| {
+        let snapshots =
+          if idx == self.current_branch { &self.snapshots } else { &branch.snapshots };
+        BranchInfo {
+          parent: branch.parent,
+          log_count: snapshots.iter().map(|(_, logs)| logs.len()).sum(),
+        }
+      })
+      .collect()
+  }
+
+  /// Saved games from before branches were added have each rollback recorded as a
+  /// `GameLog::Rollback` in the middle of their history, which can't be applied like other logs.
+  /// Replace each of them with a new snapshot of the game that was rolled back to. The logs that
+  /// were rolled back over stay in the history, just as they used to.
+  ///
+  /// The new snapshots change the indices of later logs, but none of those games have any
+  /// commands recorded for undoing.
+  pub fn migrate_legacy_rollbacks(&mut self) -> Result<(), GameError> {
+    let is_rollback = |log: &GameLog| matches!(*log, GameLog::Rollback(..));
+    if !self.snapshots.iter().any(|(_, logs)| logs.iter().any(is_rollback)) {
+      return Ok(());
+    }
+    let legacy = mem::take(&mut self.snapshots);
+    for (snapshot_idx, (baseline, logs)) in legacy.iter().enumerate() {
+      self.snapshots.push_back((baseline.clone(), vec![]));
+      for (log_idx, log) in logs.iter().enumerate() {
+        if is_rollback(log) {
+          let game = legacy_game_at(&legacy, snapshot_idx, log_idx + 1)?;
+          self.snapshots.push_back((game, vec![]));
+        } else {
+          self.snapshots.back_mut().unwrap().1.push(log.clone());
+        }
       }
     }
+    self.commands.clear();
+    self.undone = 0;
+    Ok(())
+  }
+
+  /// Pick a new journal ID, just before the app is saved.
+  pub fn new_journal_id(&mut self) { self.journal_id = rand::random(); }
+
+  /// Start journaling commands to a new, empty journal at `path`. This should be done whenever the
+  /// app is saved, since the saved game already contains everything in the old journal.
+  pub fn start_journal(&mut self, path: PathBuf) -> Result<(), GameError> {
+    journal::create(&path, self.journal_id)?;
+    self.journal_path = Some(path);
+    Ok(())
+  }
+
+  /// Re-apply entries from a journal (see `journal::recover`), bringing the app back to the state
+  /// it was in when they were written.
+  pub fn replay_journal(&mut self, entries: Vec<Vec<GameLog>>) -> Result<(), GameError> {
+    for logs in entries {
+      let game = match logs.as_slice() {
+        [log] if is_navigation(log) => self.navigate(log.clone())?.0,
+        [GameLog::EditLog { snapshot_idx, log_idx, log, .. }] => {
+          let edited = self.rewrite_history(*snapshot_idx, *log_idx, log)?;
+          self.replace_history(*snapshot_idx, *log_idx, edited);
+          continue;
+        }
+        _ => {
+          let mut game = self.current_game.clone();
+          for log in &logs {
+            game = game.apply_log(log)?;
+          }
+          game
+        }
+      };
+      self.commit(game, logs);
+    }
+    Ok(())
   }
 
-  /// Rollback to a particular point by replaying logs after a snapshot
+  /// Rollback to a particular point in the current branch by replaying logs after a snapshot.
   fn rollback_to(&self, snapshot_idx: usize, log_idx: usize) -> Result<Game, GameError> {
-    println!("Calling rollback_to {:?}[{:?}]", snapshot_idx, log_idx);
-    let &(ref baseline, ref logs_to_apply) = self
+    match self.snapshots.get(snapshot_idx) {
+      Some((_, logs)) if log_idx < logs.len() => self.game_at(snapshot_idx, log_idx),
+      _ => bail!(GameError::HistoryNotFound(snapshot_idx, log_idx)),
+    }
+  }
+
+  /// The game as it was after the first `log_idx` logs of a snapshot in the current branch. Unlike
+  /// rolling back, this can be the very end of the snapshot, which is useful for working out what
+  /// a client that's seen that much of the history has missed.
+  pub fn game_at(&self, snapshot_idx: usize, log_idx: usize) -> Result<Game, GameError> {
+    let &(ref baseline, ref logs) = self
       .snapshots
       .get(snapshot_idx)
       .ok_or_else(|| GameError::HistoryNotFound(snapshot_idx, log_idx))?;
-    if logs_to_apply.len() - 1 < log_idx {
+    if log_idx > logs.len() {
       bail!(GameError::HistoryNotFound(snapshot_idx, log_idx));
     }
-    println!("All logs: {:?}", logs_to_apply);
-    let logs_to_apply = &logs_to_apply[..log_idx];
-    Self::apply_game_logs(baseline.clone(), baseline.clone(), logs_to_apply)
-  }
-
-  fn apply_game_logs(baseline: Game, mut game: Game, logs: &[GameLog]) -> Result<Game, GameError> {
-    for log in logs {
-      if let GameLog::Rollback(_sni, li) = *log {
-        // TODO: unimplemented! Honor `sni`, the snapshot index
-        // 1. assert li is within bounds?
-        // 2. need to handle SnapshotIndex -- this assumes it's always based on the same snapshot
-        // 3. this is super inefficient
-        // 4. if each Rollback also created a Snapshot, things could be easier... we would never
-        //    need to apply a Rollback as a log in that case
-        game = Self::apply_game_logs(baseline.clone(), baseline.clone(), &logs[..li])?;
-      } else {
-        game = game.apply_log(log)?;
-      }
+    let mut game = baseline.clone();
+    for log in &logs[..log_idx] {
+      game = game.apply_log(log)?;
     }
     Ok(game)
   }
 
+  /// The total number of logs in the history.
+  pub fn history_len(&self) -> usize { self.snapshots.iter().map(|(_, logs)| logs.len()).sum() }
+
+  /// Find the logs in the current branch's history that match a query, oldest first.
+  pub fn query_history(&self, query: &HistoryQuery) -> Vec<HistoryItem> {
+    let mut result = vec![];
+    let mut index = 0;
+    for (snapshot_idx, (_, logs)) in self.snapshots.iter().enumerate() {
+      // Skip whole snapshots that are out of the requested range.
+      let snapshot_end = index + logs.len();
+      if query.start.iter().any(|&start| snapshot_end <= start) {
+        index = snapshot_end;
+        continue;
+      }
+      if query.end.iter().any(|&end| index >= end) {
+        break;
+      }
+      for (log_idx, log) in logs.iter().enumerate() {
+        if query.matches(index, log) {
+          result.push(HistoryItem { index, snapshot_idx, log_idx, log: log.clone() });
+        }
+        index += 1;
+      }
+    }
+    result
+  }
+
   pub fn game(&self) -> &Game { &self.current_game }
 
+  /// The options below are for the GM when `player_id` is `None`. A player can only ask about
+  /// the creatures they control, and only hears about the creatures and terrain they can see.
   pub fn get_movement_options(
-    &self, scene: SceneID, creature_id: CreatureID,
+    &self, player_id: Option<&PlayerID>, scene: SceneID, creature_id: CreatureID,
   ) -> Result<Vec<Point3>, GameError> {
-    self.current_game.get_movement_options(scene, creature_id)
+    let options = self.current_game.get_movement_options(scene, creature_id)?;
+    match player_id {
+      None => Ok(options),
+      Some(player_id) => {
+        self.check_controls(player_id, creature_id)?;
+        self.known_points(player_id, scene, options, Known::Explored)
+      }
+    }
   }
 
-  pub fn get_combat_movement_options(&self) -> Result<Vec<Point3>, GameError> {
-    Ok(self.current_game.get_combat()?.current_movement_options()?)
+  pub fn get_combat_movement_options(
+    &self, player_id: Option<&PlayerID>,
+  ) -> Result<Vec<Point3>, GameError> {
+    let combat = self.current_game.get_combat()?;
+    let options = combat.current_movement_options()?;
+    match player_id {
+      None => Ok(options),
+      Some(player_id) => {
+        self.check_controls(player_id, combat.combat.current_creature_id())?;
+        self.known_points(player_id, combat.scene.id, options, Known::Explored)
+      }
+    }
   }
 
   pub fn get_target_options(
-    &self, scene: SceneID, cid: CreatureID, abid: AbilityID,
+    &self, player_id: Option<&PlayerID>, scene: SceneID, cid: CreatureID, abid: AbilityID,
   ) -> Result<PotentialTargets, GameError> {
-    self.current_game.get_target_options(scene, cid, abid)
+    let options = self.current_game.get_target_options(scene, cid, abid)?;
+    let player_id = match player_id {
+      Some(player_id) => player_id,
+      None => return Ok(options),
+    };
+    self.check_controls(player_id, cid)?;
+    Ok(match options {
+      PotentialTargets::CreatureIDs(cids) => {
+        PotentialTargets::CreatureIDs(self.visible_creatures(player_id, scene, cids)?)
+      }
+      PotentialTargets::Points(pts) => {
+        PotentialTargets::Points(self.known_points(player_id, scene, pts, Known::Visible)?)
+      }
+      PotentialTargets::VolumePlacements(placements) => {
+        let pts = placements.iter().map(|&(pt, _)| pt).collect();
+        let visible = self.known_points(player_id, scene, pts, Known::Visible)?;
+        PotentialTargets::VolumePlacements(
+          placements
+            .into_iter()
+            .filter(|(pt, _)| visible.contains(pt))
+            .map(|(pt, cids)| Ok((pt, self.visible_creatures(player_id, scene, cids)?)))
+            .collect::<Result<_, GameError>>()?,
+        )
+      }
+    })
   }
 
   pub fn preview_volume_targets(
-    &self, sid: SceneID, actor_id: CreatureID, ability_id: AbilityID, pt: Point3,
+    &self, player_id: Option<&PlayerID>, sid: SceneID, actor_id: CreatureID, ability_id: AbilityID,
+    pt: Point3,
   ) -> Result<(Vec<CreatureID>, Vec<Point3>), GameError> {
     let scene = self.current_game.get_scene(sid)?;
-    self.current_game.preview_volume_targets(scene, actor_id, ability_id, pt)
+    let (cids, pts) = self.current_game.preview_volume_targets(scene, actor_id, ability_id, pt)?;
+    match player_id {
+      None => Ok((cids, pts)),
+      Some(player_id) => {
+        self.check_controls(player_id, actor_id)?;
+        let cids = self.visible_creatures(player_id, sid, cids)?;
+        Ok((cids, self.known_points(player_id, sid, pts, Known::Explored)?))
+      }
+    }
+  }
+
+  /// Leave out the points that a player doesn't know about in a scene with fog of war, so that
+  /// options don't give away the terrain (or the creatures) hidden in it.
+  fn known_points(
+    &self, player_id: &PlayerID, scene_id: SceneID, pts: Vec<Point3>, known: Known,
+  ) -> Result<Vec<Point3>, GameError> {
+    let vision = self.current_game.player_vision(player_id, scene_id)?;
+    let known = match known {
+      Known::Visible => &vision.visible,
+      Known::Explored => &vision.explored,
+    };
+    Ok(pts.into_iter().filter(|pt| known.contains(pt)).collect())
   }
 }
 
+/// Which points a player knows about: the ones they can see now, or all the ones they've seen.
+enum Known {
+  Visible,
+  Explored,
+}
+
 #[cfg(test)]
-mod test {
+pub mod test {
   use crate::app::*;
   use crate::game::test::*;
   use crate::types::test::*;
-  use std::path::PathBuf;
 
-  pub fn t_app() -> App { App::new(t_game()) }
+  pub fn t_app() -> App {
+    let mut app = App::new(t_game());
+    app.rng_seed = 0;
+    app
+  }
 
   pub fn perf(app: &mut App, cmd: GameCommand) -> Result<(&Game, Vec<GameLog>), GameError> {
-    app.perform_command(cmd, &PathBuf::from(""), None)
+    app.perform_command(cmd, &t_storage())
   }
 
   // pub fn t_app_act(app: &mut App, ab: AbilityID, dtarget: DecidedTarget) -> Result<(), GameError> {
@@ -172,6 +802,26 @@ mod test {
   //    });
   //  }
 
+  /// Two apps with the same seed roll the same dice for the same commands.
+  #[test]
+  fn pinned_seed_reproduces_rolls() {
+    let run = || {
+      let mut app = t_app();
+      for cid in &[cid_rogue(), cid_ranger(), cid_cleric()] {
+        app.current_game.creatures.mutate(cid, |c| c.initiative = Dice::expr(1, 20));
+      }
+      let cids = vec![cid_rogue(), cid_ranger(), cid_cleric()];
+      perf(&mut app, GameCommand::StartCombat(t_scene_id(), cids)).unwrap();
+      perf(&mut app, GameCommand::RerollCombatInitiative).unwrap();
+      app
+    };
+    let app1 = run();
+    let app2 = run();
+    assert_eq!(app1.rng_counter, 2);
+    assert_eq!(app1.current_game.current_combat, app2.current_game.current_combat);
+    assert_eq!(app1.snapshots, app2.snapshots);
+  }
+
   #[test]
   fn rollback() {
     // 0
@@ -183,9 +833,10 @@ mod test {
     let ranger = app.current_game.get_creature(cid_ranger()).unwrap();
     let scene = app.current_game.get_scene(t_scene_id()).unwrap();
     assert_eq!(scene.get_pos(ranger.id()).unwrap(), Point3::new(0, 0, 0));
-    let logs = &app.snapshots[0].1;
-    println!("{:?}", logs);
-    assert_eq!(logs.len(), 2);
+    // The rollback started a new, empty branch, and the old one is still around.
+    assert_eq!(app.current_branch, 1);
+    assert_eq!(app.snapshots[0].1, vec![]);
+    assert_eq!(app.list_branches()[0].log_count, 1);
   }
 
   /// bug test: ensure precedent logs are also applied, not just the one being rolled back to.
@@ -210,22 +861,18 @@ mod test {
     assert_eq!(scene.get_pos(cid_ranger()).unwrap(), Point3::new(0, 0, 0));
   }
 
-  ///
+  /// Rolling back in a branch that was itself created by rolling back.
   #[test]
   fn rollback_through_rollback() {
-    // 0
     let mut app = t_app();
-    // 1
     perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(1, 1, 1)))
       .unwrap();
-    // 2
-    perf(&mut app, GameCommand::Rollback(0, 0)).unwrap(); // oops didn't mean to move ranger
-                                                          // 3
+    // oops didn't mean to move ranger
+    perf(&mut app, GameCommand::Rollback(0, 0)).unwrap();
     perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_cleric(), Point3::new(1, 1, 1)))
       .unwrap();
-    // 4
-    perf(&mut app, GameCommand::Rollback(0, 2)).unwrap(); // oops didn't mean to move cleric
-                                                          // 5
+    // oops didn't mean to move cleric
+    perf(&mut app, GameCommand::Rollback(0, 0)).unwrap();
     perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_rogue(), Point3::new(1, 1, 1)))
       .unwrap();
     let scene = app.current_game.get_scene(t_scene_id()).unwrap();
@@ -233,4 +880,615 @@ mod test {
     assert_eq!(scene.get_pos(cid_rogue()).unwrap(), Point3::new(1, 1, 1));
     assert_eq!(scene.get_pos(cid_ranger()).unwrap(), Point3::new(0, 0, 0));
   }
+
+  #[test]
+  fn game_at_end_of_history() {
+    let mut app = t_app();
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_rogue(), Point3::new(1, 1, 1)))
+      .unwrap();
+    let len = app.snapshots[0].1.len();
+    assert_eq!(app.game_at(0, len).unwrap(), app.current_game);
+    match app.game_at(0, len + 1) {
+      Err(GameError::HistoryNotFound(0, _)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+    match app.perform_command(GameCommand::Rollback(0, len), &t_storage()) {
+      Err(GameError::HistoryNotFound(0, _)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+  }
+
+  /// Old snapshots are never thrown away, so the GM can roll back to the start of the game.
+  #[test]
+  fn rollback_to_first_snapshot() {
+    let mut app = t_app();
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(1, 1, 1)))
+      .unwrap();
+    for i in 0..(LOGS_PER_SNAP * 3) {
+      perf(&mut app, GameCommand::ChatFromGM(i.to_string())).unwrap();
+    }
+    assert_eq!(app.snapshots.len(), 4);
+    assert_eq!(app.history_len(), LOGS_PER_SNAP * 3 + 1);
+
+    // The new branch keeps the history from before the point that was rolled back to.
+    perf(&mut app, GameCommand::Rollback(2, 10)).unwrap();
+    assert_eq!(app.snapshots.len(), 3);
+    assert_eq!(app.history_len(), LOGS_PER_SNAP * 2 + 10);
+
+    perf(&mut app, GameCommand::Rollback(0, 1)).unwrap();
+    let scene = app.current_game.get_scene(t_scene_id()).unwrap();
+    assert_eq!(scene.get_pos(cid_ranger()).unwrap(), Point3::new(1, 1, 1));
+    perf(&mut app, GameCommand::Rollback(0, 0)).unwrap();
+    assert_eq!(app.current_game, t_game());
+  }
+
+  /// The branch abandoned by a rollback can be switched back to, and then back again.
+  #[test]
+  fn switch_branch() {
+    let mut app = t_app();
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(1, 1, 1)))
+      .unwrap();
+    perf(&mut app, GameCommand::Rollback(0, 0)).unwrap();
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_cleric(), Point3::new(1, 1, 1)))
+      .unwrap();
+    let cleric_moved = app.current_game.clone();
+
+    perf(&mut app, GameCommand::SwitchBranch(0)).unwrap();
+    let scene = app.current_game.get_scene(t_scene_id()).unwrap();
+    assert_eq!(scene.get_pos(cid_ranger()).unwrap(), Point3::new(1, 1, 1));
+    assert_eq!(scene.get_pos(cid_cleric()).unwrap(), Point3::new(0, 0, 0));
+    assert_eq!(
+      app.snapshots[0].1,
+      vec![GameLog::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(1, 1, 1))]
+    );
+    assert_eq!(
+      app.list_branches(),
+      vec![
+        BranchInfo { parent: None, log_count: 1 },
+        BranchInfo {
+          parent: Some(BranchPoint { branch: 0, snapshot_idx: 0, log_idx: 0 }),
+          log_count: 1
+        },
+      ]
+    );
+
+    perf(&mut app, GameCommand::SwitchBranch(1)).unwrap();
+    perf(&mut app, GameCommand::SwitchBranch(1)).unwrap();
+    assert_eq!(app.current_game, cleric_moved);
+    assert_eq!(app.history_len(), 1);
+
+    match perf(&mut app, GameCommand::SwitchBranch(2)) {
+      Err(GameError::BranchNotFound(2)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+  }
+
+  #[test]
+  fn rollback_out_of_range() {
+    let mut app = t_app();
+    perf(&mut app, GameCommand::ChatFromGM("hi".to_string())).unwrap();
+    match perf(&mut app, GameCommand::Rollback(0, 1)) {
+      Err(GameError::HistoryNotFound(0, 1)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+    match perf(&mut app, GameCommand::Rollback(1, 0)) {
+      Err(GameError::HistoryNotFound(1, 0)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+  }
+
+  /// Games saved before branches were added can have rollbacks recorded in the middle of their
+  /// history. They're turned into snapshots when the game is loaded, so the history can still be
+  /// navigated.
+  #[test]
+  fn load_legacy_rollback() {
+    let baseline = t_game();
+    let logs = vec![
+      GameLog::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(1, 1, 1)),
+      GameLog::ChatFromGM("rolled back over".to_string()),
+      GameLog::Rollback(0, 1),
+      GameLog::ChatFromGM("after".to_string()),
+    ];
+    let mut current_game = baseline.clone();
+    for log in &[&logs[0], &logs[3]] {
+      current_game = current_game.apply_log(log).unwrap();
+    }
+    // The only fields an App had back then.
+    let mut legacy = serde_yaml::Mapping::new();
+    legacy.insert("current_game".into(), serde_yaml::to_value(&current_game).unwrap());
+    legacy.insert("snapshots".into(), serde_yaml::to_value(vec![(&baseline, &logs)]).unwrap());
+    let dir = crate::journal::test::t_dir();
+    ::std::fs::write(dir.join("game.yaml"), serde_yaml::to_string(&legacy).unwrap()).unwrap();
+
+    let storage = ptstorage::FilesystemStorage::new(dir, None, 0);
+    let mut app =
+      crate::game::load_app(&storage, ModuleSource::SavedGame, "game.yaml").expect("must load");
+    assert_eq!(app.current_game, current_game);
+    assert_eq!(app.snapshots[0].1, logs[..2].to_vec());
+    assert_eq!(app.snapshots[1].1, vec![logs[3].clone()]);
+    assert_eq!(app.game_at(1, 1).unwrap(), current_game);
+
+    perf(&mut app, GameCommand::Rollback(0, 1)).unwrap();
+    let scene = app.current_game.get_scene(t_scene_id()).unwrap();
+    assert_eq!(scene.get_pos(cid_ranger()).unwrap(), Point3::new(1, 1, 1));
+    perf(&mut app, GameCommand::ChatFromGM("new".to_string())).unwrap();
+    perf(&mut app, GameCommand::Undo).unwrap();
+    assert_eq!(app.current_game, app.game_at(0, 1).unwrap());
+  }
+
+  #[test]
+  fn query_history() {
+    let mut app = t_app();
+    for i in 0..LOGS_PER_SNAP {
+      perf(&mut app, GameCommand::ChatFromGM(i.to_string())).unwrap();
+    }
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(1, 1, 1)))
+      .unwrap();
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_rogue(), Point3::new(1, 1, 1)))
+      .unwrap();
+
+    let all = app.query_history(&HistoryQuery::default());
+    assert_eq!(all.len(), LOGS_PER_SNAP + 2);
+    assert!(all.iter().enumerate().all(|(idx, item)| item.index == idx));
+
+    let range = app.query_history(&HistoryQuery {
+      start: Some(LOGS_PER_SNAP - 1),
+      end: Some(LOGS_PER_SNAP + 1),
+      ..Default::default()
+    });
+    let positions: Vec<_> =
+      range.iter().map(|item| (item.index, item.snapshot_idx, item.log_idx)).collect();
+    assert_eq!(positions, vec![(LOGS_PER_SNAP - 1, 0, LOGS_PER_SNAP - 1), (LOGS_PER_SNAP, 1, 0)]);
+
+    let ranger =
+      app.query_history(&HistoryQuery { creature_id: Some(cid_ranger()), ..Default::default() });
+    assert_eq!(
+      ranger,
+      vec![HistoryItem {
+        index: LOGS_PER_SNAP,
+        snapshot_idx: 1,
+        log_idx: 0,
+        log: GameLog::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(1, 1, 1)),
+      }]
+    );
+
+    let moves = app.query_history(&HistoryQuery {
+      kind: Some("SetCreaturePos".to_string()),
+      start: Some(LOGS_PER_SNAP + 1),
+      ..Default::default()
+    });
+    assert_eq!(moves.len(), 1);
+    assert!(moves[0].log.involves_creature(cid_rogue()));
+  }
+
+  /// Undo and redo step over whole commands, even ones that produce many logs.
+  #[test]
+  fn undo_redo() {
+    let mut app = t_app();
+    perf(
+      &mut app,
+      GameCommand::SetCreaturePos(t_scene_id(), cid_cleric(), Point3::new(1100, 0, 0)),
+    )
+    .unwrap();
+    let before_fireball = app.current_game.clone();
+    let fireball = GameCommand::ActCreature(
+      t_scene_id(),
+      cid_cleric(),
+      abid_fireball(),
+      DecidedTarget::Point(Point3::new(0, 0, 0)),
+    );
+    let (_, logs) = perf(&mut app, fireball).unwrap();
+    assert!(logs.len() > 1);
+    let after_fireball = app.current_game.clone();
+
+    perf(&mut app, GameCommand::Undo).unwrap();
+    assert_eq!(app.current_game, before_fireball);
+    perf(&mut app, GameCommand::Undo).unwrap();
+    assert_eq!(app.current_game, t_game());
+    match perf(&mut app, GameCommand::Undo) {
+      Err(GameError::NothingToUndo) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+
+    perf(&mut app, GameCommand::Redo).unwrap();
+    assert_eq!(app.current_game, before_fireball);
+    perf(&mut app, GameCommand::Redo).unwrap();
+    assert_eq!(app.current_game, after_fireball);
+    match perf(&mut app, GameCommand::Redo) {
+      Err(GameError::NothingToRedo) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+    // None of that changed the history.
+    assert_eq!(app.history_len(), 1 + logs.len());
+    assert_eq!(app.branches.len(), 1);
+  }
+
+  /// Performing a command after undoing replaces the undone commands, but they're kept in a branch.
+  #[test]
+  fn command_after_undo_splits_branch() {
+    let mut app = t_app();
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(1, 1, 1)))
+      .unwrap();
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_rogue(), Point3::new(1, 1, 1)))
+      .unwrap();
+    let both_moved = app.current_game.clone();
+    perf(&mut app, GameCommand::Undo).unwrap();
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_cleric(), Point3::new(1, 1, 1)))
+      .unwrap();
+
+    assert_eq!(app.undone, 0);
+    assert_eq!(
+      app.snapshots[0].1,
+      vec![
+        GameLog::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(1, 1, 1)),
+        GameLog::SetCreaturePos(t_scene_id(), cid_cleric(), Point3::new(1, 1, 1)),
+      ]
+    );
+    match perf(&mut app, GameCommand::Redo) {
+      Err(GameError::NothingToRedo) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+    perf(&mut app, GameCommand::SwitchBranch(1)).unwrap();
+    assert_eq!(app.current_game, both_moved);
+    // The undo history comes along with the branch.
+    perf(&mut app, GameCommand::Undo).unwrap();
+    perf(&mut app, GameCommand::Undo).unwrap();
+    assert_eq!(app.current_game, t_game());
+  }
+
+  /// Rolling back forks the undo history along with everything else.
+  #[test]
+  fn undo_after_rollback() {
+    let mut app = t_app();
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(1, 1, 1)))
+      .unwrap();
+    let ranger_moved = app.current_game.clone();
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_rogue(), Point3::new(1, 1, 1)))
+      .unwrap();
+    perf(&mut app, GameCommand::Rollback(0, 1)).unwrap();
+    assert_eq!(app.commands, vec![(0, 0)]);
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_cleric(), Point3::new(1, 1, 1)))
+      .unwrap();
+    perf(&mut app, GameCommand::Undo).unwrap();
+    assert_eq!(app.current_game, ranger_moved);
+  }
+
+  /// Editing a log changes everything after it, like fixing a damage roll from a few turns ago.
+  #[test]
+  fn edit_log() {
+    let mut app = t_app();
+    perf(&mut app, GameCommand::ChatFromGM("oops".to_string())).unwrap();
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_rogue(), Point3::new(1, 1, 1)))
+      .unwrap();
+    let original = app.current_game.clone();
+    let rogue_health = app.current_game.get_creature(cid_rogue()).unwrap().creature.cur_health;
+
+    let damage = GameLog::CreatureLog(cid_rogue(), CreatureLog::Damage(HP(3), vec![3]));
+    let (_, logs) = perf(&mut app, GameCommand::EditLog(0, 0, damage.clone())).unwrap();
+    assert_eq!(
+      logs,
+      vec![GameLog::EditLog {
+        snapshot_idx: 0,
+        log_idx: 0,
+        log: Box::new(damage),
+        invalidated: vec![]
+      }]
+    );
+    let rogue = app.current_game.get_creature(cid_rogue()).unwrap();
+    assert_eq!(rogue.creature.cur_health, HP(rogue_health.0 - 3));
+    let scene = app.current_game.get_scene(t_scene_id()).unwrap();
+    assert_eq!(scene.get_pos(cid_rogue()).unwrap(), Point3::new(1, 1, 1));
+    assert_eq!(app.commands, vec![(0, 0), (0, 1)]);
+
+    // The old history is still around.
+    perf(&mut app, GameCommand::SwitchBranch(1)).unwrap();
+    assert_eq!(app.current_game, original);
+  }
+
+  /// Later logs that can't be applied after an edit are reported, and left out of the history.
+  #[test]
+  fn edit_log_invalidates_later_logs() {
+    let mut app = t_app();
+    perf(
+      &mut app,
+      GameCommand::StartCombat(t_scene_id(), vec![cid_ranger(), cid_rogue(), cid_cleric()]),
+    )
+    .unwrap();
+    perf(&mut app, GameCommand::ChatFromGM("fight!".to_string())).unwrap();
+    let (_, done_logs) = perf(&mut app, GameCommand::Done).unwrap();
+
+    let invalidated = app.edit_log(0, 0, GameLog::ChatFromGM("never mind".to_string())).unwrap();
+    assert_eq!(invalidated[0].log, done_logs[0]);
+    assert_eq!((invalidated[0].snapshot_idx, invalidated[0].log_idx), (0, 2));
+    assert_eq!(app.current_game.current_combat, None);
+    assert_eq!(
+      app.snapshots[0].1[..2],
+      [GameLog::ChatFromGM("never mind".to_string()), GameLog::ChatFromGM("fight!".to_string())]
+    );
+  }
+
+  /// Undone commands aren't re-applied by an edit.
+  #[test]
+  fn edit_log_after_undo() {
+    let mut app = t_app();
+    perf(&mut app, GameCommand::ChatFromGM("hi".to_string())).unwrap();
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_rogue(), Point3::new(1, 1, 1)))
+      .unwrap();
+    perf(&mut app, GameCommand::Undo).unwrap();
+    match app.edit_log(0, 1, GameLog::ChatFromGM("nope".to_string())) {
+      Err(GameError::HistoryNotFound(0, 1)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+    app.edit_log(0, 0, GameLog::ChatFromGM("hello".to_string())).unwrap();
+    assert_eq!(app.snapshots[0].1, vec![GameLog::ChatFromGM("hello".to_string())]);
+    assert_eq!(app.undone, 0);
+    let scene = app.current_game.get_scene(t_scene_id()).unwrap();
+    assert_eq!(scene.get_pos(cid_rogue()).unwrap(), Point3::new(0, 0, 0));
+  }
+
+  fn t_player_app(policy: VettingPolicy) -> App {
+    let mut app = t_app();
+    perf(&mut app, GameCommand::RegisterPlayer(PlayerID("alice".to_string()))).unwrap();
+    perf(
+      &mut app,
+      GameCommand::GiveCreaturesToPlayer(PlayerID("alice".to_string()), vec![cid_cleric()]),
+    )
+    .unwrap();
+    perf(&mut app, GameCommand::SetVettingPolicy(policy)).unwrap();
+    app
+  }
+
+  fn player_perf(app: &mut App, cmd: GameCommand) -> Result<(&Game, Vec<GameLog>), GameError> {
+    app.perform_player_command(PlayerID("alice".to_string()), cmd, &t_storage())
+  }
+
+  fn heal_rogue() -> GameCommand {
+    GameCommand::ActCreature(
+      t_scene_id(),
+      cid_cleric(),
+      abid_heal(),
+      DecidedTarget::Creature(cid_rogue()),
+    )
+  }
+
+  #[test]
+  fn player_command_without_vetting() {
+    let mut app = t_player_app(VettingPolicy::Never);
+    let (_, logs) = player_perf(&mut app, heal_rogue()).unwrap();
+    assert!(!logs.is_empty());
+    assert!(app.pending.is_empty());
+    assert_eq!(&app.snapshots[0].1[3..], logs.as_slice());
+  }
+
+  #[test]
+  fn players_cant_approve_their_own_commands() {
+    let mut app = t_player_app(VettingPolicy::AllCommands);
+    let cmd = GameCommand::ApprovePendingCommand(PlayerID("alice".to_string()));
+    match player_perf(&mut app, cmd) {
+      Err(GameError::GMOnlyCommand(_)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+  }
+
+  #[test]
+  fn approve_pending_command() {
+    let mut app = t_player_app(VettingPolicy::AllCommands);
+    let before = app.current_game.clone();
+    let cmd = GameCommand::PathCreature(t_scene_id(), cid_cleric(), Point3::new(100, 0, 0));
+    let (_, logs) = player_perf(&mut app, cmd.clone()).unwrap();
+    assert_eq!(logs, vec![]);
+    assert_eq!(app.current_game, before);
+    match player_perf(&mut app, cmd) {
+      Err(GameError::PendingCommandExists(_)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+
+    // The GM does something else in the meantime, which is kept when approving.
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_rogue(), Point3::new(2, 2, 2)))
+      .unwrap();
+    perf(&mut app, GameCommand::ApprovePendingCommand(PlayerID("alice".to_string()))).unwrap();
+    assert!(app.pending.is_empty());
+    let scene = app.current_game.get_scene(t_scene_id()).unwrap();
+    assert_eq!(scene.get_pos(cid_cleric()).unwrap(), Point3::new(100, 0, 0));
+    assert_eq!(scene.get_pos(cid_rogue()).unwrap(), Point3::new(2, 2, 2));
+  }
+
+  #[test]
+  fn reject_pending_command() {
+    let mut app = t_player_app(VettingPolicy::AllCommands);
+    let before = app.current_game.clone();
+    player_perf(&mut app, heal_rogue()).unwrap();
+    perf(&mut app, GameCommand::RejectPendingCommand(PlayerID("alice".to_string()))).unwrap();
+    assert!(app.pending.is_empty());
+    assert_eq!(app.current_game, before);
+    match perf(&mut app, GameCommand::RejectPendingCommand(PlayerID("alice".to_string()))) {
+      Err(GameError::NoPendingCommand(_)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+  }
+
+  #[test]
+  fn edit_pending_command() {
+    let mut app = t_player_app(VettingPolicy::AllCommands);
+    let alice = PlayerID("alice".to_string());
+    let before = app.current_game.clone();
+    let path = |cid, x| GameCommand::PathCreature(t_scene_id(), cid, Point3::new(x, 0, 0));
+    player_perf(&mut app, path(cid_cleric(), 100)).unwrap();
+
+    let edit = |cmd| GameCommand::EditPendingCommand(alice.clone(), Box::new(cmd));
+    let (_, logs) = perf(&mut app, edit(path(cid_cleric(), 200))).unwrap();
+    assert_eq!(logs, vec![]);
+    assert_eq!(app.current_game, before);
+    assert_eq!(app.pending[&alice].command, path(cid_cleric(), 200));
+    // It's still the player's command, so it can only do what they could.
+    match perf(&mut app, edit(path(cid_rogue(), 200))) {
+      Err(GameError::PlayerDoesntControlCreature(..)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+    assert_eq!(app.pending[&alice].command, path(cid_cleric(), 200));
+
+    perf(&mut app, GameCommand::ApprovePendingCommand(alice.clone())).unwrap();
+    let scene = app.current_game.get_scene(t_scene_id()).unwrap();
+    assert_eq!(scene.get_pos(cid_cleric()).unwrap(), Point3::new(200, 0, 0));
+    match perf(&mut app, edit(path(cid_cleric(), 100))) {
+      Err(GameError::NoPendingCommand(_)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+  }
+
+  #[test]
+  fn players_only_control_their_own_creatures() {
+    let mut app = t_player_app(VettingPolicy::Never);
+    let cmd = GameCommand::PathCreature(t_scene_id(), cid_rogue(), Point3::new(100, 0, 0));
+    match player_perf(&mut app, cmd) {
+      Err(GameError::PlayerDoesntControlCreature(_, cid)) if cid == cid_rogue() => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+    let cmd = GameCommand::PathCreature(t_scene_id(), cid_cleric(), Point3::new(100, 0, 0));
+    player_perf(&mut app, cmd).unwrap();
+    for cmd in vec![
+      GameCommand::SetCreaturePos(t_scene_id(), cid_cleric(), Point3::new(1, 1, 1)),
+      GameCommand::StopCombat,
+      GameCommand::EditSceneTerrain { scene_id: t_scene_id(), terrain: Default::default() },
+      GameCommand::GiveCreaturesToPlayer(PlayerID("alice".to_string()), vec![cid_rogue()]),
+    ] {
+      match player_perf(&mut app, cmd) {
+        Err(GameError::GMOnlyCommand(_)) => {}
+        r => panic!("Unexpected result: {:?}", r),
+      }
+    }
+  }
+
+  #[test]
+  fn players_only_act_in_combat_on_their_turn() {
+    let mut app = t_player_app(VettingPolicy::Never);
+    let heal = GameCommand::CombatAct(abid_heal(), DecidedTarget::Creature(cid_rogue()));
+    match player_perf(&mut app, heal.clone()) {
+      Err(GameError::NotInCombat) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+    perf(&mut app, GameCommand::StartCombat(t_scene_id(), vec![cid_rogue()])).unwrap();
+    match player_perf(&mut app, GameCommand::Done) {
+      Err(GameError::PlayerDoesntControlCreature(_, cid)) if cid == cid_rogue() => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+    perf(&mut app, GameCommand::StopCombat).unwrap();
+    perf(&mut app, GameCommand::StartCombat(t_scene_id(), vec![cid_cleric()])).unwrap();
+    player_perf(&mut app, heal).unwrap();
+  }
+
+  /// Even on their turn, players act and move through the combat commands, which keep track of
+  /// the turn's movement.
+  #[test]
+  fn players_use_combat_commands_in_combat() {
+    let mut app = t_player_app(VettingPolicy::Never);
+    perf(&mut app, GameCommand::StartCombat(t_scene_id(), vec![cid_cleric()])).unwrap();
+    let path = GameCommand::PathCreature(t_scene_id(), cid_cleric(), Point3::new(100, 0, 0));
+    for cmd in vec![heal_rogue(), path] {
+      match player_perf(&mut app, cmd) {
+        Err(GameError::SceneInCombat(scene_id)) if scene_id == t_scene_id() => {}
+        r => panic!("Unexpected result: {:?}", r),
+      }
+    }
+    player_perf(&mut app, GameCommand::PathCurrentCombatCreature(Point3::new(100, 0, 0))).unwrap();
+  }
+
+  /// Players only get movement and target options for their own creatures, and the options
+  /// leave out the creatures they can't see.
+  #[test]
+  fn players_get_options_for_their_own_creatures() {
+    let mut app = t_player_app(VettingPolicy::Never);
+    let alice = PlayerID("alice".to_string());
+    perf(
+      &mut app,
+      GameCommand::SetSceneCreatureVisibility {
+        scene_id: t_scene_id(),
+        creature_id: cid_ranger(),
+        visibility: Visibility::GMOnly,
+      },
+    )
+    .unwrap();
+
+    match app.get_movement_options(Some(&alice), t_scene_id(), cid_rogue()) {
+      Err(GameError::PlayerDoesntControlCreature(..)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+    app.get_movement_options(Some(&alice), t_scene_id(), cid_cleric()).unwrap();
+
+    let origin = Point3::new(0, 0, 0);
+    let (cids, _) = app
+      .preview_volume_targets(None, t_scene_id(), cid_cleric(), abid_fireball(), origin)
+      .unwrap();
+    assert!(cids.contains(&cid_ranger()));
+    let (cids, _) = app
+      .preview_volume_targets(Some(&alice), t_scene_id(), cid_cleric(), abid_fireball(), origin)
+      .unwrap();
+    assert!(cids.contains(&cid_cleric()) && !cids.contains(&cid_ranger()));
+
+    let cids = |options| match options {
+      PotentialTargets::CreatureIDs(cids) => cids,
+      o => panic!("Unexpected options: {:?}", o),
+    };
+    let gm_options = app.get_target_options(None, t_scene_id(), cid_cleric(), abid_heal());
+    assert!(cids(gm_options.unwrap()).contains(&cid_ranger()));
+    let options = app.get_target_options(Some(&alice), t_scene_id(), cid_cleric(), abid_heal());
+    assert!(!cids(options.unwrap()).contains(&cid_ranger()));
+    match app.get_target_options(Some(&alice), t_scene_id(), cid_rogue(), abid_heal()) {
+      Err(GameError::PlayerDoesntControlCreature(..)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+  }
+
+  #[test]
+  fn players_only_speak_for_themselves() {
+    let mut app = t_player_app(VettingPolicy::Never);
+    let bob = PlayerID("bob".to_string());
+    match player_perf(&mut app, GameCommand::RegisterPlayer(bob.clone())) {
+      Err(GameError::NotThatPlayer(..)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+    match player_perf(&mut app, GameCommand::ChatFromPlayer(bob.clone(), "hi".to_string())) {
+      Err(GameError::NotThatPlayer(..)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+    let chat = GameCommand::ChatFromPlayer(PlayerID("alice".to_string()), "hi".to_string());
+    player_perf(&mut app, chat).unwrap();
+
+    // New players register themselves.
+    app
+      .perform_player_command(bob.clone(), GameCommand::RegisterPlayer(bob.clone()), &t_storage())
+      .unwrap();
+    assert!(app.current_game.players.contains_key(&bob));
+  }
+
+  /// With `ActionsOnNPCs`, only commands that affect creatures no player controls are vetted.
+  #[test]
+  fn vet_actions_on_npcs() {
+    let mut app = t_player_app(VettingPolicy::ActionsOnNPCs);
+    player_perf(&mut app, heal_rogue()).unwrap();
+    assert!(app.pending.contains_key(&PlayerID("alice".to_string())));
+    perf(&mut app, GameCommand::RejectPendingCommand(PlayerID("alice".to_string()))).unwrap();
+
+    let cmd = GameCommand::PathCreature(t_scene_id(), cid_cleric(), Point3::new(100, 0, 0));
+    player_perf(&mut app, cmd).unwrap();
+    assert!(app.pending.is_empty());
+
+    // Once someone else controls the rogue, healing it goes straight through.
+    perf(&mut app, GameCommand::RegisterPlayer(PlayerID("bob".to_string()))).unwrap();
+    perf(
+      &mut app,
+      GameCommand::GiveCreaturesToPlayer(PlayerID("bob".to_string()), vec![cid_rogue()]),
+    )
+    .unwrap();
+    let (_, logs) = player_perf(&mut app, heal_rogue()).unwrap();
+    assert!(!logs.is_empty());
+    assert!(app.pending.is_empty());
+  }
+
+  /// Player IDs are hashed into command seeds the same way everywhere, with FNV-1a.
+  #[test]
+  fn stable_hash_is_fnv_1a() {
+    assert_eq!(stable_hash(""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(stable_hash("a"), 0xaf63_dc4c_8601_ec8c);
+  }
 }
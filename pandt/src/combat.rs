@@ -3,6 +3,7 @@
 use error_chain::bail;
 use nonempty;
 use num::{Saturating, Zero};
+use rand::RngCore;
 
 use crate::types::*;
 
@@ -83,9 +84,11 @@ impl<'game> DynamicCombat<'game> {
     }
   }
 
-  pub fn reroll_initiative(&self) -> Result<ChangedCombat<'game>, GameError> {
+  pub fn reroll_initiative(
+    &self, rng: &mut dyn RngCore,
+  ) -> Result<ChangedCombat<'game>, GameError> {
     let cids = self.combat.creature_ids();
-    let combatants = Combat::roll_initiative(self.game, cids)?;
+    let combatants = Combat::roll_initiative(self.game, cids, rng)?;
     self.change_with(CombatLog::RerollInitiative(combatants))
   }
 
@@ -127,13 +130,13 @@ impl Combat {
   pub fn creature_ids(&self) -> Vec<CreatureID> { self.creatures.iter().map(|&(c, _)| c).collect() }
 
   pub fn roll_initiative(
-    game: &Game, cids: Vec<CreatureID>,
+    game: &Game, cids: Vec<CreatureID>, rng: &mut dyn RngCore,
   ) -> Result<Vec<(CreatureID, i16)>, GameError> {
     cids
       .iter()
       .map(|cid| {
         let creature = game.get_creature(*cid)?;
-        Ok((*cid, creature.creature.initiative.roll().1 as i16))
+        Ok((*cid, creature.creature.initiative.roll(rng)?.1 as i16))
       })
       .collect::<Result<Vec<(CreatureID, i16)>, GameError>>()
   }
@@ -217,6 +220,7 @@ pub mod test {
   use crate::game::test::*;
   use crate::game::ChangedGame;
   use crate::types::test::*;
+  use std::sync::Arc;
 
   /// Create a Test combat. Combat order is rogue, ranger, then cleric.
   pub fn t_combat() -> Game {
@@ -233,6 +237,19 @@ pub mod test {
     perf(game, GameCommand::CombatAct(abid, target))
   }
 
+  /// With a pinned seed, real initiative dice roll the same way every time.
+  #[test]
+  fn roll_initiative_with_pinned_seed() {
+    let mut game = t_game();
+    for cid in &[cid_rogue(), cid_ranger(), cid_cleric()] {
+      game.creatures.mutate(cid, |c| c.initiative = Dice::expr(1, 20));
+    }
+    let cids = vec![cid_rogue(), cid_ranger(), cid_cleric()];
+    let inits = Combat::roll_initiative(&game, cids.clone(), &mut t_rng()).unwrap();
+    assert_eq!(inits, Combat::roll_initiative(&game, cids, &mut t_rng()).unwrap());
+    assert!(inits.iter().all(|&(_, init)| init >= 1 && init <= 20));
+  }
+
   /// Try to melee-atack the ranger when the ranger is out of melee range.
   #[test]
   fn target_melee_out_of_range() {
@@ -355,7 +372,7 @@ pub mod test {
       Point3::new(200, 100, 0),
       Point3::new(200, 0, 0),
     ];
-    game.scenes.mutate(&t_scene_id(), move |s| s.terrain = terrain);
+    game.scenes.mutate(&t_scene_id(), move |s| s.terrain = Arc::new(terrain.into()));
 
     let next_game = game
       .get_combat()
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 
 use error_chain::bail;
 use num::Saturating;
+use rand::RngCore;
 
 use crate::types::*;
 use indexed::*;
@@ -20,6 +21,11 @@ use indexed::*;
 /// circular movement distance.
 const STANDARD_CREATURE_SPEED: u32 = 1086;
 
+/// How far a creature can see in a scene with fog of war, in centimeters.
+const STANDARD_CREATURE_SIGHT: u32 = 1000;
+
+pub fn standard_sight() -> u32units::Length { u32cm(STANDARD_CREATURE_SIGHT) }
+
 impl<'creature, 'game: 'creature> DynamicCreature<'creature, 'game> {
   pub fn new(
     creature: &'creature Creature, game: &'game Game,
@@ -83,7 +89,7 @@ impl<'creature, 'game: 'creature> DynamicCreature<'creature, 'game> {
     conditions
   }
 
-  pub fn tick(&self) -> Result<ChangedCreature, GameError> {
+  pub fn tick(&self, rng: &mut dyn RngCore) -> Result<ChangedCreature, GameError> {
     let mut changes = self.creature.change();
     for condition in self.all_conditions() {
       if let AppliedCondition { condition: Condition::RecurringEffect(ref eff), ref remaining } =
@@ -93,7 +99,7 @@ impl<'creature, 'game: 'creature> DynamicCreature<'creature, 'game> {
           Duration::Rounds(0) => false,
           Duration::Interminate | Duration::Rounds(_) => true,
         } {
-          changes = changes.merge(changes.creature(self.game)?.apply_effect(eff)?);
+          changes = changes.merge(changes.creature(self.game)?.apply_effect(eff, rng)?);
         }
       }
     }
@@ -124,42 +130,50 @@ impl<'creature, 'game: 'creature> DynamicCreature<'creature, 'game> {
     }
   }
 
-  fn damage(&self, expr: &Dice) -> Vec<CreatureLog> {
-    let (rolls, amt) = expr.roll();
+  fn damage(&self, expr: &Dice, rng: &mut dyn RngCore) -> Result<Vec<CreatureLog>, GameError> {
+    let (rolls, amt) = expr.roll(rng)?;
     let amt = HP(amt as u8);
     if amt >= self.creature.cur_health {
-      vec![
+      Ok(vec![
         CreatureLog::Damage(self.creature.cur_health, rolls),
         Self::apply_condition_log(Duration::Interminate, Condition::Dead),
-      ]
+      ])
     } else {
-      vec![CreatureLog::Damage(amt, rolls)]
+      Ok(vec![CreatureLog::Damage(amt, rolls)])
     }
   }
 
-  fn heal(&self, expr: &Dice) -> Vec<CreatureLog> {
-    let (dice, amt) = expr.roll();
+  fn heal(&self, expr: &Dice, rng: &mut dyn RngCore) -> Result<Vec<CreatureLog>, GameError> {
+    let (dice, amt) = expr.roll(rng)?;
     let amt = HP(amt as u8);
     let missing = self.creature.max_health - self.creature.cur_health;
-    vec![CreatureLog::Heal(cmp::min(missing, amt), dice)]
+    Ok(vec![CreatureLog::Heal(cmp::min(missing, amt), dice)])
   }
 
-  fn eff2log(&self, effect: &CreatureEffect) -> Vec<CreatureLog> {
+  fn eff2log(
+    &self, effect: &CreatureEffect, rng: &mut dyn RngCore,
+  ) -> Result<Vec<CreatureLog>, GameError> {
     match *effect {
-      CreatureEffect::Damage(ref expr) => self.damage(expr),
-      CreatureEffect::Heal(ref expr) => self.heal(expr),
-      CreatureEffect::GenerateEnergy(amt) => self.generate_energy(amt),
+      CreatureEffect::Damage(ref expr) => self.damage(expr, rng),
+      CreatureEffect::Heal(ref expr) => self.heal(expr, rng),
+      CreatureEffect::GenerateEnergy(amt) => Ok(self.generate_energy(amt)),
       CreatureEffect::MultiEffect(ref effects) => {
-        effects.iter().flat_map(|x| self.eff2log(x)).collect()
+        let mut logs = vec![];
+        for effect in effects {
+          logs.extend(self.eff2log(effect, rng)?);
+        }
+        Ok(logs)
       }
       CreatureEffect::ApplyCondition(ref duration, ref condition) => {
-        vec![Self::apply_condition_log(*duration, condition.clone())]
+        Ok(vec![Self::apply_condition_log(*duration, condition.clone())])
       }
     }
   }
 
-  pub fn apply_effect(&self, effect: &CreatureEffect) -> Result<ChangedCreature, GameError> {
-    let ops = Self::eff2log(self, effect);
+  pub fn apply_effect(
+    &self, effect: &CreatureEffect, rng: &mut dyn RngCore,
+  ) -> Result<ChangedCreature, GameError> {
+    let ops = Self::eff2log(self, effect, rng)?;
     let mut changes = self.creature.change();
     for op in &ops {
       changes = changes.apply(op)?;
@@ -213,6 +227,7 @@ impl Creature {
       initiative: spec.initiative.clone(),
       size: spec.size,
       inventory: HashMap::new(),
+      sight: spec.sight,
     }
   }
 
@@ -279,14 +294,16 @@ impl Creature {
       .ok_or_else(|| GameError::AttributeNotFound(self.id, attr.clone()).into())
   }
 
-  pub fn attribute_check(&self, check: &AttributeCheck) -> Result<(u8, bool), GameError> {
+  pub fn attribute_check(
+    &self, check: &AttributeCheck, rng: &mut dyn RngCore,
+  ) -> Result<(u8, bool), GameError> {
     let my_skill = self.get_attribute_score(&check.attr)?;
     if check.reliable && check.target <= my_skill {
       Ok((100, true))
     } else {
       let dice = Dice::expr(1, 100);
-      let roll = dice.roll().1 as u8; // panic: 1d100 better fit into a u8!
-      let success = roll >= my_skill.difficulty(check.target);
+      let roll = dice.roll(rng)?.1 as u8; // panic: 1d100 better fit into a u8!
+      let success = check_succeeds(roll, my_skill.difficulty(check.target));
       Ok((roll, success))
     }
   }
@@ -322,6 +339,9 @@ impl ChangedCreature {
   pub fn done(self) -> (Creature, Vec<CreatureLog>) { (self.creature, self.logs) }
 }
 
+/// A 1d100 roll passes an attribute check if it's at least the difficulty.
+fn check_succeeds(roll: u8, difficulty: u8) -> bool { roll >= difficulty }
+
 fn conditions_able(conditions: &[AppliedCondition]) -> bool {
   !conditions.iter().any(|&AppliedCondition { ref condition, .. }| {
     condition == &Condition::Incapacitated || condition == &Condition::Dead
@@ -336,6 +356,28 @@ pub mod test {
 
   use std::iter::FromIterator;
 
+  /// The actual odds of passing a 1d100 attribute check: a roll of exactly the difficulty passes,
+  /// so they're one percent better than `100 - difficulty` (short of a sure thing).
+  #[test]
+  fn attribute_check_odds_match_difficulty() {
+    use crate::types::SkillLevel::*;
+    let levels = [Inept, Unskilled, Skilled, Expert, Supernatural];
+    let stats = Dice::expr(1, 100).stats().unwrap();
+    for skill in &levels {
+      for target in &levels {
+        let difficulty = skill.difficulty(*target);
+        let chance: f64 = stats
+          .distribution
+          .iter()
+          .filter(|&&(roll, _)| check_succeeds(roll as u8, difficulty))
+          .map(|&(_, probability)| probability)
+          .sum();
+        let expected = f64::from((101 - difficulty).min(100)) / 100.0;
+        assert!((chance - expected).abs() < 1e-9, "{:?} vs {:?}: {}", skill, target, chance);
+      }
+    }
+  }
+
   #[test]
   fn test_tick_and_expire_condition_remaining() {
     let mut game = t_game();
@@ -347,7 +389,7 @@ pub mod test {
       ]);
     });
     assert_eq!(
-      game.get_creature(cid_rogue()).unwrap().tick().unwrap().creature.conditions,
+      game.get_creature(cid_rogue()).unwrap().tick(&mut t_rng()).unwrap().creature.conditions,
       HashMap::from_iter(vec![
         (ConditionID(uuid_1()), app_cond(Condition::Incapacitated, Duration::Rounds(4))),
         (ConditionID(uuid_2()), app_cond(Condition::Incapacitated, Duration::Interminate)),
@@ -369,11 +411,11 @@ pub mod test {
         ),
       )]);
     });
-    let c = game.get_creature(cid_rogue()).unwrap().tick().unwrap().creature;
+    let c = game.get_creature(cid_rogue()).unwrap().tick(&mut t_rng()).unwrap().creature;
     assert_eq!(c.cur_health, HP(9));
-    let c = game.dyn_creature(&c).unwrap().tick().unwrap().creature;
+    let c = game.dyn_creature(&c).unwrap().tick(&mut t_rng()).unwrap().creature;
     assert_eq!(c.cur_health, HP(8));
-    let c = game.dyn_creature(&c).unwrap().tick().unwrap().creature;
+    let c = game.dyn_creature(&c).unwrap().tick(&mut t_rng()).unwrap().creature;
     assert_eq!(c.cur_health, HP(8));
   }
 
@@ -388,7 +430,7 @@ pub mod test {
         app_cond(Condition::Incapacitated, Duration::Rounds(1)),
       )]);
     });
-    let c = game.get_creature(cid_rogue()).unwrap().tick().unwrap().creature;
+    let c = game.get_creature(cid_rogue()).unwrap().tick(&mut t_rng()).unwrap().creature;
     assert_eq!(
       c.conditions,
       HashMap::from_iter(vec![(
@@ -396,7 +438,7 @@ pub mod test {
         app_cond(Condition::Incapacitated, Duration::Rounds(0))
       ),])
     );
-    let c = game.dyn_creature(&c).unwrap().tick().unwrap().creature;
+    let c = game.dyn_creature(&c).unwrap().tick(&mut t_rng()).unwrap().creature;
     assert_eq!(c.conditions, HashMap::new());
   }
 }
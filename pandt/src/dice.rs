@@ -0,0 +1,939 @@
+//! Dice: rolling, and the textual notation used to write them down.
+//!
+//! The notation looks like `2d6+1d4-2` or `best 2 of (1d20+3)`. Dice serialize as that string, but
+//! the older structured form (`Plus: [{Expr: {num: 1, size: 20}}, {Flat: 3}]`) is still accepted
+//! when deserializing.
+
+use std::cmp;
+use std::fmt;
+use std::str::FromStr;
+
+use rand::{Rng, RngCore};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::types::{Dice, DiceStats, GameError};
+
+impl Dice {
+  pub fn expr(n: u8, d: u8) -> Dice { Dice::Expr { num: n, size: d } }
+
+  pub fn flat(val: i8) -> Dice { Dice::Flat(val) }
+
+  pub fn plus(&self, d: Dice) -> Dice { Dice::Plus(Box::new(self.clone()), Box::new(d)) }
+
+  /// Roll the dice, returning a vector containing all of the individual die rolls, and then the
+  /// final result. Every die that was rolled is included, even ones that were dropped or rerolled.
+  pub fn roll(&self, rng: &mut dyn RngCore) -> Result<(Vec<i16>, i32), GameError> {
+    self.validate()?;
+    Ok(self.roll_valid(rng))
+  }
+
+  /// Check that these dice can actually be rolled.
+  pub fn validate(&self) -> Result<(), GameError> {
+    let invalid = |reason: String| Err(GameError::InvalidDice(self.clone(), reason));
+    match *self {
+      Dice::Flat(_) => Ok(()),
+      Dice::Plus(ref l, ref r) => {
+        l.validate()?;
+        r.validate()
+      }
+      Dice::BestOf(0, _) | Dice::WorstOf(0, _) => invalid("must roll at least once".to_string()),
+      Dice::BestOf(_, ref dice) | Dice::WorstOf(_, ref dice) => dice.validate(),
+      Dice::Expr { size: 0, .. }
+      | Dice::KeepHighest { size: 0, .. }
+      | Dice::KeepLowest { size: 0, .. }
+      | Dice::Exploding { size: 0, .. }
+      | Dice::Reroll { size: 0, .. } => invalid("dice must have at least one side".to_string()),
+      Dice::Expr { .. } => Ok(()),
+      Dice::KeepHighest { num, keep, .. } | Dice::KeepLowest { num, keep, .. } => {
+        if keep == 0 || keep > num {
+          invalid(format!("can't keep {} of {} dice", keep, num))
+        } else {
+          Ok(())
+        }
+      }
+      Dice::Exploding { size: 1, .. } => invalid("one-sided dice would always explode".to_string()),
+      Dice::Exploding { .. } => Ok(()),
+      Dice::Reroll { size, threshold, .. } => {
+        if threshold >= size {
+          invalid(format!("every roll of a d{} would be rerolled", size))
+        } else {
+          Ok(())
+        }
+      }
+    }
+  }
+
+  /// Compute the exact probability distribution of the dice, along with some summary statistics.
+  pub fn stats(&self) -> Result<DiceStats, GameError> {
+    self.validate()?;
+    let (min, max) = self.bounds();
+    if max - min >= MAX_DISTRIBUTION_SIZE {
+      return Err(GameError::DiceTooComplex(self.clone()));
+    }
+    let dist = self.distribution()?;
+    let distribution: Vec<(i32, f64)> = (dist.min..)
+      .zip(dist.probs.iter().cloned())
+      .filter(|&(_, probability)| probability > 0.0)
+      .collect();
+    Ok(DiceStats {
+      min: distribution.first().map_or(0, |&(val, _)| val),
+      max: distribution.last().map_or(0, |&(val, _)| val),
+      mean: distribution.iter().map(|&(val, probability)| f64::from(val) * probability).sum(),
+      distribution,
+    })
+  }
+
+  /// The smallest and largest possible results.
+  fn bounds(&self) -> (i64, i64) {
+    match *self {
+      Dice::Flat(val) => (i64::from(val), i64::from(val)),
+      Dice::Expr { num, size } | Dice::Reroll { num, size, .. } => {
+        (i64::from(num), i64::from(num) * i64::from(size))
+      }
+      Dice::KeepHighest { size, keep, .. } | Dice::KeepLowest { size, keep, .. } => {
+        (i64::from(keep), i64::from(keep) * i64::from(size))
+      }
+      Dice::Exploding { num, size } => {
+        (i64::from(num), i64::from(num) * i64::from(size) * (MAX_EXPLOSIONS as i64 + 1))
+      }
+      Dice::Plus(ref l, ref r) => {
+        let ((lmin, lmax), (rmin, rmax)) = (l.bounds(), r.bounds());
+        (lmin + rmin, lmax + rmax)
+      }
+      Dice::BestOf(_, ref dice) | Dice::WorstOf(_, ref dice) => dice.bounds(),
+    }
+  }
+
+  /// Assumes the dice are valid.
+  fn distribution(&self) -> Result<Distribution, GameError> {
+    let dist = match *self {
+      Dice::Flat(val) => Distribution::constant(i32::from(val)),
+      Dice::Expr { num, size } => Distribution::uniform(size).repeat(num),
+      Dice::Plus(ref l, ref r) => l.distribution()?.convolve(&r.distribution()?),
+      Dice::BestOf(count, ref dice) => dice.distribution()?.best_of(count),
+      Dice::WorstOf(count, ref dice) => dice.distribution()?.worst_of(count),
+      Dice::KeepHighest { num, size, keep } | Dice::KeepLowest { num, size, keep } => {
+        let (num, size, keep) = (usize::from(num), usize::from(size), usize::from(keep));
+        if size * size * num * num * keep > MAX_KEEP_COMPLEXITY {
+          return Err(GameError::DiceTooComplex(self.clone()));
+        }
+        let highest = if let Dice::KeepHighest { .. } = *self { true } else { false };
+        Distribution::keep(num, size, keep, highest)
+      }
+      Dice::Exploding { num, size } => Distribution::exploding(size).repeat(num),
+      Dice::Reroll { num, size, threshold } => Distribution::reroll(size, threshold).repeat(num),
+    };
+    Ok(dist)
+  }
+
+  fn roll_valid(&self, rng: &mut dyn RngCore) -> (Vec<i16>, i32) {
+    match *self {
+      Dice::Expr { num, size } => {
+        let intermediate = roll_dice(rng, num, size);
+        let result = intermediate.iter().map(|&x| i32::from(x)).sum();
+        (intermediate, result)
+      }
+      Dice::Flat(val) => (vec![i16::from(val)], i32::from(val)),
+      Dice::Plus(ref l, ref r) => {
+        let (mut intermediate, left_result) = l.roll_valid(rng);
+        let (right_intermediate, right_result) = r.roll_valid(rng);
+        intermediate.extend(right_intermediate);
+        (intermediate, left_result + right_result)
+      }
+      Dice::BestOf(count, ref dice) => roll_repeatedly(rng, count, dice, |new, old| new > old),
+      Dice::WorstOf(count, ref dice) => roll_repeatedly(rng, count, dice, |new, old| new < old),
+      Dice::KeepHighest { num, size, keep } => {
+        let intermediate = roll_dice(rng, num, size);
+        let mut sorted = intermediate.clone();
+        sorted.sort_by(|a, b| b.cmp(a));
+        let result = sorted.iter().take(keep as usize).map(|&x| i32::from(x)).sum();
+        (intermediate, result)
+      }
+      Dice::KeepLowest { num, size, keep } => {
+        let intermediate = roll_dice(rng, num, size);
+        let mut sorted = intermediate.clone();
+        sorted.sort();
+        let result = sorted.iter().take(keep as usize).map(|&x| i32::from(x)).sum();
+        (intermediate, result)
+      }
+      Dice::Exploding { num, size } => {
+        let mut intermediate = vec![];
+        let mut result = 0;
+        for _ in 0..num {
+          for _ in 0..=MAX_EXPLOSIONS {
+            let val = roll_die(rng, size);
+            intermediate.push(val);
+            result += i32::from(val);
+            if val != i16::from(size) {
+              break;
+            }
+          }
+        }
+        (intermediate, result)
+      }
+      Dice::Reroll { num, size, threshold } => {
+        let mut intermediate = vec![];
+        let mut result = 0;
+        for _ in 0..num {
+          let mut val = roll_die(rng, size);
+          if val <= i16::from(threshold) {
+            intermediate.push(val);
+            val = roll_die(rng, size);
+          }
+          intermediate.push(val);
+          result += i32::from(val);
+        }
+        (intermediate, result)
+      }
+    }
+  }
+}
+
+/// The maximum number of times a single exploding die can explode, so that a very lucky streak
+/// can't produce an absurd result.
+pub const MAX_EXPLOSIONS: usize = 100;
+
+fn roll_die(rng: &mut dyn RngCore, size: u8) -> i16 { rng.gen_range(1, i16::from(size) + 1) }
+
+fn roll_dice(rng: &mut dyn RngCore, num: u8, size: u8) -> Vec<i16> {
+  (0..num).map(|_| roll_die(rng, size)).collect()
+}
+
+/// Roll `dice` `count` times, keeping the result that `better` prefers.
+fn roll_repeatedly<F>(rng: &mut dyn RngCore, count: u8, dice: &Dice, better: F) -> (Vec<i16>, i32)
+where
+  F: Fn(i32, i32) -> bool,
+{
+  let (mut best_rolls, mut best_result) = dice.roll_valid(rng);
+  for _ in 1..count {
+    let (rolls, result) = dice.roll_valid(rng);
+    if better(result, best_result) {
+      best_rolls = rolls;
+      best_result = result;
+    }
+  }
+  (best_rolls, best_result)
+}
+
+/// The largest number of distinct results we're willing to compute a distribution for.
+const MAX_DISTRIBUTION_SIZE: i64 = 10_000;
+/// A rough limit on the amount of work to do when computing the distribution of keep-highest and
+/// keep-lowest dice.
+const MAX_KEEP_COMPLEXITY: usize = 100_000_000;
+
+/// An exact probability distribution over the integers from `min` to `min + probs.len() - 1`.
+#[derive(Clone, Debug)]
+struct Distribution {
+  min: i32,
+  probs: Vec<f64>,
+}
+
+impl Distribution {
+  fn constant(val: i32) -> Distribution { Distribution { min: val, probs: vec![1.0] } }
+
+  fn uniform(size: u8) -> Distribution {
+    Distribution { min: 1, probs: vec![1.0 / f64::from(size); usize::from(size)] }
+  }
+
+  /// A single die that is rerolled once when it rolls `threshold` or lower.
+  fn reroll(size: u8, threshold: u8) -> Distribution {
+    let p = 1.0 / f64::from(size);
+    let rerolled = f64::from(threshold) * p * p;
+    let probs =
+      (1..=size).map(|val| if val > threshold { p + rerolled } else { rerolled }).collect();
+    Distribution { min: 1, probs }
+  }
+
+  /// A single exploding die, which can explode at most `MAX_EXPLOSIONS` times.
+  fn exploding(size: u8) -> Distribution {
+    let size = usize::from(size);
+    let p = 1.0 / size as f64;
+    let mut probs = vec![0.0; size * (MAX_EXPLOSIONS + 1)];
+    let mut all_max = 1.0;
+    for explosions in 0..=MAX_EXPLOSIONS {
+      for val in 1..size {
+        probs[explosions * size + val - 1] = all_max * p;
+      }
+      all_max *= p;
+    }
+    // Every roll was the maximum, so we stopped exploding.
+    probs[size * (MAX_EXPLOSIONS + 1) - 1] = all_max;
+    Distribution { min: 1, probs }
+  }
+
+  /// Roll `num` dice, sort them, and keep the highest (or lowest) `keep` of them.
+  ///
+  /// Rather than enumerate every possible roll, this walks over the faces of the die in the order
+  /// they'd be kept, deciding how many of the dice landed on each face. The first `keep` dice
+  /// assigned to a face are the ones that are kept.
+  fn keep(num: usize, size: usize, keep: usize, highest: bool) -> Distribution {
+    let p = 1.0 / size as f64;
+    let max_sum = keep * size;
+    // weights[assigned][sum]: the probability that `assigned` dice have landed on the faces
+    // visited so far, and the kept ones add up to `sum`.
+    let mut weights = vec![vec![0.0; max_sum + 1]; num + 1];
+    weights[0][0] = 1.0;
+    let faces: Vec<usize> = if highest { (1..=size).rev().collect() } else { (1..=size).collect() };
+    for face in faces {
+      let mut next = vec![vec![0.0; max_sum + 1]; num + 1];
+      for assigned in 0..=num {
+        for sum in 0..=max_sum {
+          let weight = weights[assigned][sum];
+          if weight == 0.0 {
+            continue;
+          }
+          let remaining = num - assigned;
+          // choose(remaining, count) * p^count
+          let mut coefficient = 1.0;
+          for count in 0..=remaining {
+            if count > 0 {
+              coefficient *= (remaining - count + 1) as f64 / count as f64 * p;
+            }
+            let kept = cmp::min(count, keep.saturating_sub(assigned));
+            next[assigned + count][sum + kept * face] += weight * coefficient;
+          }
+        }
+      }
+      weights = next;
+    }
+    Distribution { min: 0, probs: weights.swap_remove(num) }.trim()
+  }
+
+  /// Remove impossible results from either end.
+  fn trim(mut self) -> Distribution {
+    let leading = self.probs.iter().take_while(|&&p| p == 0.0).count();
+    if leading == self.probs.len() {
+      return self;
+    }
+    let trailing = self.probs.iter().rev().take_while(|&&p| p == 0.0).count();
+    self.probs.truncate(self.probs.len() - trailing);
+    self.probs.drain(..leading);
+    self.min += leading as i32;
+    self
+  }
+
+  /// The distribution of the sum of two independent results.
+  fn convolve(&self, other: &Distribution) -> Distribution {
+    let mut probs = vec![0.0; self.probs.len() + other.probs.len() - 1];
+    for (i, p) in self.probs.iter().enumerate() {
+      if *p == 0.0 {
+        continue;
+      }
+      for (j, q) in other.probs.iter().enumerate() {
+        probs[i + j] += p * q;
+      }
+    }
+    Distribution { min: self.min + other.min, probs }
+  }
+
+  /// The distribution of the sum of `count` independent results.
+  fn repeat(&self, count: u8) -> Distribution {
+    let mut result = Distribution::constant(0);
+    for _ in 0..count {
+      result = result.convolve(self);
+    }
+    result
+  }
+
+  /// The distribution of the best of `count` independent results.
+  fn best_of(&self, count: u8) -> Distribution {
+    // P(best <= x) = P(X <= x) ^ count
+    let mut cumulative = 0.0;
+    let mut previous = 0.0;
+    let mut probs = Vec::with_capacity(self.probs.len());
+    for p in &self.probs {
+      cumulative += p;
+      let all_at_most = f64::powi(cumulative, i32::from(count));
+      probs.push(all_at_most - previous);
+      previous = all_at_most;
+    }
+    Distribution { min: self.min, probs }
+  }
+
+  /// The distribution of the worst of `count` independent results.
+  fn worst_of(&self, count: u8) -> Distribution {
+    // P(worst >= x) = P(X >= x) ^ count
+    let mut cumulative = 0.0;
+    let mut previous = 0.0;
+    let mut probs = vec![0.0; self.probs.len()];
+    for (i, p) in self.probs.iter().enumerate().rev() {
+      cumulative += p;
+      let all_at_least = f64::powi(cumulative, i32::from(count));
+      probs[i] = all_at_least - previous;
+      previous = all_at_least;
+    }
+    Distribution { min: self.min, probs }
+  }
+}
+
+impl fmt::Display for Dice {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Dice::Expr { num, size } => write!(f, "{}d{}", num, size),
+      Dice::Flat(val) => write!(f, "{}", val),
+      Dice::Plus(ref l, ref r) => {
+        write!(f, "{}", l)?;
+        match **r {
+          Dice::Flat(val) if val < 0 => write!(f, "-{}", -i16::from(val)),
+          // Plus is left-associative when parsed, so a nested right-hand side needs parens to
+          // round-trip.
+          Dice::Plus(..) => write!(f, "+({})", r),
+          _ => write!(f, "+{}", r),
+        }
+      }
+      Dice::BestOf(count, ref dice) => write!(f, "best {} of ({})", count, dice),
+      Dice::WorstOf(count, ref dice) => write!(f, "worst {} of ({})", count, dice),
+      Dice::KeepHighest { num, size, keep } => write!(f, "{}d{}kh{}", num, size, keep),
+      Dice::KeepLowest { num, size, keep } => write!(f, "{}d{}kl{}", num, size, keep),
+      Dice::Exploding { num, size } => write!(f, "{}d{}!", num, size),
+      Dice::Reroll { num, size, threshold } => write!(f, "{}d{}r{}", num, size, threshold),
+    }
+  }
+}
+
+impl FromStr for Dice {
+  type Err = GameError;
+  fn from_str(s: &str) -> Result<Dice, GameError> {
+    let mut parser = DiceParser { input: s, pos: 0 };
+    let dice = parser.expression()?;
+    parser.skip_whitespace();
+    if parser.pos < s.len() {
+      return Err(parser.error("unexpected trailing input"));
+    }
+    dice.validate()?;
+    Ok(dice)
+  }
+}
+
+/// A little recursive-descent parser for dice notation.
+///
+/// ```text
+/// expression := ['-' number] (('+' term) | ('-' number))*
+///            |  term (('+' term) | ('-' number))*
+/// term       := [number] 'd' number [modifier] | number | '(' expression ')'
+///            |  ('best' | 'worst') number 'of' term
+/// modifier   := ('kh' | 'k' | 'kl' | 'dl' | 'dh' | 'r') number | '!'
+/// ```
+///
+/// Only flat numbers can be subtracted, since there's no way to represent a negated die roll.
+struct DiceParser<'a> {
+  input: &'a str,
+  pos: usize,
+}
+
+impl<'a> DiceParser<'a> {
+  fn error(&self, message: &str) -> GameError { self.error_at(self.pos, message) }
+
+  fn error_at(&self, pos: usize, message: &str) -> GameError {
+    GameError::InvalidDiceExpression(self.input.to_string(), pos, message.to_string())
+  }
+
+  fn peek(&self) -> Option<u8> { self.input.as_bytes().get(self.pos).cloned() }
+
+  fn skip_whitespace(&mut self) {
+    while let Some(c) = self.peek() {
+      if !c.is_ascii_whitespace() {
+        break;
+      }
+      self.pos += 1;
+    }
+  }
+
+  /// Consume `c` if it's the next non-whitespace character.
+  fn eat(&mut self, c: u8) -> bool {
+    self.skip_whitespace();
+    if self.peek() == Some(c) {
+      self.pos += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn keyword(&mut self, word: &str) -> Result<(), GameError> {
+    self.skip_whitespace();
+    let rest = &self.input.as_bytes()[self.pos..];
+    let matched =
+      rest.len() >= word.len() && rest[..word.len()].eq_ignore_ascii_case(word.as_bytes());
+    let followed_by_letter = rest.get(word.len()).map_or(false, |c| c.is_ascii_alphabetic());
+    if !matched || followed_by_letter {
+      return Err(self.error(&format!("expected '{}'", word)));
+    }
+    self.pos += word.len();
+    Ok(())
+  }
+
+  /// Parse an unsigned decimal number, returning it along with the position it started at.
+  fn number(&mut self) -> Result<(u32, usize), GameError> {
+    self.skip_whitespace();
+    let start = self.pos;
+    while let Some(b'0'..=b'9') = self.peek() {
+      self.pos += 1;
+    }
+    if start == self.pos {
+      return Err(self.error("expected a number"));
+    }
+    let num = self.input[start..self.pos]
+      .parse()
+      .map_err(|_| self.error_at(start, "number is too large"))?;
+    Ok((num, start))
+  }
+
+  fn small_number(&mut self) -> Result<u8, GameError> {
+    let (num, start) = self.number()?;
+    if num > u32::from(u8::MAX) {
+      return Err(self.error_at(start, "number is too large"));
+    }
+    Ok(num as u8)
+  }
+
+  fn flat(&self, num: u32, negative: bool, start: usize) -> Result<Dice, GameError> {
+    let val = if negative { -i64::from(num) } else { i64::from(num) };
+    if val < i64::from(i8::MIN) || val > i64::from(i8::MAX) {
+      return Err(self.error_at(start, "flat number is out of range"));
+    }
+    Ok(Dice::Flat(val as i8))
+  }
+
+  /// Parse the number after a '-' sign, which must be flat.
+  fn negative(&mut self) -> Result<Dice, GameError> {
+    let (num, start) = self.number()?;
+    if let Some(b'd') | Some(b'D') = self.peek() {
+      return Err(self.error_at(start, "only flat numbers can be subtracted"));
+    }
+    self.flat(num, true, start)
+  }
+
+  fn expression(&mut self) -> Result<Dice, GameError> {
+    let mut dice = if self.eat(b'-') { self.negative()? } else { self.term()? };
+    loop {
+      let right = if self.eat(b'+') {
+        self.term()?
+      } else if self.eat(b'-') {
+        self.negative()?
+      } else {
+        return Ok(dice);
+      };
+      dice = Dice::Plus(Box::new(dice), Box::new(right));
+    }
+  }
+
+  fn term(&mut self) -> Result<Dice, GameError> {
+    self.skip_whitespace();
+    match self.peek() {
+      Some(b'(') => {
+        self.pos += 1;
+        let dice = self.expression()?;
+        if !self.eat(b')') {
+          return Err(self.error("expected ')'"));
+        }
+        Ok(dice)
+      }
+      Some(b'0'..=b'9') => {
+        let (num, start) = self.number()?;
+        if let Some(b'd') | Some(b'D') = self.peek() {
+          self.pos += 1;
+          if num > u32::from(u8::MAX) {
+            return Err(self.error_at(start, "number is too large"));
+          }
+          let size = self.die_size()?;
+          self.modifier(num as u8, size)
+        } else {
+          self.flat(num, false, start)
+        }
+      }
+      Some(b'd') | Some(b'D') => {
+        self.pos += 1;
+        let size = self.die_size()?;
+        self.modifier(1, size)
+      }
+      Some(b'b') | Some(b'B') => {
+        self.keyword("best")?;
+        let count = self.small_number()?;
+        self.keyword("of")?;
+        let dice = self.term()?;
+        Ok(Dice::BestOf(count, Box::new(dice)))
+      }
+      Some(b'w') | Some(b'W') => {
+        self.keyword("worst")?;
+        let count = self.small_number()?;
+        self.keyword("of")?;
+        let dice = self.term()?;
+        Ok(Dice::WorstOf(count, Box::new(dice)))
+      }
+      Some(_) => Err(self.error("unexpected character")),
+      None => Err(self.error("unexpected end of input")),
+    }
+  }
+
+  /// The size of a die comes directly after the 'd', with no whitespace.
+  fn die_size(&mut self) -> Result<u8, GameError> {
+    match self.peek() {
+      Some(b'0'..=b'9') => self.small_number(),
+      _ => Err(self.error("expected a die size")),
+    }
+  }
+
+  /// Parse the optional modifier directly after `NdM`, like the "kh3" in "4d6kh3".
+  fn modifier(&mut self, num: u8, size: u8) -> Result<Dice, GameError> {
+    let rest = self.input[self.pos..].to_ascii_lowercase();
+    let (len, make): (usize, fn(u8, u8, u8) -> Option<Dice>) = if rest.starts_with("kh") {
+      (2, |num, size, keep| Some(Dice::KeepHighest { num, size, keep }))
+    } else if rest.starts_with("kl") {
+      (2, |num, size, keep| Some(Dice::KeepLowest { num, size, keep }))
+    } else if rest.starts_with('k') {
+      (1, |num, size, keep| Some(Dice::KeepHighest { num, size, keep }))
+    } else if rest.starts_with("dl") {
+      (2, |num, size, drop| num.checked_sub(drop).map(|keep| Dice::KeepHighest { num, size, keep }))
+    } else if rest.starts_with("dh") {
+      (2, |num, size, drop| num.checked_sub(drop).map(|keep| Dice::KeepLowest { num, size, keep }))
+    } else if rest.starts_with('r') {
+      (1, |num, size, threshold| Some(Dice::Reroll { num, size, threshold }))
+    } else if rest.starts_with('!') {
+      self.pos += 1;
+      return Ok(Dice::Exploding { num, size });
+    } else {
+      return Ok(Dice::Expr { num, size });
+    };
+    self.pos += len;
+    let start = self.pos;
+    match self.peek() {
+      Some(b'0'..=b'9') => {
+        let arg = self.small_number()?;
+        make(num, size, arg).ok_or_else(|| self.error_at(start, "can't drop more dice than rolled"))
+      }
+      _ => Err(self.error("expected a number")),
+    }
+  }
+}
+
+impl Serialize for Dice {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&self.to_string())
+  }
+}
+
+impl<'de> Deserialize<'de> for Dice {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Dice, D::Error> {
+    match SerializedDice::deserialize(deserializer)? {
+      SerializedDice::Notation(s) => s.parse().map_err(de::Error::custom),
+      SerializedDice::Number(val) => Ok(Dice::Flat(val)),
+      SerializedDice::Structured(StructuredDice::Expr { num, size }) => {
+        Ok(Dice::Expr { num, size })
+      }
+      SerializedDice::Structured(StructuredDice::Plus(l, r)) => Ok(Dice::Plus(l, r)),
+      SerializedDice::Structured(StructuredDice::Flat(val)) => Ok(Dice::Flat(val)),
+      SerializedDice::Structured(StructuredDice::BestOf(count, dice)) => {
+        Ok(Dice::BestOf(count, dice))
+      }
+    }
+  }
+}
+
+/// All of the forms we accept when deserializing Dice.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SerializedDice {
+  Notation(String),
+  Number(i8),
+  Structured(StructuredDice),
+}
+
+/// The structured representation that Dice used to be serialized as. Sub-dice can be in any form.
+#[derive(Deserialize)]
+enum StructuredDice {
+  Expr { num: u8, size: u8 },
+  Plus(Box<Dice>, Box<Dice>),
+  Flat(i8),
+  BestOf(u8, Box<Dice>),
+}
+
+#[cfg(test)]
+mod test {
+  use crate::types::test::*;
+  use crate::types::*;
+
+  fn parse(s: &str) -> Dice { s.parse().unwrap_or_else(|e| panic!("{:?}: {}", s, e)) }
+
+  fn parse_error(s: &str) -> (usize, String) {
+    match s.parse::<Dice>() {
+      Err(GameError::InvalidDiceExpression(input, pos, message)) => {
+        assert_eq!(input, s);
+        (pos, message)
+      }
+      r => panic!("Expected a parse error for {:?}, got {:?}", s, r),
+    }
+  }
+
+  #[test]
+  fn parse_simple() {
+    assert_eq!(parse("1d20"), Dice::expr(1, 20));
+    assert_eq!(parse("d20"), Dice::expr(1, 20));
+    assert_eq!(parse("5"), Dice::flat(5));
+    assert_eq!(parse("-5"), Dice::flat(-5));
+    assert_eq!(parse(" 3D6 "), Dice::expr(3, 6));
+  }
+
+  #[test]
+  fn parse_sums() {
+    assert_eq!(parse("2d6+1d4-2"), Dice::expr(2, 6).plus(Dice::expr(1, 4)).plus(Dice::flat(-2)));
+    assert_eq!(parse("2d6 + 3"), Dice::expr(2, 6).plus(Dice::flat(3)));
+    assert_eq!(parse("-1+1d4"), Dice::flat(-1).plus(Dice::expr(1, 4)));
+    assert_eq!(parse("1d4+(1d6+1)"), Dice::expr(1, 4).plus(Dice::expr(1, 6).plus(Dice::flat(1))));
+  }
+
+  #[test]
+  fn parse_best_of() {
+    assert_eq!(
+      parse("best 2 of (1d20+3)"),
+      Dice::BestOf(2, Box::new(Dice::expr(1, 20).plus(Dice::flat(3))))
+    );
+    assert_eq!(parse("best 2 of 1d20"), Dice::BestOf(2, Box::new(Dice::expr(1, 20))));
+    assert_eq!(
+      parse("best 2 of (1d20)+3"),
+      Dice::BestOf(2, Box::new(Dice::expr(1, 20))).plus(Dice::flat(3))
+    );
+  }
+
+  #[test]
+  fn display_round_trips() {
+    let cases = vec![
+      "1d20",
+      "-5",
+      "2d6+1d4-2",
+      "best 2 of (1d20+3)",
+      "1d4+(1d6+1)",
+      "1d8+best 3 of (1d6)-128",
+    ];
+    for case in cases {
+      assert_eq!(parse(case).to_string(), case);
+    }
+    let d = Dice::expr(1, 4).plus(Dice::flat(-3).plus(Dice::expr(2, 8)));
+    assert_eq!(parse(&d.to_string()), d);
+  }
+
+  #[test]
+  fn parse_errors() {
+    assert_eq!(parse_error(""), (0, "unexpected end of input".to_string()));
+    assert_eq!(parse_error("1d20+"), (5, "unexpected end of input".to_string()));
+    assert_eq!(parse_error("1d20-1d4"), (5, "only flat numbers can be subtracted".to_string()));
+    assert_eq!(parse_error("1d"), (2, "expected a die size".to_string()));
+    assert_eq!(parse_error("1d300"), (2, "number is too large".to_string()));
+    assert_eq!(parse_error("200"), (0, "flat number is out of range".to_string()));
+    assert_eq!(parse_error("1d20 x"), (5, "unexpected trailing input".to_string()));
+    assert_eq!(parse_error("(1d20"), (5, "expected ')'".to_string()));
+    assert_eq!(parse_error("best 2 1d20"), (7, "expected 'of'".to_string()));
+  }
+
+  #[test]
+  fn parse_modifiers() {
+    assert_eq!(parse("4d6kh3"), Dice::KeepHighest { num: 4, size: 6, keep: 3 });
+    assert_eq!(parse("4d6k3"), Dice::KeepHighest { num: 4, size: 6, keep: 3 });
+    assert_eq!(parse("4d6dl1"), Dice::KeepHighest { num: 4, size: 6, keep: 3 });
+    assert_eq!(parse("2d20kl1"), Dice::KeepLowest { num: 2, size: 20, keep: 1 });
+    assert_eq!(parse("2d20dh1"), Dice::KeepLowest { num: 2, size: 20, keep: 1 });
+    assert_eq!(parse("2d6!"), Dice::Exploding { num: 2, size: 6 });
+    assert_eq!(parse("2d6r1"), Dice::Reroll { num: 2, size: 6, threshold: 1 });
+    assert_eq!(parse("worst 2 of (1d20)"), Dice::WorstOf(2, Box::new(Dice::expr(1, 20))));
+    assert_eq!(parse("d8!+2"), Dice::Exploding { num: 1, size: 8 }.plus(Dice::flat(2)));
+  }
+
+  #[test]
+  fn display_modifiers_round_trips() {
+    for case in &["4d6kh3", "2d20kl1", "2d6!", "2d6r1", "worst 2 of (1d20+1)", "1d6!+4d6kh3-1"] {
+      assert_eq!(parse(case).to_string(), *case);
+    }
+  }
+
+  #[test]
+  fn invalid_dice() {
+    let invalid = |s: &str| match s.parse::<Dice>() {
+      Err(GameError::InvalidDice(..)) => {}
+      r => panic!("Expected {:?} to be invalid, got {:?}", s, r),
+    };
+    invalid("best 0 of (1d20)");
+    invalid("worst 0 of (1d20)");
+    invalid("1d0");
+    invalid("4d6kh5");
+    invalid("4d6kh0");
+    invalid("3d1!");
+    invalid("2d6r6");
+    assert_eq!(parse_error("2d6dl3"), (5, "can't drop more dice than rolled".to_string()));
+    assert_eq!(parse_error("2d6kh"), (5, "expected a number".to_string()));
+  }
+
+  #[test]
+  fn roll_degenerate_dice_is_an_error() {
+    let d = Dice::BestOf(0, Box::new(Dice::expr(1, 20)));
+    match d.roll(&mut t_rng()) {
+      Err(GameError::InvalidDice(ref dice, _)) if dice == &d => {}
+      r => panic!("Expected an error, got {:?}", r),
+    }
+    assert!(Dice::flat(1).plus(Dice::expr(1, 0)).roll(&mut t_rng()).is_err());
+  }
+
+  #[test]
+  fn roll_keep() {
+    let mut rng = t_rng();
+    for _ in 0..100 {
+      let (rolls, result) = parse("4d6kh3").roll(&mut rng).unwrap();
+      let mut sorted = rolls.clone();
+      sorted.sort();
+      assert_eq!(rolls.len(), 4);
+      assert_eq!(result, sorted[1..].iter().map(|&x| i32::from(x)).sum::<i32>());
+
+      let (rolls, result) = parse("2d20kl1").roll(&mut rng).unwrap();
+      assert_eq!(result, i32::from(*rolls.iter().min().unwrap()));
+    }
+  }
+
+  #[test]
+  fn roll_worst_of() {
+    let mut rng = t_rng();
+    for _ in 0..100 {
+      let (rolls, result) = parse("worst 3 of (1d20)").roll(&mut rng).unwrap();
+      assert_eq!(rolls, vec![result as i16]);
+      assert!(result >= 1 && result <= 20);
+    }
+  }
+
+  #[test]
+  fn roll_exploding() {
+    let mut rng = t_rng();
+    let mut exploded = false;
+    for _ in 0..100 {
+      let (rolls, result) = parse("1d2!").roll(&mut rng).unwrap();
+      // Every die but the last one in the chain rolled the maximum.
+      assert!(rolls[..rolls.len() - 1].iter().all(|&x| x == 2));
+      assert_eq!(*rolls.last().unwrap(), 1);
+      assert_eq!(result, rolls.iter().map(|&x| i32::from(x)).sum::<i32>());
+      exploded = exploded || rolls.len() > 1;
+    }
+    assert!(exploded);
+  }
+
+  #[test]
+  fn roll_reroll() {
+    let mut rng = t_rng();
+    let mut rerolled = false;
+    for _ in 0..100 {
+      let (rolls, result) = parse("1d4r2").roll(&mut rng).unwrap();
+      match rolls.len() {
+        1 => assert!(rolls[0] > 2),
+        2 => {
+          assert!(rolls[0] <= 2);
+          rerolled = true;
+        }
+        _ => panic!("Rolled too many dice: {:?}", rolls),
+      }
+      assert_eq!(result, i32::from(*rolls.last().unwrap()));
+    }
+    assert!(rerolled);
+  }
+
+  fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+  }
+
+  fn probability(stats: &DiceStats, val: i32) -> f64 {
+    stats.distribution.iter().find(|&&(v, _)| v == val).map_or(0.0, |&(_, p)| p)
+  }
+
+  fn assert_sums_to_one(stats: &DiceStats) {
+    assert_close(stats.distribution.iter().map(|&(_, p)| p).sum(), 1.0);
+  }
+
+  #[test]
+  fn stats_simple() {
+    let stats = parse("2d6").stats().unwrap();
+    assert_eq!((stats.min, stats.max), (2, 12));
+    assert_close(stats.mean, 7.0);
+    assert_close(probability(&stats, 7), 6.0 / 36.0);
+    assert_sums_to_one(&stats);
+
+    let stats = parse("1d4-2").stats().unwrap();
+    assert_eq!((stats.min, stats.max), (-1, 2));
+    assert_close(stats.mean, 0.5);
+
+    let stats = parse("3").stats().unwrap();
+    assert_eq!(stats.distribution, vec![(3, 1.0)]);
+  }
+
+  #[test]
+  fn stats_best_and_worst_of() {
+    let best = parse("best 2 of (1d20)").stats().unwrap();
+    assert_close(best.mean, 13.825);
+    assert_close(probability(&best, 20), 1.0 - (0.95 * 0.95));
+    assert_sums_to_one(&best);
+
+    let worst = parse("worst 2 of (1d20)").stats().unwrap();
+    assert_close(worst.mean, 7.175);
+    assert_sums_to_one(&worst);
+  }
+
+  #[test]
+  fn stats_keep() {
+    let stats = parse("4d6kh3").stats().unwrap();
+    assert_eq!((stats.min, stats.max), (3, 18));
+    assert_close(stats.mean, 15869.0 / 1296.0);
+    assert_sums_to_one(&stats);
+
+    // Keeping the highest die of two is the same as the best of two dice.
+    let kh = parse("2d20kh1").stats().unwrap();
+    let best = parse("best 2 of (1d20)").stats().unwrap();
+    for (&(kv, kp), &(bv, bp)) in kh.distribution.iter().zip(best.distribution.iter()) {
+      assert_eq!(kv, bv);
+      assert_close(kp, bp);
+    }
+
+    let kl = parse("2d20kl1").stats().unwrap();
+    assert_close(kl.mean, 7.175);
+  }
+
+  #[test]
+  fn stats_exploding_and_reroll() {
+    let stats = parse("1d6!").stats().unwrap();
+    // A die that can explode forever averages 3.5 * 6/5.
+    assert_close(stats.mean, 4.2);
+    assert_eq!(probability(&stats, 6), 0.0);
+    assert_close(probability(&stats, 7), 1.0 / 36.0);
+    assert_sums_to_one(&stats);
+
+    let stats = parse("1d6r1").stats().unwrap();
+    assert_close(stats.mean, 20.0 / 6.0 + 21.0 / 36.0);
+    assert_close(probability(&stats, 1), 1.0 / 36.0);
+    assert_sums_to_one(&stats);
+  }
+
+  #[test]
+  fn stats_too_complex() {
+    match parse("200d200").stats() {
+      Err(GameError::DiceTooComplex(_)) => {}
+      r => panic!("Expected an error, got {:?}", r),
+    }
+  }
+
+  #[test]
+  fn serialize_as_notation() {
+    let d = Dice::expr(2, 6).plus(Dice::flat(-2));
+    assert_eq!(serde_json::to_string(&d).unwrap(), "\"2d6-2\"");
+    assert_eq!(serde_yaml::from_str::<Dice>("2d6-2").unwrap(), d);
+  }
+
+  #[test]
+  fn deserialize_structured() {
+    let yaml = "BestOf: [2, {Plus: [{Expr: {num: 1, size: 20}}, \"1d4\"]}]";
+    assert_eq!(
+      serde_yaml::from_str::<Dice>(yaml).unwrap(),
+      Dice::BestOf(2, Box::new(Dice::expr(1, 20).plus(Dice::expr(1, 4))))
+    );
+    assert_eq!(serde_yaml::from_str::<Dice>("Flat: -3").unwrap(), Dice::flat(-3));
+    assert_eq!(serde_json::from_str::<Dice>("4").unwrap(), Dice::flat(4));
+  }
+
+  #[test]
+  fn deserialize_reports_parse_error() {
+    let err = serde_json::from_str::<Dice>("\"1d20+\"").unwrap_err();
+    assert!(err.to_string().contains("position 5"), "{}", err);
+  }
+}
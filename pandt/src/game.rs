@@ -1,18 +1,19 @@
 use std::cmp;
 use std::collections::{HashMap, HashSet};
-use std::fs::File;
-use std::io::Read;
 use std::iter::FromIterator;
-use std::path::Path;
+use std::sync::Arc;
 
 use error_chain::bail;
+use rand::RngCore;
 use serde_yaml;
 
 use crate::combat::*;
 use crate::creature::ChangedCreature;
 use crate::grid::line_through_point;
+use crate::journal;
 use crate::types::*;
 use foldertree::FolderPath;
+use ptstorage::Storage;
 
 impl Game {
   pub fn export_module(&self, export_path: &FolderPath) -> Result<Game, GameError> {
@@ -164,14 +165,15 @@ impl Game {
     self.abilities.get(&abid).ok_or_else(|| GameError::NoAbility(abid).into())
   }
 
-  /// Perform a GameCommand on the current Game.
+  /// Perform a GameCommand on the current Game. All randomness (dice rolls, initiative, and so on)
+  /// is drawn from `rng`.
   pub fn perform_command(
-    &self, cmd: GameCommand, saved_game_path: &Path, module_path: Option<&Path>,
+    &self, cmd: GameCommand, rng: &mut dyn RngCore, storage: &dyn Storage,
   ) -> Result<ChangedGame, GameError> {
     use self::GameCommand::*;
     let change = match cmd {
       LoadModule { ref name, ref path, source } => {
-        let app = load_app_from_path(saved_game_path, module_path, source, name)?;
+        let app = load_app(storage, source, name)?;
         let module = app.current_game;
         self.change_with(GameLog::LoadModule {
           name: name.clone(),
@@ -181,6 +183,7 @@ impl Game {
         })
       }
       SetActiveScene(m_sid) => self.change_with(GameLog::SetActiveScene(m_sid)),
+      SetVettingPolicy(policy) => self.change_with(GameLog::SetVettingPolicy(policy)),
       // ** Player Management **
       RegisterPlayer(ref pid) => self.change_with(GameLog::RegisterPlayer(pid.clone())),
       GiveCreaturesToPlayer(ref pid, ref cids) => {
@@ -201,7 +204,7 @@ impl Game {
       }
 
       // ** Attribute checks **
-      AttributeCheck(cid, check) => self.attribute_check(cid, &check),
+      AttributeCheck(cid, check) => self.attribute_check(cid, &check, rng),
       // ** Folder Management **
       CreateFolder(path) => self.change_with(GameLog::CreateFolder(path)),
       RenameFolder(path, name) => self.change_with(GameLog::RenameFolder(path, name)),
@@ -289,8 +292,8 @@ impl Game {
       PathCreature(scene, cid, pt) => Ok(self.path_creature(scene, cid, pt)?.0),
       SetCreaturePos(scene, cid, pt) => self.change_with(GameLog::SetCreaturePos(scene, cid, pt)),
       PathCurrentCombatCreature(pt) => self.get_combat()?.get_movement()?.move_current(pt),
-      CombatAct(abid, dtarget) => self.combat_act(abid, dtarget),
-      ActCreature(scene, cid, abid, dtarget) => self.ooc_act(scene, cid, abid, dtarget),
+      CombatAct(abid, dtarget) => self.combat_act(abid, dtarget, rng),
+      ActCreature(scene, cid, abid, dtarget) => self.ooc_act(scene, cid, abid, dtarget, rng),
       EditSceneTerrain { scene_id, ref terrain } => {
         self.change_with(GameLog::EditSceneTerrain { scene_id, terrain: terrain.clone() })
       }
@@ -311,42 +314,51 @@ impl Game {
           scene_hotspots: scene_hotspots.clone(),
         })
       }
-      StartCombat(scene, cids) => self.start_combat(scene, cids),
+      StartCombat(scene, cids) => self.start_combat(scene, cids, rng),
       StopCombat => self.change_with(GameLog::StopCombat),
-      AddCreatureToCombat(cid) => self.add_creature_to_combat(cid),
+      AddCreatureToCombat(cid) => self.add_creature_to_combat(cid, rng),
       RemoveCreatureFromCombat(cid) => self.change_with(GameLog::RemoveCreatureFromCombat(cid)),
-      RerollCombatInitiative => self.change().apply_combat(|c| c.reroll_initiative()),
+      RerollCombatInitiative => self.change().apply_combat(|c| c.reroll_initiative(rng)),
       ChangeCreatureInitiative(cid, new_pos) => {
         self.change_with(GameLog::CombatLog(CombatLog::ChangeCreatureInitiative(cid, new_pos)))
       }
       ForceNextTurn => self.change_with(GameLog::CombatLog(CombatLog::ForceNextTurn)),
       ForcePrevTurn => self.change_with(GameLog::CombatLog(CombatLog::ForcePrevTurn)),
-      Done => self.next_turn(),
+      Done => self.next_turn(rng),
 
       // These are handled by the app before being passed to the Game:
-      Rollback(..) => bug("Game Rollback"),
+      Rollback(..)
+      | SwitchBranch(..)
+      | Undo
+      | Redo
+      | EditLog(..)
+      | ApprovePendingCommand(..)
+      | RejectPendingCommand(..)
+      | EditPendingCommand(..) => bug("Game Rollback"),
     }?;
     Ok(change)
   }
 
   fn start_combat(
-    &self, scene_id: SceneID, cids: Vec<CreatureID>,
+    &self, scene_id: SceneID, cids: Vec<CreatureID>, rng: &mut dyn RngCore,
   ) -> Result<ChangedGame, GameError> {
-    let cids_with_inits = Combat::roll_initiative(self, cids)?;
+    let cids_with_inits = Combat::roll_initiative(self, cids, rng)?;
     self.change_with(GameLog::StartCombat(scene_id, cids_with_inits))
   }
 
-  fn add_creature_to_combat(&self, cid: CreatureID) -> Result<ChangedGame, GameError> {
+  fn add_creature_to_combat(
+    &self, cid: CreatureID, rng: &mut dyn RngCore,
+  ) -> Result<ChangedGame, GameError> {
     let creature = self.get_creature(cid)?;
-    let init = creature.creature.initiative.roll().1 as i16;
+    let init = creature.creature.initiative.roll(rng)?.1 as i16;
     self.change_with(GameLog::AddCreatureToCombat(cid, init))
   }
 
   fn attribute_check(
-    &self, cid: CreatureID, check: &AttributeCheck,
+    &self, cid: CreatureID, check: &AttributeCheck, rng: &mut dyn RngCore,
   ) -> Result<ChangedGame, GameError> {
     let creature = self.get_creature(cid)?;
-    let (rolled, success) = creature.creature.attribute_check(check)?;
+    let (rolled, success) = creature.creature.attribute_check(check, rng)?;
     self.change_with(GameLog::AttributeCheckResult(cid, check.clone(), rolled, success))
   }
 
@@ -378,9 +390,10 @@ impl Game {
     Ok((change, distance))
   }
 
-  fn next_turn(&self) -> Result<ChangedGame, GameError> {
+  fn next_turn(&self, rng: &mut dyn RngCore) -> Result<ChangedGame, GameError> {
     let change = self.change().apply_combat(|c| c.next_turn())?;
-    change.apply_creature(self.current_combat.as_ref().unwrap().current_creature_id(), |c| c.tick())
+    change
+      .apply_creature(self.current_combat.as_ref().unwrap().current_creature_id(), |c| c.tick(rng))
   }
 
   fn link_folder_item(
@@ -484,6 +497,7 @@ impl Game {
       }
 
       SetActiveScene(m_sid) => self.active_scene = m_sid,
+      SetVettingPolicy(policy) => self.vetting_policy = policy,
 
       // Player stuff
       RegisterPlayer(ref pid) => {
@@ -540,7 +554,7 @@ impl Game {
             .notes
             .remove(name)
             .ok_or_else(|| GameError::NoteNotFound(src.clone(), name.clone()))?;
-          self.campaign.get_mut(dst)?.notes.insert(note.clone());
+          self.campaign.get_mut(dst)?.notes.insert((*note).clone());
         }
         FolderItemID::SubfolderID(ref name) => {
           self.campaign.move_folder(&src.child(name.clone()), dst)?;
@@ -802,6 +816,7 @@ impl Game {
             scene.background_image_url = details.background_image_url.clone();
             scene.background_image_offset = details.background_image_offset;
             scene.background_image_scale = details.background_image_scale;
+            scene.fog_of_war = details.fog_of_war;
           })
           .ok_or_else(|| GameError::SceneNotFound(scene_id))?;
       }
@@ -870,19 +885,19 @@ impl Game {
       EditSceneTerrain { scene_id, ref terrain } => {
         self
           .scenes
-          .mutate(&scene_id, move |s| s.terrain = terrain.clone())
+          .mutate(&scene_id, move |s| s.terrain = Arc::new(terrain.clone()))
           .ok_or_else(|| GameError::SceneNotFound(scene_id))?;
       }
       EditSceneHighlights { scene_id, ref highlights } => {
         self
           .scenes
-          .mutate(&scene_id, move |s| s.highlights = highlights.clone())
+          .mutate(&scene_id, move |s| s.highlights = Arc::new(highlights.clone()))
           .ok_or_else(|| GameError::SceneNotFound(scene_id))?;
       }
       EditSceneAnnotations { scene_id, ref annotations } => {
         self
           .scenes
-          .mutate(&scene_id, move |s| s.annotations = annotations.clone())
+          .mutate(&scene_id, move |s| s.annotations = Arc::new(annotations.clone()))
           .ok_or_else(|| GameError::SceneNotFound(scene_id))?;
       }
       EditSceneRelatedScenes { scene_id, ref related_scenes } => {
@@ -912,6 +927,7 @@ impl Game {
           c.bio = details.bio.clone();
           c.initiative = details.initiative.clone();
           c.size = details.size;
+          c.sight = details.sight;
         });
         mutated.ok_or_else(|| GameError::CreatureNotFound(creature_id.to_string()))?;
       }
@@ -974,11 +990,11 @@ impl Game {
       }
 
       // Things that are handled at the App level
-      Rollback(..) => {
+      Rollback(..) | SwitchBranch(..) | Undo | Redo | EditLog { .. } => {
         return bug("GameLog Rollback");
       }
     }
-    Ok(())
+    self.explore_after(log)
   }
 
   pub fn check_creature_id(&self, cid: CreatureID) -> Result<(), GameError> {
@@ -1021,6 +1037,10 @@ impl Game {
     self.scenes.get(&id).ok_or_else(|| GameError::SceneNotFound(id).into())
   }
 
+  pub fn get_player(&self, id: &PlayerID) -> Result<&Player, GameError> {
+    self.players.get(id).ok_or_else(|| GameError::PlayerNotFound(id.clone()))
+  }
+
   pub fn get_combat(&self) -> Result<DynamicCombat, GameError> {
     let combat = self.current_combat.as_ref().ok_or(GameError::NotInCombat)?;
     let scene = self.get_scene(combat.scene)?;
@@ -1029,22 +1049,26 @@ impl Game {
 
   // ** CONSIDER ** moving this chunk of code to... Scene.rs?
 
-  fn combat_act(&self, abid: AbilityID, target: DecidedTarget) -> Result<ChangedGame, GameError> {
+  fn combat_act(
+    &self, abid: AbilityID, target: DecidedTarget, rng: &mut dyn RngCore,
+  ) -> Result<ChangedGame, GameError> {
     let combat = self.get_combat()?;
     let scene = combat.scene;
     let actor = combat.combat.current_creature_id();
-    self._act(scene, actor, abid, target, true)
+    self._act(scene, actor, abid, target, true, rng)
   }
 
   fn ooc_act(
     &self, scene: SceneID, cid: CreatureID, abid: AbilityID, target: DecidedTarget,
+    rng: &mut dyn RngCore,
   ) -> Result<ChangedGame, GameError> {
     let scene = self.get_scene(scene)?;
-    self._act(scene, cid, abid, target, false)
+    self._act(scene, cid, abid, target, false, rng)
   }
 
   fn _act(
     &self, scene: &Scene, cid: CreatureID, abid: AbilityID, target: DecidedTarget, in_combat: bool,
+    rng: &mut dyn RngCore,
   ) -> Result<ChangedGame, GameError> {
     if !scene.creatures.contains_key(&cid) {
       bail!(GameError::CreatureNotFound(cid.to_string()));
@@ -1059,6 +1083,7 @@ impl Game {
           target,
           self.change(),
           in_combat,
+          rng,
         )
       } else {
         Err(GameError::CreatureLacksAbility(creature.id(), abid).into())
@@ -1070,13 +1095,13 @@ impl Game {
 
   pub fn creature_act(
     &self, creature: &DynamicCreature, scene: &Scene, ability: &Ability, target: DecidedTarget,
-    mut change: ChangedGame, in_combat: bool,
+    mut change: ChangedGame, in_combat: bool, rng: &mut dyn RngCore,
   ) -> Result<ChangedGame, GameError> {
     let mut change = match ability.action {
       Action::Creature { ref effect, target: tspec } => {
         let targets = self.resolve_creature_targets(creature, scene, tspec, target)?;
         for creature_id in &targets {
-          change = change.apply_creature(*creature_id, |c| c.apply_effect(effect))?;
+          change = change.apply_creature(*creature_id, |c| c.apply_effect(effect, &mut *rng))?;
         }
         change
       }
@@ -1084,10 +1109,10 @@ impl Game {
         match (effect, tspec, target) {
           (
             &SceneEffect::CreateVolumeCondition { duration, ref condition },
-            SceneTarget::RangedVolume { volume, .. },
-            // TODO: unimplemented!: honor and check `range`
+            SceneTarget::RangedVolume { volume, range },
             DecidedTarget::Point(point),
           ) => {
+            self.check_target_point(scene, creature.id(), range, point)?;
             let log = GameLog::AddVolumeCondition {
               condition_id: ConditionID::gen(),
               scene_id: scene.id,
@@ -1138,6 +1163,32 @@ impl Game {
         }
       }
       (CreatureTarget::Actor, DecidedTarget::Actor) => Ok(vec![creature.id()]),
+      (
+        CreatureTarget::SomeCreaturesInVolumeInRange { volume, maximum, range, propagation },
+        DecidedTarget::Creatures(cids),
+      ) => {
+        let unique: HashSet<&CreatureID> = HashSet::from_iter(&cids);
+        if cids.is_empty() || unique.len() != cids.len() {
+          bail!(GameError::InvalidTargetForTargetSpec(target, DecidedTarget::Creatures(cids)));
+        }
+        if cids.len() > maximum as usize {
+          bail!(GameError::TooManyTargets(maximum));
+        }
+        let placements =
+          self.volume_placements(scene, creature.id(), volume, range, propagation)?;
+        let fits = |candidates: &Vec<CreatureID>| cids.iter().all(|cid| candidates.contains(cid));
+        if placements.iter().any(|(_, candidates)| fits(candidates)) {
+          return Ok(cids);
+        }
+        // Be specific if one of the creatures can't be reached at all, rather than just not along
+        // with the others.
+        for cid in &cids {
+          if !placements.iter().any(|(_, candidates)| candidates.contains(cid)) {
+            bail!(GameError::CreatureOutOfRange(*cid));
+          }
+        }
+        Err(GameError::InvalidTargetForTargetSpec(target, DecidedTarget::Creatures(cids)))
+      }
       (_, DecidedTarget::Point(pt)) => {
         self.volume_creature_targets(scene, creature.creature.id, target, pt)
       }
@@ -1145,22 +1196,21 @@ impl Game {
     }
   }
 
-  // TODO: unimplemented! Honor terrain!
-  // 1. `pt` must be visible to the caster
-  // 2. volumes must not go through blocked terrain
-  // 3. volumes must (generally) not go around corners
+  /// The creatures hit by an ability targeted at a point. Solid terrain shields creatures from the
+  /// volume's origin (see `Scene::creatures_in_volume`).
   fn volume_creature_targets(
     &self, scene: &Scene, actor_id: CreatureID, target: CreatureTarget, pt: Point3,
   ) -> Result<Vec<CreatureID>, GameError> {
     match target {
-      CreatureTarget::AllCreaturesInVolumeInRange { volume, .. } => {
-        // TODO: unimplemented! honor and check `range`
-        Ok(scene.creatures_in_volume(self.tile_system, pt, volume))
+      CreatureTarget::AllCreaturesInVolumeInRange { volume, range, propagation } => {
+        self.check_target_point(scene, actor_id, range, pt)?;
+        Ok(scene.creatures_in_volume(self.tile_system, pt, volume, propagation))
       }
       CreatureTarget::LineFromActor { distance } => {
         let actor_pos = scene.get_pos(actor_id)?;
         let volume = line_through_point(actor_pos, pt, distance);
-        let cids = scene.creatures_in_volume(self.tile_system, actor_pos, volume);
+        let cids =
+          scene.creatures_in_volume(self.tile_system, actor_pos, volume, Propagation::Burst);
         // TODO: *ideally* we should start the line adjacent to the caster, but filtering out
         // also works.
         let cids = cids.into_iter().filter(|cid| *cid != actor_id).collect();
@@ -1170,6 +1220,25 @@ impl Game {
     }
   }
 
+  /// Check that a point being targeted by a creature is open terrain within `range` of it, and that
+  /// it can see the point.
+  fn check_target_point(
+    &self, scene: &Scene, actor_id: CreatureID, range: u32units::Length, pt: Point3,
+  ) -> Result<(), GameError> {
+    let actor_pos = scene.get_pos(actor_id)?;
+    if !self.tile_system.points_within_distance(actor_pos, pt, range) {
+      bail!(GameError::PointOutOfRange(pt));
+    }
+    // Line of sight doesn't look at the point itself, so a wall could be targeted otherwise.
+    if !scene.terrain.contains(&pt) {
+      bail!(GameError::TargetNotOpenTerrain(pt));
+    }
+    if !self.tile_system.line_of_sight(&scene.terrain, actor_pos, pt) {
+      bail!(GameError::PointNotVisible(pt));
+    }
+    Ok(())
+  }
+
   /// Calculate which *points* and which *creatures* will be affected by an ability targeted at a
   /// point.
   pub fn preview_volume_targets(
@@ -1178,26 +1247,38 @@ impl Game {
     let ability = self.get_ability(ability_id)?;
 
     let cids = match ability.action {
+      Action::Creature {
+        target: CreatureTarget::SomeCreaturesInVolumeInRange { volume, range, propagation, .. },
+        ..
+      } => {
+        // These are the creatures that can be chosen from, not necessarily the ones that'll be hit.
+        self.check_target_point(scene, actor_id, range, pt)?;
+        scene.creatures_in_volume(self.tile_system, pt, volume, propagation)
+      }
       Action::Creature { target, .. } => {
         self.volume_creature_targets(scene, actor_id, target, pt)?
       }
-      Action::SceneVolume { target: SceneTarget::RangedVolume { volume, .. }, .. } => {
-        scene.creatures_in_volume(self.tile_system, pt, volume)
+      Action::SceneVolume { target: SceneTarget::RangedVolume { volume, range }, .. } => {
+        self.check_target_point(scene, actor_id, range, pt)?;
+        scene.creatures_in_volume(self.tile_system, pt, volume, Propagation::Burst)
       }
     };
     let tiles = match ability.action {
       Action::Creature {
-        target: CreatureTarget::AllCreaturesInVolumeInRange { volume, .. },
+        target: CreatureTarget::AllCreaturesInVolumeInRange { volume, propagation, .. },
         ..
       }
-      | Action::SceneVolume { target: SceneTarget::RangedVolume { volume, .. }, .. } => {
-        // TODO: unimplemented! honor and check `range`
-        scene.open_terrain_in_volume(self, pt, volume)?
+      | Action::Creature {
+        target: CreatureTarget::SomeCreaturesInVolumeInRange { volume, propagation, .. },
+        ..
+      } => scene.open_terrain_in_volume(self, pt, volume, propagation)?,
+      Action::SceneVolume { target: SceneTarget::RangedVolume { volume, .. }, .. } => {
+        scene.open_terrain_in_volume(self, pt, volume, Propagation::Burst)?
       }
       Action::Creature { target: CreatureTarget::LineFromActor { distance }, .. } => {
         let actor_pos = scene.get_pos(actor_id)?;
         let volume = line_through_point(actor_pos, pt, distance);
-        scene.open_terrain_in_volume(self, actor_pos, volume)?
+        scene.open_terrain_in_volume(self, actor_pos, volume, Propagation::Burst)?
       }
       _ => vec![],
     };
@@ -1244,8 +1325,18 @@ impl Game {
       A::Creature { target: CT::LineFromActor { distance }, .. } => {
         self.open_terrain_in_range(scene, creature_id, distance)?
       }
-      A::Creature { target: CT::SomeCreaturesInVolumeInRange { .. }, .. } => {
-        unimplemented!("SomeCreaturesInVolumeInRange not implemented")
+      A::Creature {
+        target: CT::SomeCreaturesInVolumeInRange { volume, range, propagation, .. },
+        ..
+      } => {
+        let scene = self.get_scene(scene)?;
+        PotentialTargets::VolumePlacements(self.volume_placements(
+          scene,
+          creature_id,
+          volume,
+          range,
+          propagation,
+        )?)
       }
     })
   }
@@ -1254,9 +1345,36 @@ impl Game {
     &self, scene: SceneID, creature_id: CreatureID, range: u32units::Length,
   ) -> Result<PotentialTargets, GameError> {
     let scene = self.get_scene(scene)?;
+    Ok(PotentialTargets::Points(self.targetable_points(scene, creature_id, range)?))
+  }
+
+  /// The points of open terrain within `range` of a creature that it can see.
+  fn targetable_points(
+    &self, scene: &Scene, creature_id: CreatureID, range: u32units::Length,
+  ) -> Result<Vec<Point3>, GameError> {
     let creature_pos = scene.get_pos(creature_id)?;
-    let pts = self.tile_system.open_points_in_range(creature_pos, &scene.terrain, range);
-    Ok(PotentialTargets::Points(pts))
+    // open_points_in_range gives us a square, but the corners of it can be out of range.
+    Ok(
+      self
+        .tile_system
+        .open_points_in_range(creature_pos, &scene.terrain, range)
+        .into_iter()
+        .filter(|pt| self.tile_system.points_within_distance(creature_pos, *pt, range))
+        .filter(|pt| self.tile_system.line_of_sight(&scene.terrain, creature_pos, *pt))
+        .collect(),
+    )
+  }
+
+  /// The places a creature can put the volume of a `SomeCreaturesInVolumeInRange` ability, each
+  /// with the creatures that could be chosen from there. Places with nobody in them are left out.
+  fn volume_placements(
+    &self, scene: &Scene, creature_id: CreatureID, volume: Volume, range: u32units::Length,
+    propagation: Propagation,
+  ) -> Result<Vec<(Point3, Vec<CreatureID>)>, GameError> {
+    let pts = self.targetable_points(scene, creature_id, range)?;
+    let placements =
+      scene.creatures_in_volume_at_points(self.tile_system, &pts, volume, propagation);
+    Ok(placements.into_iter().filter(|(_, cids)| !cids.is_empty()).collect())
   }
 
   fn creatures_in_range(
@@ -1295,9 +1413,7 @@ pub struct ChangedGame {
 
 impl ChangedGame {
   pub fn apply(&self, log: &GameLog) -> Result<ChangedGame, GameError> {
-    let mut new = self.clone();
-    new.game = self.game.apply_log(log)?;
-    Ok(new)
+    Ok(ChangedGame { game: self.game.apply_log(log)?, logs: self.logs.clone() })
   }
 
   pub fn apply_combat<'game, F>(&'game self, f: F) -> Result<ChangedGame, GameError>
@@ -1333,20 +1449,24 @@ fn bug<T>(msg: &str) -> Result<T, GameError> {
   Err(GameError::BuggyProgram(msg.to_string()).into())
 }
 
-pub fn load_app_from_path(
-  saved_game_path: &Path, module_path: Option<&Path>, source: ModuleSource, filename: &str,
-) -> Result<App, GameError> {
-  let filename = match (source, module_path) {
-    (ModuleSource::Module, Some(module_path)) => module_path.join(filename),
-    (ModuleSource::Module, None) => return Err(GameError::NoModuleSource),
-    (ModuleSource::SavedGame, _) => saved_game_path.join(filename),
-  };
-  let mut appf = File::open(filename.clone())
-    .map_err(|e| GameError::CouldNotOpenAppFile(filename.to_string_lossy().into(), e))?;
-  let mut apps = String::new();
-  appf.read_to_string(&mut apps).unwrap();
-  let app: App = serde_yaml::from_str(&apps).map_err(|e| GameError::CouldNotParseApp(e))?;
+/// Load an app from `storage`. Saved games have whatever was in their journal (if the storage
+/// keeps one) replayed on top of them, and old rollbacks in their history migrated (see
+/// `App::migrate_legacy_rollbacks`).
+pub fn load_app(storage: &dyn Storage, source: ModuleSource, name: &str) -> Result<App, GameError> {
+  let apps = storage.load(source, name)?;
+  let mut app: App = serde_yaml::from_str(&apps).map_err(|e| GameError::CouldNotParseApp(e))?;
+  app.migrate_legacy_rollbacks()?;
   app.current_game.validate_campaign()?;
+  if let (ModuleSource::SavedGame, Some(journal_path)) = (source, storage.journal_path(name)) {
+    let journal = journal::recover(&journal_path)?;
+    if journal.journal_id == app.journal_id {
+      app.replay_journal(journal.entries)?;
+      app.journal_path = Some(journal_path);
+    } else {
+      // This journal is from before the game was last saved, so everything in it is in the game.
+      app.start_journal(journal_path)?;
+    }
+  }
   Ok(app)
 }
 
@@ -1354,12 +1474,12 @@ pub fn load_app_from_path(
 pub mod test {
   use std::collections::HashSet;
   use std::iter::FromIterator;
-  use std::path::PathBuf;
 
   use maplit::hashset;
 
   use crate::combat::test::*;
   use crate::game::*;
+  use crate::grid::test::huge_box;
   use crate::types::test::*;
   use indexed::IndexedHashMap;
 
@@ -1408,7 +1528,7 @@ pub mod test {
   pub fn t_classes() -> IndexedHashMap<Class> {
     let rogue_abs = vec![abid_punch()];
     let ranger_abs = vec![abid_shoot(), abid_piercing_shot()];
-    let cleric_abs = vec![abid_heal(), abid_fireball()];
+    let cleric_abs = vec![abid_heal(), abid_fireball(), abid_thorn_patch(), abid_ball_lightning()];
     IndexedHashMap::from_iter(vec![
       Class {
         id: classid_rogue(),
@@ -1435,7 +1555,7 @@ pub mod test {
   }
 
   pub fn perf(game: &Game, cmd: GameCommand) -> Result<ChangedGame, GameError> {
-    game.perform_command(cmd, &PathBuf::from(""), None)
+    game.perform_command(cmd, &mut t_rng(), &t_storage())
   }
 
   pub fn t_perform(game: &Game, cmd: GameCommand) -> Game { perf(game, cmd).unwrap().game }
@@ -1446,8 +1566,8 @@ pub mod test {
     let non = CreatureID::gen();
     let result = game.perform_command(
       GameCommand::StartCombat(t_scene_id(), vec![non]),
-      &PathBuf::from(""),
-      None,
+      &mut t_rng(),
+      &t_storage(),
     );
     match result {
       Err(GameError::CreatureNotFound(id)) => assert_eq!(id, non.to_string()),
@@ -1460,8 +1580,8 @@ pub mod test {
     let game = t_game();
     let result = game.perform_command(
       GameCommand::StartCombat(t_scene_id(), vec![]),
-      &PathBuf::from(""),
-      None,
+      &mut t_rng(),
+      &t_storage(),
     );
     match result {
       Err(GameError::CombatMustHaveCreatures) => {}
@@ -1487,6 +1607,26 @@ pub mod test {
     t_perform(&game, GameCommand::PathCurrentCombatCreature(Point3::new(100, 0, 0)));
   }
 
+  #[test]
+  fn edit_creature_sight() {
+    let game = t_game();
+    let ranger = game.get_creature(cid_ranger()).unwrap().creature.clone();
+    let details = CreatureCreation {
+      name: ranger.name.clone(),
+      class: ranger.class,
+      portrait_url: ranger.portrait_url.clone(),
+      icon_url: ranger.icon_url.clone(),
+      note: ranger.note.clone(),
+      bio: ranger.bio.clone(),
+      initiative: ranger.initiative.clone(),
+      size: ranger.size,
+      sight: u32cm(2000),
+    };
+    let game =
+      t_perform(&game, GameCommand::EditCreatureDetails { creature_id: cid_ranger(), details });
+    assert_eq!(game.get_creature(cid_ranger()).unwrap().creature.sight, u32cm(2000));
+  }
+
   #[test]
   fn change_creature_initiative() {
     let game = t_combat();
@@ -1542,6 +1682,234 @@ pub mod test {
     assert_eq!(game.get_creature(cid_cleric()).unwrap().creature.cur_health, HP(10));
   }
 
+  #[test]
+  fn volume_abilities_must_be_targeted_in_range() {
+    let game = t_game();
+    let act = |ability_id, pt| {
+      perf(
+        &game,
+        GameCommand::ActCreature(t_scene_id(), cid_cleric(), ability_id, DecidedTarget::Point(pt)),
+      )
+    };
+    // fireball has a range of 20 meters, and thorn patch has a range of 10.
+    let far = Point3::new(2100, 0, 0);
+    match act(abid_fireball(), far) {
+      Err(GameError::PointOutOfRange(pt)) => assert_eq!(pt, far),
+      x => panic!("Unexpected result: {:?}", x),
+    }
+    let far = Point3::new(800, 800, 0);
+    match act(abid_thorn_patch(), far) {
+      Err(GameError::PointOutOfRange(pt)) => assert_eq!(pt, far),
+      x => panic!("Unexpected result: {:?}", x),
+    }
+    let scene = game.get_scene(t_scene_id()).unwrap();
+    match game.preview_volume_targets(scene, cid_cleric(), abid_thorn_patch(), far) {
+      Err(GameError::PointOutOfRange(pt)) => assert_eq!(pt, far),
+      x => panic!("Unexpected result: {:?}", x),
+    }
+    act(abid_thorn_patch(), Point3::new(700, 700, 0)).unwrap();
+  }
+
+  /// Every point offered as a target for a volume ability is within its range.
+  #[test]
+  fn target_options_are_in_range() {
+    let game = t_game();
+    let scene = game.get_scene(t_scene_id()).unwrap();
+    let pts = match game.get_target_options(t_scene_id(), cid_cleric(), abid_thorn_patch()) {
+      Ok(PotentialTargets::Points(pts)) => pts,
+      x => panic!("Unexpected result: {:?}", x),
+    };
+    assert!(pts.contains(&Point3::new(700, 700, 0)));
+    assert!(!pts.contains(&Point3::new(800, 800, 0)));
+    let cleric_pos = scene.get_pos(cid_cleric()).unwrap();
+    for pt in pts {
+      assert!(game.tile_system.points_within_distance(cleric_pos, pt, u32cm(1000)));
+    }
+  }
+
+  /// Walls shield creatures from area effects, and can't be targeted through.
+  #[test]
+  fn walls_block_volume_abilities() {
+    let mut terrain = huge_box();
+    for y in -20..20 {
+      terrain.0.remove(&Point3::new(200, y * 100, 0));
+    }
+    let game =
+      t_perform(&t_game(), GameCommand::EditSceneTerrain { scene_id: t_scene_id(), terrain });
+    let game = t_perform(
+      &game,
+      GameCommand::SetCreaturePos(t_scene_id(), cid_rogue(), Point3::new(300, 0, 0)),
+    );
+    let fireball = |game: &Game, pt| {
+      perf(
+        game,
+        GameCommand::ActCreature(
+          t_scene_id(),
+          cid_cleric(),
+          abid_fireball(),
+          DecidedTarget::Point(pt),
+        ),
+      )
+    };
+
+    let scene = game.get_scene(t_scene_id()).unwrap();
+    let (cids, tiles) = game
+      .preview_volume_targets(scene, cid_cleric(), abid_fireball(), Point3::new(0, 0, 0))
+      .unwrap();
+    assert_eq!(HashSet::<CreatureID>::from_iter(cids), hashset! {cid_cleric(), cid_ranger()});
+    assert!(tiles.contains(&Point3::new(100, 0, 0)));
+    assert!(!tiles.iter().any(|pt| pt.x > i64cm(200)));
+
+    let game2 = fireball(&game, Point3::new(0, 0, 0)).unwrap().game;
+    assert_eq!(game2.get_creature(cid_rogue()).unwrap().creature.cur_health, HP(10));
+    assert_eq!(game2.get_creature(cid_ranger()).unwrap().creature.cur_health, HP(7));
+
+    match fireball(&game, Point3::new(400, 0, 0)) {
+      Err(GameError::PointNotVisible(pt)) => assert_eq!(pt, Point3::new(400, 0, 0)),
+      x => panic!("Unexpected result: {:?}", x),
+    }
+
+    // A volume centred on the wall itself would reach both sides of it.
+    let wall = Point3::new(200, 0, 0);
+    match fireball(&game, wall) {
+      Err(GameError::TargetNotOpenTerrain(pt)) => assert_eq!(pt, wall),
+      x => panic!("Unexpected result: {:?}", x),
+    }
+    match game.preview_volume_targets(scene, cid_cleric(), abid_fireball(), wall) {
+      Err(GameError::TargetNotOpenTerrain(pt)) => assert_eq!(pt, wall),
+      x => panic!("Unexpected result: {:?}", x),
+    }
+  }
+
+  /// Wrap-around area effects flow around walls to reach creatures that a burst wouldn't.
+  #[test]
+  fn wrap_around_volume_abilities() {
+    let mut terrain = huge_box();
+    for y in -20..1 {
+      terrain.0.remove(&Point3::new(200, y * 100, 0));
+    }
+    let mut game =
+      t_perform(&t_game(), GameCommand::EditSceneTerrain { scene_id: t_scene_id(), terrain });
+    let mut fireball = t_fireball();
+    if let Action::Creature {
+      target: CreatureTarget::AllCreaturesInVolumeInRange { ref mut propagation, .. },
+      ..
+    } = fireball.action
+    {
+      *propagation = Propagation::WrapAround;
+    }
+    game.abilities.insert(fireball);
+    let game = t_perform(
+      &game,
+      GameCommand::SetCreaturePos(t_scene_id(), cid_rogue(), Point3::new(300, 0, 0)),
+    );
+
+    let scene = game.get_scene(t_scene_id()).unwrap();
+    let (cids, tiles) = game
+      .preview_volume_targets(scene, cid_cleric(), abid_fireball(), Point3::new(0, 0, 0))
+      .unwrap();
+    assert_eq!(
+      HashSet::<CreatureID>::from_iter(cids),
+      hashset! {cid_cleric(), cid_ranger(), cid_rogue()}
+    );
+    assert!(tiles.contains(&Point3::new(300, 0, 0)));
+    assert!(!tiles.contains(&Point3::new(200, 0, 0)));
+
+    let game = t_perform(
+      &game,
+      GameCommand::ActCreature(
+        t_scene_id(),
+        cid_cleric(),
+        abid_fireball(),
+        DecidedTarget::Point(Point3::new(0, 0, 0)),
+      ),
+    );
+    assert_eq!(game.get_creature(cid_rogue()).unwrap().creature.cur_health, HP(7));
+  }
+
+  /// Ball lightning can be put anywhere within 10 meters, and hits up to two creatures within 2
+  /// meters of where it's put.
+  #[test]
+  fn some_creatures_target_options() {
+    let game = t_game();
+    let game = t_perform(
+      &game,
+      GameCommand::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(1300, 0, 0)),
+    );
+    let placements =
+      match game.get_target_options(t_scene_id(), cid_cleric(), abid_ball_lightning()) {
+        Ok(PotentialTargets::VolumePlacements(placements)) => placements,
+        x => panic!("Unexpected result: {:?}", x),
+      };
+    assert!(!placements.is_empty());
+    for (pt, cids) in placements {
+      assert!(pt.x <= i64cm(1000));
+      assert_eq!(HashSet::<CreatureID>::from_iter(cids), hashset! {cid_rogue(), cid_cleric()});
+    }
+  }
+
+  #[test]
+  fn some_creatures_only_hits_chosen() {
+    let game = t_game();
+    let game = t_perform(
+      &game,
+      GameCommand::ActCreature(
+        t_scene_id(),
+        cid_cleric(),
+        abid_ball_lightning(),
+        DecidedTarget::Creatures(vec![cid_rogue(), cid_ranger()]),
+      ),
+    );
+    assert_eq!(game.get_creature(cid_rogue()).unwrap().creature.cur_health, HP(7));
+    assert_eq!(game.get_creature(cid_ranger()).unwrap().creature.cur_health, HP(7));
+    assert_eq!(game.get_creature(cid_cleric()).unwrap().creature.cur_health, HP(10));
+  }
+
+  #[test]
+  fn some_creatures_validates_choice() {
+    let game = t_game();
+    let act = |game: &Game, cids| {
+      perf(
+        game,
+        GameCommand::ActCreature(
+          t_scene_id(),
+          cid_cleric(),
+          abid_ball_lightning(),
+          DecidedTarget::Creatures(cids),
+        ),
+      )
+    };
+    match act(&game, vec![cid_rogue(), cid_ranger(), cid_cleric()]) {
+      Err(GameError::TooManyTargets(2)) => {}
+      x => panic!("Unexpected result: {:?}", x),
+    }
+    match act(&game, vec![cid_rogue(), cid_rogue()]) {
+      Err(GameError::InvalidTargetForTargetSpec(..)) => {}
+      x => panic!("Unexpected result: {:?}", x),
+    }
+
+    // The ranger is too far away for the ball to reach them.
+    let game = t_perform(
+      &game,
+      GameCommand::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(1300, 0, 0)),
+    );
+    match act(&game, vec![cid_ranger()]) {
+      Err(GameError::CreatureOutOfRange(cid)) => assert_eq!(cid, cid_ranger()),
+      x => panic!("Unexpected result: {:?}", x),
+    }
+
+    // The ranger can be reached, but not by the same ball as the rogue.
+    let game = t_perform(
+      &game,
+      GameCommand::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(900, 0, 0)),
+    );
+    act(&game, vec![cid_ranger()]).unwrap();
+    match act(&game, vec![cid_rogue(), cid_ranger()]) {
+      Err(GameError::InvalidTargetForTargetSpec(..)) => {}
+      x => panic!("Unexpected result: {:?}", x),
+    }
+  }
+
   #[test]
   fn test_creatures_in_sphere() {
     let game = t_game();
@@ -1558,7 +1926,7 @@ pub mod test {
     );
     let scene = game.get_scene(t_scene_id()).unwrap();
 
-    let cids = scene.creatures_in_volume(game.tile_system, pt, volume);
+    let cids = scene.creatures_in_volume(game.tile_system, pt, volume, Propagation::Burst);
     let cids = HashSet::<CreatureID>::from_iter(cids);
     assert_eq!(cids, HashSet::from_iter(vec![cid_rogue(), cid_cleric()]));
   }
@@ -1569,6 +1937,7 @@ pub mod test {
     let target_spec = CreatureTarget::AllCreaturesInVolumeInRange {
       range: u32cm(1000),
       volume: Volume::Sphere(u32cm(200)),
+      propagation: Propagation::Burst,
     };
     let pt = Point3::new(500, 0, 0);
 
@@ -16,24 +16,10 @@ use num_traits::Signed;
 use uom::si::length::{centimeter, meter};
 
 use crate::types::{
-  i64cm, i64meter, u32cm, u32units, up_length, CollisionData, CollisionWorld, ConditionID,
-  Creature, Point3, Terrain, TileSystem, Volume, VolumeCondition,
+  i64meter, u32cm, u32units, up_length, CollisionData, CollisionWorld, ConditionID, Creature,
+  Point3, Propagation, Terrain, TileSystem, Volume, VolumeCondition, AABB,
 };
 
-// unimplemented!: "burst"-style AoE effects, and "wrap-around-corner" AoE effects.
-// This needs to be implemented for both Spheres and Circles (or VerticalCylinder?)
-//
-// Grenades should be "burst"-style spheres. These basically "cast" the effect outward from the
-// center, and are blocked by any solid terrain. This could be implemented by raycasting from
-// the origin to every point within a particular radius of the origin.
-//
-// Abilities like D&D's "fireball" and P&T's "thorn patch" should be "wrap-around-corner" AoE
-// effects: Fireball is a sphere and Thorn Patch is a Circle or VerticalCylinder{height=1}.
-//
-// These "crawl" out from the origin point and can go anywhere within the radius that has a valid.
-// path. This could be implemented exactly the same way we implement potential walk-targets.
-// However, for 3d shapes this might get expensive...
-
 // I got curious about how to implement this in integer math.
 // the maximum distance on a grid of i16 positions (−32768 to 32767) is....?
 // √((x₂ - x₁)² + (y₂ - y₁)² + (z₂ - z₁)²)
@@ -84,6 +70,23 @@ pub fn point3_add_vec(pt: Point3, diff: Point3) -> Point3 {
 
 fn is_open(terrain: &Terrain, pt: Point3) -> bool { terrain.contains(&pt) }
 
+impl Terrain {
+  pub fn contains(&self, pt: &Point3) -> bool { self.0.contains(pt) }
+  pub fn iter(&self) -> ::std::collections::hash_set::Iter<'_, Point3> { self.0.iter() }
+  pub fn len(&self) -> usize { self.0.len() }
+  pub fn is_empty(&self) -> bool { self.0.is_empty() }
+}
+
+impl FromIterator<Point3> for Terrain {
+  fn from_iter<T: IntoIterator<Item = Point3>>(iter: T) -> Self {
+    Terrain(iter.into_iter().collect())
+  }
+}
+
+impl From<Vec<Point3>> for Terrain {
+  fn from(points: Vec<Point3>) -> Self { points.into_iter().collect() }
+}
+
 impl TileSystem {
   /// Get the distance between two points, considering the system being used.
   /// In DnD, an angular distance is "equivalent" to a horizontal/vertical distance.
@@ -110,40 +113,104 @@ impl TileSystem {
     self.point3_distance(c1, c2) <= d
   }
 
-  /// Garbage Function
+  /// Can something at `from` see `to`? This walks the squares on the line between them, on the
+  /// horizontal plane at `from`'s height, and any solid square in between blocks the view. The
+  /// squares at either end don't count, so a wall can be seen, but not what's behind it. A diagonal
+  /// step can't slip between two solid squares that touch at their corners.
+  pub fn line_of_sight(&self, terrain: &Terrain, from: Point3, to: Point3) -> bool {
+    let z = from.z.get::<meter>();
+    let start = (from.x.get::<meter>() as isize, from.y.get::<meter>() as isize);
+    let end = (to.x.get::<meter>() as isize, to.y.get::<meter>() as isize);
+    let is_open =
+      |x: isize, y: isize| terrain.contains(&Point3::new(x as i64 * 100, y as i64 * 100, z * 100));
+    // Bresenham doesn't include the end point, which is what we want.
+    let mut prev = start;
+    for (x, y) in bresenham::Bresenham::new(start, end) {
+      if (x, y) != start && !is_open(x, y) {
+        return false;
+      }
+      if x != prev.0 && y != prev.1 && !is_open(x, prev.1) && !is_open(prev.0, y) {
+        return false;
+      }
+      prev = (x, y);
+    }
+    // The last step onto the end point can still squeeze between two corners.
+    !(end.0 != prev.0 && end.1 != prev.1 && !is_open(end.0, prev.1) && !is_open(prev.0, end.1))
+  }
+
+  /// Find the points that an area effect reaches when its volume is put at `pt`. These are the
+  /// `points_in_volume` that solid terrain doesn't shield from `pt`, which depends on how the
+  /// effect propagates:
+  ///
+  /// - A `Burst` reaches the points that are in `line_of_sight` of `pt`.
+  /// - A `WrapAround` effect floods out from `pt` through open terrain, staying inside the volume
+  ///   and going no further than the volume's radius. Lines and boxes have no radius, so they can
+  ///   go as far as their shape does.
+  ///
+  /// Like `line_of_sight`, this works on the horizontal plane at `pt`'s height: a point above or
+  /// below that plane is reached if the point on the plane under or over it is.
+  pub fn points_reached(
+    &self, terrain: &Terrain, volume: Volume, propagation: Propagation, pt: Point3,
+  ) -> HashSet<Point3> {
+    let points = self.points_in_volume(volume, pt);
+    match propagation {
+      Propagation::Burst => {
+        points.into_iter().filter(|&point| self.line_of_sight(terrain, pt, point)).collect()
+      }
+      Propagation::WrapAround => {
+        let on_plane = |point: Point3| Point3::from_quantities(point.x, point.y, pt.z);
+        let plane: HashSet<Point3> = points.iter().map(|&point| on_plane(point)).collect();
+        let reach = match volume {
+          Volume::Sphere(radius) | Volume::VerticalCylinder { radius, .. } => radius,
+          // No path through the shape can be longer than a diagonal step to every point in it.
+          Volume::Line { .. } | Volume::AABB(..) => u32cm(plane.len() as u32 * 141),
+        };
+        let one_square = AABB { x: u32cm(100), y: u32cm(100), z: u32cm(100) };
+        let neighbors = self.point3_neighbors(terrain, Volume::AABB(one_square));
+        let neighbors_in_volume = |point| {
+          let mut found = neighbors(point);
+          found.retain(|(neighbor, _)| plane.contains(neighbor));
+          found
+        };
+        let reached = costs_within(pt, neighbors_in_volume, reach);
+        points.into_iter().filter(|&point| reached.contains_key(&on_plane(point))).collect()
+      }
+    }
+  }
+
+  /// Find the items whose positions are inside a volume placed at a point. An item is inside the
+  /// volume if its position is one of the `points_in_volume`.
   pub fn items_within_volume<I: Clone + Eq + Hash>(
     &self, volume: Volume, pt: Point3, items: &HashMap<I, Point3>,
   ) -> Vec<I> {
     // TODO: unimplemented! this doesn't support non-1x1 items
-    // TODO: this function is really dumb, and instead should probably work on a HashSet of Point3s,
-    // or maybe a HashMap<Point3, I>. And it should make use of points_in_volume.
-    let mut results = vec![];
-    match volume {
-      Volume::Sphere(radius) => {
-        for (item, item_pos) in items {
-          if self.point3_distance(pt, *item_pos) <= radius {
-            results.push(item.clone());
-          }
-        }
-      }
-      Volume::AABB(_) => unimplemented!("unimplemented: items_within_volume for AABB"),
-      Volume::Line { vector } => {
-        let dest = point3_add_vec(pt, vector);
-        let start = (pt.x.get::<meter>() as isize, pt.y.get::<meter>() as isize);
-        let end = (dest.x.get::<meter>() as isize, dest.y.get::<meter>() as isize);
-        let line_pts: HashSet<Point3> = HashSet::from_iter(
-          bresenham::Bresenham::new(start, end)
-            .map(|(x, y)| Point3::new(x as i64 * 100, y as i64 * 100, 0)),
-        );
-        for (item, item_pos) in items {
-          if line_pts.contains(item_pos) {
-            results.push(item.clone());
-          }
-        }
-      }
-      Volume::VerticalCylinder { .. } => unimplemented!("items_within_volume for VerticalCylinder"),
-    }
-    results
+    let points: HashSet<Point3> = HashSet::from_iter(self.points_in_volume(volume, pt));
+    items
+      .iter()
+      .filter(|(_, item_pos)| points.contains(item_pos))
+      .map(|(item, _)| item.clone())
+      .collect()
+  }
+
+  /// Find the items inside a volume for each of several places it could be put. This only works
+  /// out the volume's points once, so it's much cheaper than `items_within_volume` for each place.
+  pub fn items_within_volume_at_points<I: Clone + Eq + Hash>(
+    &self, volume: Volume, pts: &[Point3], items: &HashMap<I, Point3>,
+  ) -> Vec<(Point3, Vec<I>)> {
+    // Every volume covers the same shape of points wherever it's put.
+    let footprint: HashSet<Point3> =
+      HashSet::from_iter(self.points_in_volume(volume, Point3::new(0, 0, 0)));
+    pts
+      .iter()
+      .map(|&pt| {
+        let inside = items
+          .iter()
+          .filter(|(_, &item_pos)| footprint.contains(&point3_difference(item_pos, pt)))
+          .map(|(item, _)| item.clone())
+          .collect();
+        (pt, inside)
+      })
+      .collect()
   }
 
   pub fn open_points_in_range(
@@ -172,30 +239,9 @@ impl TileSystem {
   pub fn get_all_accessible(
     &self, start: Point3, terrain: &Terrain, volume: Volume, speed: u32units::Length,
   ) -> Vec<Point3> {
-    let points_to_check = self.open_points_in_range(start, terrain, speed);
-    let mut success_fns: Vec<Box<dyn Fn(&Point3) -> bool>> = vec![];
-    for pt in points_to_check {
-      if pt != start {
-        success_fns.push(Box::new(move |n: &Point3| *n == pt));
-      }
-    }
-    let mut final_points = vec![];
-    let path_result = astar_multi(
-      &start,
-      |n| self.point3_neighbors(terrain, volume, *n),
-      |n| self.point3_distance(start, *n),
-      speed,
-      success_fns,
-    );
-
-    for (path, cost) in path_result {
-      if cost <= speed {
-        // FIXME: we should NOT be checking cost here, instead astar_multi should support
-        // max distance.  (um, it does support max distance? )
-        final_points.push(*path.last().unwrap())
-      }
-    }
-    final_points
+    let mut costs = costs_within(start, self.point3_neighbors(terrain, volume), speed);
+    costs.remove(&start);
+    costs.keys().cloned().collect()
   }
 
   /// Find a path from some start point to some destination point. If one can be found, a Vec of
@@ -205,17 +251,27 @@ impl TileSystem {
     destination: Point3,
   ) -> Option<(Vec<Point3>, u32units::Length)> {
     let success = Box::new(move |n: &Point3| *n == destination);
+    let neighbors = self.point3_neighbors(terrain, volume);
     let result = astar_multi(
       &start,
-      |n| self.point3_neighbors(terrain, volume, *n),
-      |n| self.point3_distance(start, *n),
+      |n| neighbors(*n),
+      |n| self.min_movement_cost(*n, destination),
       speed,
       vec![success],
     );
-    if let Some((path, cost)) = result.into_iter().next() {
-      Some((path, cost))
-    } else {
-      None
+    result.into_iter().next()
+  }
+
+  /// The cost of moving between two points if there were nothing in the way. This never
+  /// overestimates the cost of a real path, so it's the A* heuristic for `find_path`.
+  fn min_movement_cost(&self, pt1: Point3, pt2: Point3) -> u32units::Length {
+    let xdiff = (pt1.x - pt2.x).abs().get::<centimeter>() as u32;
+    let ydiff = (pt1.y - pt2.y).abs().get::<centimeter>() as u32;
+    let (short, long) = (cmp::min(xdiff, ydiff), cmp::max(xdiff, ydiff));
+    // These match the costs of moving to a neighbor in `point3_neighbors`.
+    match *self {
+      TileSystem::Realistic => u32cm(short * 141 / 100 + (long - short)),
+      TileSystem::DnD => u32cm(long * 99 / 100),
     }
   }
 
@@ -225,47 +281,70 @@ impl TileSystem {
   /// Sphere: center
   /// Line: origin
   /// VerticalCylinder: center of base
-  // TODO FIXME XXX: Implement for more volume types.
+  ///
+  /// Distances are measured the same way as `point3_distance`, so in DnD a sphere is a cube and a
+  /// cylinder is a square prism.
   // TODO: Should this use ncollide?
   fn points_in_volume(&self, volume: Volume, pt: Point3) -> Vec<Point3> {
+    // sadly uom doesn't implement Step for Quantity
+    let (x, y, z) = (pt.x.get::<meter>(), pt.y.get::<meter>(), pt.z.get::<meter>());
+    let grid_point = |x: i64, y: i64, z: i64| Point3::new(x * 100, y * 100, z * 100);
     match volume {
-      Volume::Sphere(..) => {
-        unimplemented!("unimplemented: points_in_volume for Sphere");
+      Volume::Sphere(radius) => {
+        let r = i64::from(radius.get::<meter>());
+        let mut points = vec![];
+        for px in x - r..=x + r {
+          for py in y - r..=y + r {
+            for pz in z - r..=z + r {
+              let candidate = grid_point(px, py, pz);
+              if self.point3_distance(pt, candidate) <= radius {
+                points.push(candidate);
+              }
+            }
+          }
+        }
+        points
       }
-      // sadly uom doesn't implement Step for Quantity
       Volume::AABB(aabb) => {
         let max = aabb.get_max(pt);
-        (pt.x.get::<meter>()..(max.x.get::<meter>()))
-          .flat_map(|x| {
-            (pt.y.get::<meter>()..(max.y.get::<meter>())).flat_map(move |y| {
-              (pt.z.get::<meter>()..(max.z.get::<meter>()))
-                .map(move |z| Point3::new(x * 100, y * 100, z * 100))
-            })
+        (x..max.x.get::<meter>())
+          .flat_map(|px| {
+            (y..max.y.get::<meter>())
+              .flat_map(move |py| (z..max.z.get::<meter>()).map(move |pz| grid_point(px, py, pz)))
           })
           .collect()
       }
-      Volume::Line { .. } => unimplemented!("points_in_volume for Line"),
-      Volume::VerticalCylinder { .. } => {
-        unimplemented!("unimplemented: points_in_volume for VerticalCylinder")
+      Volume::Line { vector } => {
+        let dest = point3_add_vec(pt, vector);
+        let end = (dest.x.get::<meter>() as isize, dest.y.get::<meter>() as isize);
+        bresenham::Bresenham::new((x as isize, y as isize), end)
+          .map(|(px, py)| grid_point(px as i64, py as i64, z))
+          .collect()
       }
-    }
-  }
-
-  /// Determine whether a volume will not collide *with terrain* if it is placed at a point.
-  /// Note that this doesn't consider other creatures or other map objects.
-  fn volume_fits_at_point(&self, volume: Volume, terrain: &Terrain, pt: Point3) -> bool {
-    for pt in self.points_in_volume(volume, pt) {
-      if !terrain.contains(&pt) {
-        return false;
+      Volume::VerticalCylinder { radius, height } => {
+        let r = i64::from(radius.get::<meter>());
+        let height = i64::from(height.get::<meter>());
+        let mut points = vec![];
+        for px in x - r..=x + r {
+          for py in y - r..=y + r {
+            if self.point3_distance(pt, grid_point(px, py, z)) <= radius {
+              points.extend((z..z + height).map(|pz| grid_point(px, py, pz)));
+            }
+          }
+        }
+        points
       }
     }
-    true
   }
 
-  /// Find neighbors of the given point that the given volume can fit in, given the terrain.
-  fn point3_neighbors(
-    &self, terrain: &Terrain, volume: Volume, pt: Point3,
-  ) -> Vec<(Point3, u32units::Length)> {
+  /// Make a function that finds the neighbors of a point that the given volume can fit in, given
+  /// the terrain. Note that this doesn't consider other creatures or other map objects.
+  ///
+  /// Unit conversions are surprisingly slow, and this is called for every point a search visits,
+  /// so everything that doesn't depend on the point is worked out up front.
+  fn point3_neighbors<'a>(
+    &self, terrain: &'a Terrain, volume: Volume,
+  ) -> impl Fn(Point3) -> Vec<(Point3, u32units::Length)> + 'a {
     let diagonal_distance = match *self {
       TileSystem::Realistic => u32cm(141),
       TileSystem::DnD => u32cm(100),
@@ -279,30 +358,68 @@ impl TileSystem {
       // we get it to prefer to move straight.
       TileSystem::DnD => u32cm(99),
     };
-    let mut results = vec![];
-    for &x in [-100, 0, 100].into_iter() {
-      for &y in [-100, 0, 100].into_iter() {
+    let mut steps = vec![];
+    for &x in [-100, 0, 100].iter() {
+      for &y in [-100, 0, 100].iter() {
         if (x, y) == (0, 0) {
           continue;
         }
-        let neighbor = Point3::from_quantities(pt.x + i64cm(x), pt.y + i64cm(y), pt.z);
-        if is_open(terrain, neighbor) && self.volume_fits_at_point(volume, terrain, neighbor) {
-          let is_angle = x.abs() == y.abs(); // there's probably a cheaper way to do this
-          let cost = if is_angle { diagonal_distance } else { straight_distance };
-          // don't allow diagonal movement around corners
-          if is_angle && !is_open(terrain, Point3::from_quantities(neighbor.x, pt.y, pt.z))
-            || !is_open(terrain, Point3::from_quantities(pt.x, neighbor.y, pt.z))
-          {
-            continue;
-          }
-          results.push((neighbor, cost));
+        let is_angle = x != 0 && y != 0;
+        let cost = if is_angle { diagonal_distance } else { straight_distance };
+        steps.push((Point3::new(x, y, 0), is_angle, cost));
+      }
+    }
+    let footprint = self.points_in_volume(volume, Point3::new(0, 0, 0));
+    move |pt| {
+      let mut results = vec![];
+      for &(step, is_angle, cost) in &steps {
+        let neighbor = point3_add_vec(pt, step);
+        if !is_open(terrain, neighbor)
+          || !footprint.iter().all(|&offset| is_open(terrain, point3_add_vec(neighbor, offset)))
+        {
+          continue;
+        }
+        // don't allow diagonal movement around corners
+        if is_angle && !is_open(terrain, Point3::from_quantities(neighbor.x, pt.y, pt.z))
+          || !is_open(terrain, Point3::from_quantities(pt.x, neighbor.y, pt.z))
+        {
+          continue;
         }
+        results.push((neighbor, cost));
       }
+      results
     }
-    results
   }
 }
 
+/// A Dijkstra search outward from `start`, which finds the cheapest cost of getting to every point
+/// that can be reached for no more than `max_cost`.
+fn costs_within<FN>(
+  start: Point3, neighbors: FN, max_cost: u32units::Length,
+) -> HashMap<Point3, u32units::Length>
+where
+  FN: Fn(Point3) -> Vec<(Point3, u32units::Length)>,
+{
+  let mut costs = HashMap::new();
+  costs.insert(start, u32cm(0));
+  let mut to_see = BinaryHeap::new();
+  to_see.push(InvCmpHolder { key: u32cm(0), payload: start });
+  while let Some(InvCmpHolder { key: cost, payload: pt }) = to_see.pop() {
+    // A point can be pushed more than once if a cheaper way to it was found later.
+    if costs[&pt] < cost {
+      continue;
+    }
+    for (neighbor, move_cost) in neighbors(pt) {
+      let new_cost = cost + move_cost;
+      if new_cost <= max_cost && costs.get(&neighbor).iter().all(|&&old_cost| new_cost < old_cost) {
+        costs.insert(neighbor, new_cost);
+        to_see.push(InvCmpHolder { key: new_cost, payload: neighbor });
+      }
+    }
+  }
+  costs
+}
+
 /// Make a `CollisionWorld` given some creatures and volume conditions.
 /// The factoring is a little sad; this is the only function in grid.rs that knows about these P&T
 /// types. Maybe I should just move this to collision.rs or something.
@@ -369,19 +486,53 @@ where
   results
 }
 
+/// Make an ncollide shape for a volume, measured in meters like `na_iso`.
+///
+/// Each grid point stands for the 1-meter cell centered on it, so the shapes are placed to cover
+/// the same cells as `points_in_volume`: e.g. an AABB at a point starts half a meter before it.
 fn volume_to_na_shape(volume: Volume) -> shape::ShapeHandle<f64> {
+  let meters = |l: u32units::Length| f64::from(l.get::<centimeter>()) / 100.0;
   match volume {
-    Volume::Sphere(r) => shape::ShapeHandle::new(shape::Ball::new(r.get::<centimeter>() as f64)),
-    Volume::AABB(aabb) => shape::ShapeHandle::new(shape::Cuboid::new(Vector3::new(
-      (f64::from(aabb.x.get::<centimeter>())) / 2.0,
-      (f64::from(aabb.y.get::<centimeter>())) / 2.0,
-      (f64::from(aabb.z.get::<centimeter>())) / 2.0,
-    ))),
-    Volume::Line { .. } => unimplemented!("volume_to_na_shape for Line"),
-    Volume::VerticalCylinder { .. } => unimplemented!("volume_to_na_shape for VerticalCylinder"),
+    Volume::Sphere(r) => shape::ShapeHandle::new(shape::Ball::new(meters(r))),
+    Volume::AABB(aabb) => {
+      let (x, y, z) = (meters(aabb.x), meters(aabb.y), meters(aabb.z));
+      let mut corners = vec![];
+      for &cx in &[-0.5, x - 0.5] {
+        for &cy in &[-0.5, y - 0.5] {
+          for &cz in &[-0.5, z - 0.5] {
+            corners.push(na::Point3::new(cx, cy, cz));
+          }
+        }
+      }
+      na_convex_hull(&corners)
+    }
+    Volume::Line { vector } => {
+      shape::ShapeHandle::new(shape::Segment::new(na::Point3::origin(), na_point(vector)))
+    }
+    Volume::VerticalCylinder { radius, height } => {
+      // A 16-sided prism is close enough to a cylinder on a grid of 1-meter squares.
+      let (r, h) = (meters(radius), meters(height));
+      let mut outline = vec![];
+      for idx in 0..16 {
+        let angle = f64::from(idx) * ::std::f64::consts::PI / 8.0;
+        outline.push(na::Point3::new(r * angle.cos(), r * angle.sin(), -0.5));
+        outline.push(na::Point3::new(r * angle.cos(), r * angle.sin(), h - 0.5));
+      }
+      na_convex_hull(&outline)
+    }
   }
 }
 
+/// A convex hull around some points. Flat or empty volumes are given a tiny bit of thickness, since
+/// ncollide can't make a hull out of points that are all in a plane.
+fn na_convex_hull(points: &[na::Point3<f64>]) -> shape::ShapeHandle<f64> {
+  let nudges = [Vector3::zeros(), Vector3::x() * 0.001, Vector3::y() * 0.001, Vector3::z() * 0.001];
+  let padded: Vec<na::Point3<f64>> =
+    points.iter().flat_map(|pt| nudges.iter().map(move |nudge| pt + nudge)).collect();
+  let hull = shape::ConvexHull::try_from_points(&padded).expect("padded hull can't be degenerate");
+  shape::ShapeHandle::new(hull)
+}
+
 // FOLLOWING COPIED FROM PATHFINDING CRATE
 // ***************************************
 use num_traits::Zero;
@@ -482,10 +633,11 @@ pub mod test {
   use maplit::hashmap;
 
   use crate::grid::*;
+  use crate::types::test::*;
   use crate::types::*;
 
   /// A map containing a single open block of terrain at 0,0,0
-  fn box_map() -> Terrain { vec![Point3::new(0, 0, 0)] }
+  fn box_map() -> Terrain { vec![Point3::new(0, 0, 0)].into() }
 
   /// A map shaped like a dumbbell, with two 2x3 rooms connected by a 1x1 passage
   fn dumbbell_map() -> Terrain {
@@ -504,6 +656,7 @@ pub mod test {
       Point3::new(300, 200, 0),
       Point3::new(400, 200, 0),
     ]
+    .into()
   }
 
   /// A map containing a large 40-meter square of open terrain.
@@ -514,7 +667,26 @@ pub mod test {
         map.push(Point3::new(x * 100, y * 100, 0));
       }
     }
-    map
+    map.into()
+  }
+
+  /// A 5x3 room with a wall down the middle, which has a gap at the bottom:
+  ///
+  /// ```text
+  /// . . # . .
+  /// . . # . .
+  /// . . . . .
+  /// ```
+  fn walled_room() -> Terrain {
+    let mut map = vec![];
+    for x in 0..5 {
+      for y in 0..3 {
+        if x != 2 || y == 2 {
+          map.push(Point3::new(x * 100, y * 100, 0));
+        }
+      }
+    }
+    map.into()
   }
 
   fn medium_size() -> AABB { AABB { x: u32cm(100), y: u32cm(100), z: u32cm(100) } }
@@ -539,7 +711,7 @@ pub mod test {
   fn test_neighbors() {
     let terrain = huge_box();
     let size = Volume::AABB(medium_size());
-    let mut pts = TileSystem::Realistic.point3_neighbors(&terrain, size, Point3::new(0, 0, 0));
+    let mut pts = TileSystem::Realistic.point3_neighbors(&terrain, size)(Point3::new(0, 0, 0));
     pts.sort();
     let mut expected = vec![
       (Point3::new(-100, 0, 0), u32cm(100)),
@@ -558,13 +730,13 @@ pub mod test {
   /// a diagonal neighbor is not considered accessible if it "goes around" a blocked corner
   #[test]
   fn test_neighbors_around_corners() {
-    let terrain = vec![Point3::new(100, 0, 0)];
+    let terrain = vec![Point3::new(100, 0, 0)].into();
     let size = Volume::AABB(medium_size());
-    let pts: Vec<Point3> = TileSystem::Realistic
-      .point3_neighbors(&terrain, size, Point3::new(0, 0, 0))
-      .iter()
-      .map(|&(p, _)| p)
-      .collect();
+    let pts: Vec<Point3> =
+      TileSystem::Realistic.point3_neighbors(&terrain, size)(Point3::new(0, 0, 0))
+        .iter()
+        .map(|&(p, _)| p)
+        .collect();
     assert!(!pts.contains(&Point3::new(100, 100, 0)));
     assert!(!pts.contains(&Point3::new(100, -100, 0)));
   }
@@ -576,7 +748,7 @@ pub mod test {
     let size = Volume::AABB(medium_size());
     let paths_and_costs = astar_multi(
       &start,
-      |n| TileSystem::Realistic.point3_neighbors(&huge_box(), size, *n),
+      |n| TileSystem::Realistic.point3_neighbors(&huge_box(), size)(*n),
       |n| TileSystem::Realistic.point3_distance(start, *n),
       u32cm(u32::max_value() / 64 - 1), // FIXME this is a workaround for uom bug #55
       vec![success],
@@ -592,7 +764,7 @@ pub mod test {
     let size = Volume::AABB(medium_size());
     let result = astar_multi(
       &start,
-      |n| TileSystem::Realistic.point3_neighbors(&huge_box(), size, *n),
+      |n| TileSystem::Realistic.point3_neighbors(&huge_box(), size)(*n),
       |n| TileSystem::Realistic.point3_distance(start, *n),
       u32cm(499),
       vec![success],
@@ -609,7 +781,7 @@ pub mod test {
     let size = Volume::AABB(medium_size());
     let result = astar_multi(
       &start,
-      |n| TileSystem::Realistic.point3_neighbors(&huge_box(), size, *n),
+      |n| TileSystem::Realistic.point3_neighbors(&huge_box(), size)(*n),
       |n| TileSystem::Realistic.point3_distance(start, *n),
       u32cm(500),
       vec![success],
@@ -640,7 +812,7 @@ pub mod test {
     let size = Volume::AABB(medium_size());
     let paths_and_costs = astar_multi(
       &start,
-      |n| TileSystem::Realistic.point3_neighbors(&huge_box(), size, *n),
+      |n| TileSystem::Realistic.point3_neighbors(&huge_box(), size)(*n),
       |n| TileSystem::Realistic.point3_distance(start, *n),
       u32cm(u32::max_value() / 64 - 1), // FIXME this is a workaround for uom bug #55
       successes,
@@ -719,6 +891,67 @@ pub mod test {
     assert_eq!(pts.len(), 284);
   }
 
+  #[test]
+  fn line_of_sight() {
+    let ts = TileSystem::Realistic;
+    let terrain = walled_room();
+    let pt = |x: i64, y: i64| Point3::new(x * 100, y * 100, 0);
+    assert!(ts.line_of_sight(&terrain, pt(0, 0), pt(1, 1)));
+    assert!(!ts.line_of_sight(&terrain, pt(0, 0), pt(4, 0)));
+    assert!(!ts.line_of_sight(&terrain, pt(4, 1), pt(0, 0)));
+    assert!(ts.line_of_sight(&terrain, pt(0, 2), pt(4, 2)));
+    // The wall itself can be seen, just not past it.
+    assert!(ts.line_of_sight(&terrain, pt(0, 0), pt(2, 0)));
+  }
+
+  /// Two solid squares that only touch at their corners still block the view between them.
+  #[test]
+  fn line_of_sight_between_corners() {
+    let ts = TileSystem::Realistic;
+    let pt = |x: i64, y: i64| Point3::new(x * 100, y * 100, 0);
+    let terrain = vec![pt(0, 0), pt(1, 1), pt(2, 2)].into();
+    assert!(!ts.line_of_sight(&terrain, pt(0, 0), pt(1, 1)));
+    assert!(!ts.line_of_sight(&terrain, pt(0, 0), pt(2, 2)));
+    let terrain = vec![pt(0, 0), pt(1, 0), pt(1, 1), pt(2, 2)].into();
+    assert!(ts.line_of_sight(&terrain, pt(0, 0), pt(1, 1)));
+  }
+
+  /// A burst can't get around the wall in `walled_room`, but a wrap-around effect can, as long as
+  /// the way around isn't longer than its radius.
+  #[test]
+  fn points_reached() {
+    let ts = TileSystem::Realistic;
+    let terrain = walled_room();
+    let pt = |x: i64, y: i64| Point3::new(x * 100, y * 100, 0);
+    let reached = |radius, propagation| {
+      ts.points_reached(&terrain, Volume::Sphere(u32cm(radius)), propagation, pt(1, 0))
+    };
+
+    let burst = reached(400, Propagation::Burst);
+    assert!(burst.contains(&pt(0, 2)));
+    assert!(!burst.contains(&pt(3, 0)));
+    assert!(!burst.contains(&pt(3, 2)));
+
+    // The way around the wall to 3,2 is 4 squares long.
+    let wrap_around = reached(400, Propagation::WrapAround);
+    assert!(wrap_around.contains(&pt(0, 2)));
+    assert!(wrap_around.contains(&pt(3, 2)));
+    assert!(!wrap_around.contains(&pt(3, 0)));
+    assert!(!wrap_around.contains(&pt(2, 0)));
+    assert!(!reached(300, Propagation::WrapAround).contains(&pt(3, 2)));
+  }
+
+  /// A wrap-around effect in a shape without a radius goes wherever it can within the shape.
+  #[test]
+  fn points_reached_wrap_around_aabb() {
+    let ts = TileSystem::Realistic;
+    let terrain = walled_room();
+    let pt = |x: i64, y: i64| Point3::new(x * 100, y * 100, 0);
+    let volume = Volume::AABB(AABB { x: u32cm(500), y: u32cm(300), z: u32cm(100) });
+    let reached = ts.points_reached(&terrain, volume, Propagation::WrapAround, pt(0, 0));
+    assert_eq!(reached, terrain.iter().cloned().collect());
+  }
+
   #[test]
   fn items_within_volume_sphere() {
     let ts = TileSystem::Realistic;
@@ -787,6 +1020,112 @@ pub mod test {
     );
   }
 
+  #[test]
+  fn points_in_volume_sphere() {
+    let vol = Volume::Sphere(u32cm(100));
+    let mut results = TileSystem::Realistic.points_in_volume(vol, Point3::new(100, 100, 100));
+    results.sort();
+    assert_eq!(
+      results,
+      vec![
+        Point3::new(0, 100, 100),
+        Point3::new(100, 0, 100),
+        Point3::new(100, 100, 0),
+        Point3::new(100, 100, 100),
+        Point3::new(100, 100, 200),
+        Point3::new(100, 200, 100),
+        Point3::new(200, 100, 100),
+      ]
+    );
+    // Diagonals are as close as anything else in DnD, so the sphere is a 3x3x3 cube.
+    assert_eq!(TileSystem::DnD.points_in_volume(vol, Point3::new(100, 100, 100)).len(), 27);
+  }
+
+  #[test]
+  fn points_in_volume_line() {
+    let vol = Volume::Line { vector: Point3::new(300, 200, 0) };
+    let results = TileSystem::Realistic.points_in_volume(vol, Point3::new(100, 0, 100));
+    assert_eq!(
+      results,
+      vec![Point3::new(100, 0, 100), Point3::new(200, 0, 100), Point3::new(300, 100, 100)]
+    );
+  }
+
+  #[test]
+  fn points_in_volume_vertical_cylinder() {
+    let vol = Volume::VerticalCylinder { radius: u32cm(100), height: u32cm(200) };
+    let mut results = TileSystem::Realistic.points_in_volume(vol, Point3::new(0, 0, 0));
+    results.sort();
+    let mut expected = vec![];
+    for &(x, y) in &[(-100, 0), (0, -100), (0, 0), (0, 100), (100, 0)] {
+      expected.push(Point3::new(x, y, 0));
+      expected.push(Point3::new(x, y, 100));
+    }
+    expected.sort();
+    assert_eq!(results, expected);
+  }
+
+  #[test]
+  fn items_within_volume_aabb() {
+    let vol = Volume::AABB(large_size());
+    let items = hashmap! {
+      "Elron" => Point3::new(0, 0, 0),
+      "Kurok To" => Point3::new(100, 100, 0),
+      "Silmarillion" => Point3::new(200, 200, 0),
+      "Dudman" => Point3::new(300, 200, 0),
+    };
+    let mut results =
+      TileSystem::Realistic.items_within_volume(vol, Point3::new(100, 100, 0), &items);
+    results.sort();
+    assert_eq!(results, vec!["Kurok To", "Silmarillion"]);
+  }
+
+  #[test]
+  fn items_within_volume_vertical_cylinder() {
+    let vol = Volume::VerticalCylinder { radius: u32cm(200), height: u32cm(100) };
+    let items = hashmap! {
+      "Elron" => Point3::new(200, 0, 0),
+      "Kurok To" => Point3::new(100, 100, 0),
+      "Silmarillion" => Point3::new(200, 200, 0),
+      "Dudman" => Point3::new(0, 0, 100),
+    };
+    let mut results = TileSystem::Realistic.items_within_volume(vol, Point3::new(0, 0, 0), &items);
+    results.sort();
+    assert_eq!(results, vec!["Elron", "Kurok To"]);
+  }
+
+  /// Every kind of volume can be put into a collision world, at the same scale as the creatures in
+  /// it.
+  #[test]
+  fn volumes_collide_with_creatures() {
+    let inside = t_rogue("inside");
+    let outside = t_ranger("outside");
+    let volumes = vec![
+      Volume::Sphere(u32cm(100)),
+      Volume::AABB(medium_size()),
+      Volume::Line { vector: Point3::new(300, 0, 0) },
+      Volume::VerticalCylinder { radius: u32cm(100), height: u32cm(100) },
+    ];
+    for volume in volumes {
+      let cond_id = ConditionID::gen();
+      let condition = VolumeCondition {
+        point: Point3::new(0, 0, 0),
+        volume,
+        condition: Condition::Dead,
+        remaining: Duration::Interminate,
+      };
+      let world = make_world(
+        vec![(&inside, Point3::new(0, 0, 0)), (&outside, Point3::new(500, 500, 0))].into_iter(),
+        vec![(cond_id, &condition)].into_iter(),
+      );
+      let touching = query_world(&world, |cdata1, cdata2| match (*cdata1, *cdata2) {
+        (CollisionData::Creature(cid), CollisionData::ConditionVolume(_)) => Some(cid),
+        _ => None,
+      });
+      assert_eq!(touching, vec![inside.id], "{:?}", volume);
+    }
+  }
+
   #[test]
   fn line_through_point_simple() {
     let line = line_through_point(Point3::new(0, 0, 0), Point3::new(100, 0, 0), u32cm(200));
@@ -819,7 +1158,7 @@ pub mod test {
   fn large_creature_can_fit_through_large_opening() {
     let ts = TileSystem::Realistic;
     let mut dumbbell = dumbbell_map();
-    dumbbell.push(Point3::new(200, 200, 0));
+    dumbbell.0.insert(Point3::new(200, 200, 0));
     let big_guy = Volume::AABB(large_size());
     let path =
       ts.find_path(Point3::new(0, 0, 0), u32cm(1000), &dumbbell, big_guy, Point3::new(300, 0, 0));
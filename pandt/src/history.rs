@@ -0,0 +1,189 @@
+//! Support for searching through the history of a game. The history itself is kept by `App`.
+
+use crate::types::*;
+
+impl HistoryQuery {
+  /// Does the log at `index` in the history match this query?
+  pub fn matches(&self, index: usize, log: &GameLog) -> bool {
+    self.start.iter().all(|&start| index >= start)
+      && self.end.iter().all(|&end| index < end)
+      && self.creature_id.iter().all(|&cid| log.involves_creature(cid))
+      && self.kind.iter().all(|kind| log.is_kind(kind))
+  }
+}
+
+impl GameLog {
+  /// The name of this log's variant.
+  pub fn kind(&self) -> &'static str {
+    match *self {
+      GameLog::LoadModule { .. } => "LoadModule",
+      GameLog::SetActiveScene(..) => "SetActiveScene",
+      GameLog::SetVettingPolicy(..) => "SetVettingPolicy",
+      GameLog::RegisterPlayer(..) => "RegisterPlayer",
+      GameLog::GiveCreaturesToPlayer(..) => "GiveCreaturesToPlayer",
+      GameLog::UnregisterPlayer(..) => "UnregisterPlayer",
+      GameLog::RemoveCreaturesFromPlayer(..) => "RemoveCreaturesFromPlayer",
+      GameLog::SetPlayerScene(..) => "SetPlayerScene",
+      GameLog::ChatFromGM(..) => "ChatFromGM",
+      GameLog::ChatFromPlayer(..) => "ChatFromPlayer",
+      GameLog::AttributeCheckResult(..) => "AttributeCheckResult",
+      GameLog::CreateFolder(..) => "CreateFolder",
+      GameLog::RenameFolder(..) => "RenameFolder",
+      GameLog::MoveFolderItem(..) => "MoveFolderItem",
+      GameLog::CopyFolderItem { .. } => "CopyFolderItem",
+      GameLog::DeleteFolderItem(..) => "DeleteFolderItem",
+      GameLog::CreateItem(..) => "CreateItem",
+      GameLog::EditItem(..) => "EditItem",
+      GameLog::CreateNote(..) => "CreateNote",
+      GameLog::EditNote(..) => "EditNote",
+      GameLog::TransferItem { .. } => "TransferItem",
+      GameLog::RemoveItem { .. } => "RemoveItem",
+      GameLog::SetItemCount { .. } => "SetItemCount",
+      GameLog::CreateScene(..) => "CreateScene",
+      GameLog::EditSceneDetails { .. } => "EditSceneDetails",
+      GameLog::SetSceneCreatureVisibility { .. } => "SetSceneCreatureVisibility",
+      GameLog::AddCreatureToScene { .. } => "AddCreatureToScene",
+      GameLog::RemoveCreatureFromScene { .. } => "RemoveCreatureFromScene",
+      GameLog::AddSceneChallenge { .. } => "AddSceneChallenge",
+      GameLog::RemoveSceneChallenge { .. } => "RemoveSceneChallenge",
+      GameLog::SetFocusedSceneCreatures { .. } => "SetFocusedSceneCreatures",
+      GameLog::RemoveSceneVolumeCondition { .. } => "RemoveSceneVolumeCondition",
+      GameLog::EditSceneTerrain { .. } => "EditSceneTerrain",
+      GameLog::EditSceneHighlights { .. } => "EditSceneHighlights",
+      GameLog::EditSceneAnnotations { .. } => "EditSceneAnnotations",
+      GameLog::EditSceneRelatedScenes { .. } => "EditSceneRelatedScenes",
+      GameLog::EditSceneSceneHotspots { .. } => "EditSceneSceneHotspots",
+      GameLog::CombatLog(..) => "CombatLog",
+      GameLog::CreatureLog(..) => "CreatureLog",
+      GameLog::SetCreaturePos(..) => "SetCreaturePos",
+      GameLog::PathCreature(..) => "PathCreature",
+      GameLog::AddVolumeCondition { .. } => "AddVolumeCondition",
+      GameLog::StartCombat(..) => "StartCombat",
+      GameLog::StopCombat => "StopCombat",
+      GameLog::CreateCreature(..) => "CreateCreature",
+      GameLog::EditCreatureDetails { .. } => "EditCreatureDetails",
+      GameLog::AddCreatureToCombat(..) => "AddCreatureToCombat",
+      GameLog::RemoveCreatureFromCombat(..) => "RemoveCreatureFromCombat",
+      GameLog::Rollback(..) => "Rollback",
+      GameLog::SwitchBranch(..) => "SwitchBranch",
+      GameLog::Undo => "Undo",
+      GameLog::Redo => "Redo",
+      GameLog::EditLog { .. } => "EditLog",
+    }
+  }
+
+  /// Is this log of the given kind? Logs that wrap a `CombatLog` or `CreatureLog` match both
+  /// their own kind and that of the wrapped log.
+  pub fn is_kind(&self, kind: &str) -> bool {
+    self.kind() == kind
+      || match *self {
+        GameLog::CombatLog(ref log) => log.kind() == kind,
+        GameLog::CreatureLog(_, ref log) => log.kind() == kind,
+        _ => false,
+      }
+  }
+
+  /// Can players see this log in their copy of the history? Only chat is shared with them, since
+  /// anything else could give away something that's only for the GM.
+  pub fn visible_to_players(&self) -> bool {
+    matches!(*self, GameLog::ChatFromGM(..) | GameLog::ChatFromPlayer(..))
+  }
+
+  /// Does this log refer to the given creature?
+  pub fn involves_creature(&self, cid: CreatureID) -> bool {
+    let owned_by = |owner: &InventoryOwner| *owner == InventoryOwner::Creature(cid);
+    let folder_item = |item: &FolderItemID| *item == FolderItemID::CreatureID(cid);
+    match *self {
+      GameLog::GiveCreaturesToPlayer(_, ref cids)
+      | GameLog::RemoveCreaturesFromPlayer(_, ref cids)
+      | GameLog::SetFocusedSceneCreatures { creatures: ref cids, .. } => cids.contains(&cid),
+      GameLog::AttributeCheckResult(creature_id, ..)
+      | GameLog::CreatureLog(creature_id, _)
+      | GameLog::SetCreaturePos(_, creature_id, _)
+      | GameLog::PathCreature(_, creature_id, _)
+      | GameLog::EditCreatureDetails { creature_id, .. }
+      | GameLog::AddCreatureToCombat(creature_id, _)
+      | GameLog::RemoveCreatureFromCombat(creature_id)
+      | GameLog::SetSceneCreatureVisibility { creature_id, .. }
+      | GameLog::AddCreatureToScene { creature_id, .. }
+      | GameLog::RemoveCreatureFromScene { creature_id, .. } => creature_id == cid,
+      GameLog::CreateCreature(_, ref creature) => creature.id == cid,
+      GameLog::EditLog { ref log, .. } => log.involves_creature(cid),
+      GameLog::StartCombat(_, ref combatants) => combatants.iter().any(|&(c, _)| c == cid),
+      GameLog::CombatLog(CombatLog::ChangeCreatureInitiative(creature_id, _))
+      | GameLog::CombatLog(CombatLog::EndTurn(creature_id)) => creature_id == cid,
+      GameLog::CombatLog(CombatLog::RerollInitiative(ref combatants)) => {
+        combatants.iter().any(|&(c, _)| c == cid)
+      }
+      GameLog::MoveFolderItem(_, ref item, _)
+      | GameLog::DeleteFolderItem(_, ref item)
+      | GameLog::CopyFolderItem { item_id: ref item, .. } => folder_item(item),
+      GameLog::TransferItem { ref from, ref to, .. } => owned_by(from) || owned_by(to),
+      GameLog::RemoveItem { ref owner, .. } | GameLog::SetItemCount { ref owner, .. } => {
+        owned_by(owner)
+      }
+      _ => false,
+    }
+  }
+}
+
+impl CombatLog {
+  /// The name of this log's variant.
+  pub fn kind(&self) -> &'static str {
+    match *self {
+      CombatLog::ConsumeMovement(..) => "ConsumeMovement",
+      CombatLog::ChangeCreatureInitiative(..) => "ChangeCreatureInitiative",
+      CombatLog::EndTurn(..) => "EndTurn",
+      CombatLog::ForceNextTurn => "ForceNextTurn",
+      CombatLog::ForcePrevTurn => "ForcePrevTurn",
+      CombatLog::RerollInitiative(..) => "RerollInitiative",
+    }
+  }
+}
+
+impl CreatureLog {
+  /// The name of this log's variant.
+  pub fn kind(&self) -> &'static str {
+    match *self {
+      CreatureLog::Damage(..) => "Damage",
+      CreatureLog::Heal(..) => "Heal",
+      CreatureLog::GenerateEnergy(..) => "GenerateEnergy",
+      CreatureLog::ReduceEnergy(..) => "ReduceEnergy",
+      CreatureLog::ApplyCondition(..) => "ApplyCondition",
+      CreatureLog::DecrementConditionRemaining(..) => "DecrementConditionRemaining",
+      CreatureLog::RemoveCondition(..) => "RemoveCondition",
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::types::test::*;
+  use crate::types::*;
+
+  #[test]
+  fn kinds_include_wrapped_logs() {
+    let log = GameLog::CreatureLog(cid_rogue(), CreatureLog::Damage(HP(3), vec![3]));
+    assert_eq!(log.kind(), "CreatureLog");
+    assert!(log.is_kind("CreatureLog"));
+    assert!(log.is_kind("Damage"));
+    assert!(!log.is_kind("Heal"));
+    assert!(GameLog::CombatLog(CombatLog::ForceNextTurn).is_kind("ForceNextTurn"));
+  }
+
+  #[test]
+  fn involves_creature() {
+    let log = GameLog::StartCombat(t_scene_id(), vec![(cid_rogue(), 1), (cid_ranger(), 2)]);
+    assert!(log.involves_creature(cid_ranger()));
+    assert!(!log.involves_creature(cid_cleric()));
+    let log = GameLog::TransferItem {
+      from: InventoryOwner::Scene(t_scene_id()),
+      to: InventoryOwner::Creature(cid_cleric()),
+      item_id: ItemID::gen(),
+      count: 1,
+    };
+    assert!(log.involves_creature(cid_cleric()));
+    assert!(!log.involves_creature(cid_rogue()));
+    assert!(!GameLog::ChatFromGM("hi".to_string()).involves_creature(cid_rogue()));
+  }
+}
@@ -0,0 +1,239 @@
+//! An append-only journal of the logs produced since a game was last saved, so that a game can be
+//! recovered if the server crashes.
+//!
+//! The journal lives next to the saved game, as `{name}.journal`, and contains one line of JSON
+//! (the list of `GameLog`s) for each command that has been performed. Loading a saved game replays
+//! its journal on top of it, and saving the game starts a new, empty journal.
+//!
+//! The first line of a journal is a header with the `App::journal_id` of the saved game it goes
+//! with. If the server crashes after a game is saved but before its new journal is started, the
+//! old journal is left behind with the wrong ID, and so isn't replayed onto a save that already
+//! has everything in it.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json;
+
+use crate::types::{GameError, GameLog};
+
+pub use ptstorage::{journal_path, JOURNAL_EXTENSION};
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+  journal_id: u64,
+}
+
+/// What was recovered from a journal.
+#[derive(Debug, PartialEq)]
+pub struct Journal {
+  /// The `App::journal_id` of the saved game that the journal goes with. Journals from before
+  /// there were IDs count as 0, like the games saved back then.
+  pub journal_id: u64,
+  /// The logs from each command, in order.
+  pub entries: Vec<Vec<GameLog>>,
+}
+
+/// Create an empty journal for the app with the given `journal_id`, throwing away any existing one.
+pub fn create(path: &Path, journal_id: u64) -> Result<(), GameError> {
+  let mut header = serde_json::to_vec(&Header { journal_id })
+    .map_err(|e| GameError::BuggyProgram(e.to_string()))?;
+  header.push(b'\n');
+  let mut file = File::create(path).map_err(|e| write_error(path, e))?;
+  file.write_all(&header).map_err(|e| write_error(path, e))?;
+  file.sync_data().map_err(|e| write_error(path, e))
+}
+
+/// Append the logs from a single command to the journal, creating it if necessary.
+pub fn append(path: &Path, logs: &[GameLog]) -> Result<(), GameError> {
+  let mut line = serde_json::to_vec(logs).map_err(|e| GameError::BuggyProgram(e.to_string()))?;
+  line.push(b'\n');
+  let mut file =
+    OpenOptions::new().create(true).append(true).open(path).map_err(|e| write_error(path, e))?;
+  // The whole line is written with one call, so a crash can at worst leave a partial line at the
+  // end of the journal, which `recover` will throw away.
+  file.write_all(&line).map_err(|e| write_error(path, e))?;
+  file.sync_data().map_err(|e| write_error(path, e))
+}
+
+/// Read all of the entries in a journal. A missing journal is treated as empty.
+///
+/// If the last line of the journal was only partially written, it's discarded (and truncated from
+/// the file, so that further entries can be appended).
+pub fn recover(path: &Path) -> Result<Journal, GameError> {
+  let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+    Ok(file) => file,
+    Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+      return Ok(Journal { journal_id: 0, entries: vec![] })
+    }
+    Err(e) => return Err(read_error(path, e)),
+  };
+  let mut contents = String::new();
+  file.read_to_string(&mut contents).map_err(|e| read_error(path, e))?;
+
+  let complete_len = contents.rfind('\n').map_or(0, |idx| idx + 1);
+  if complete_len < contents.len() {
+    file.set_len(complete_len as u64).map_err(|e| write_error(path, e))?;
+  }
+
+  let mut lines = contents[..complete_len].lines().enumerate().peekable();
+  let header = lines.peek().and_then(|(_, line)| serde_json::from_str::<Header>(line).ok());
+  if header.is_some() {
+    lines.next();
+  }
+  let entries = lines
+    .map(|(idx, line)| {
+      serde_json::from_str(line)
+        .map_err(|e| GameError::CouldNotParseJournal(path.to_string_lossy().into(), idx + 1, e))
+    })
+    .collect::<Result<_, _>>()?;
+  Ok(Journal { journal_id: header.map_or(0, |header| header.journal_id), entries })
+}
+
+fn read_error(path: &Path, e: io::Error) -> GameError {
+  GameError::CouldNotReadJournal(path.to_string_lossy().into(), e)
+}
+
+fn write_error(path: &Path, e: io::Error) -> GameError {
+  GameError::CouldNotWriteJournal(path.to_string_lossy().into(), e)
+}
+
+#[cfg(test)]
+pub mod test {
+  use std::fs;
+  use std::path::PathBuf;
+
+  use crate::app::test::*;
+  use crate::game::load_app;
+  use crate::journal::*;
+  use crate::types::test::*;
+  use crate::types::*;
+
+  /// A fresh, empty directory to keep saved games and journals in.
+  pub fn t_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pandt-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn journal_path_is_next_to_game() {
+    assert_eq!(
+      journal_path(&PathBuf::from("/games/session.yaml")),
+      PathBuf::from("/games/session.yaml.journal")
+    );
+  }
+
+  #[test]
+  fn missing_journal_is_empty() {
+    let path = t_dir().join("nothing.journal");
+    assert_eq!(recover(&path).unwrap(), Journal { journal_id: 0, entries: vec![] });
+    assert!(!path.exists());
+  }
+
+  #[test]
+  fn append_and_recover() {
+    let path = t_dir().join("game.journal");
+    let entries = vec![
+      vec![GameLog::StopCombat],
+      vec![GameLog::ChatFromGM("hi".to_string()), GameLog::ChatFromGM("there".to_string())],
+    ];
+    for entry in &entries {
+      append(&path, entry).unwrap();
+    }
+    assert_eq!(recover(&path).unwrap(), Journal { journal_id: 0, entries: entries.clone() });
+    create(&path, 5).unwrap();
+    append(&path, &entries[0]).unwrap();
+    assert_eq!(
+      recover(&path).unwrap(),
+      Journal { journal_id: 5, entries: vec![entries[0].clone()] }
+    );
+  }
+
+  /// A line that was only partially written when the server crashed is ignored, and doesn't get in
+  /// the way of later entries.
+  #[test]
+  fn recover_discards_partial_line() {
+    let path = t_dir().join("game.journal");
+    append(&path, &[GameLog::StopCombat]).unwrap();
+    let mut contents = fs::read_to_string(&path).unwrap();
+    contents.push_str("[{\"ChatFromGM\":\"hel");
+    fs::write(&path, contents).unwrap();
+
+    assert_eq!(recover(&path).unwrap().entries, vec![vec![GameLog::StopCombat]]);
+    append(&path, &[GameLog::ChatFromGM("hi".to_string())]).unwrap();
+    assert_eq!(
+      recover(&path).unwrap().entries,
+      vec![vec![GameLog::StopCombat], vec![GameLog::ChatFromGM("hi".to_string())]]
+    );
+  }
+
+  #[test]
+  fn corrupt_journal_is_an_error() {
+    let path = t_dir().join("game.journal");
+    fs::write(&path, "[\"StopCombat\"]\ngarbage\n").unwrap();
+    match recover(&path) {
+      Err(GameError::CouldNotParseJournal(_, 2, _)) => {}
+      r => panic!("Expected a parse error, got {:?}", r),
+    }
+  }
+
+  /// Loading a saved game replays everything that happened after it was saved.
+  #[test]
+  fn load_replays_journal() {
+    let dir = t_dir();
+    let mut app = t_app();
+    fs::write(dir.join("game.yaml"), serde_yaml::to_string(&app).unwrap()).unwrap();
+    app.start_journal(journal_path(&dir.join("game.yaml"))).unwrap();
+
+    perf(&mut app, GameCommand::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(1, 1, 1)))
+      .unwrap();
+    perf(
+      &mut app,
+      GameCommand::StartCombat(t_scene_id(), vec![cid_ranger(), cid_rogue(), cid_cleric()]),
+    )
+    .unwrap();
+    perf(&mut app, GameCommand::Rollback(0, 1)).unwrap();
+    perf(&mut app, GameCommand::ChatFromGM("still here".to_string())).unwrap();
+    perf(&mut app, GameCommand::SwitchBranch(0)).unwrap();
+    perf(&mut app, GameCommand::Undo).unwrap();
+    perf(&mut app, GameCommand::EditLog(0, 0, GameLog::ChatFromGM("edited".to_string()))).unwrap();
+
+    let storage = ptstorage::FilesystemStorage::new(dir.clone(), None, 0);
+    let loaded = load_app(&storage, ModuleSource::SavedGame, "game.yaml").expect("must load");
+    assert_eq!(loaded.current_game, app.current_game);
+    assert_eq!(loaded.snapshots, app.snapshots);
+    assert_eq!(loaded.branches, app.branches);
+    assert_eq!(loaded.current_branch, app.current_branch);
+    assert_eq!(loaded.commands, app.commands);
+    assert_eq!(loaded.undone, app.undone);
+    assert_eq!(loaded.rng_counter, app.rng_counter);
+    assert_eq!(loaded.journal_path, app.journal_path);
+  }
+
+  /// If the server crashed after saving a game but before starting its new journal, the old
+  /// journal isn't replayed, since the game already has everything in it.
+  #[test]
+  fn load_skips_journal_from_before_save() {
+    let dir = t_dir();
+    let storage = ptstorage::FilesystemStorage::new(dir.clone(), None, 0);
+    let mut app = t_app();
+    fs::write(dir.join("game.yaml"), serde_yaml::to_string(&app).unwrap()).unwrap();
+    app.start_journal(journal_path(&dir.join("game.yaml"))).unwrap();
+    perf(&mut app, GameCommand::CreateFolder("/Notes".parse().unwrap())).unwrap();
+
+    app.new_journal_id();
+    fs::write(dir.join("game.yaml"), serde_yaml::to_string(&app).unwrap()).unwrap();
+    let mut loaded = load_app(&storage, ModuleSource::SavedGame, "game.yaml").expect("must load");
+    assert_eq!(loaded.current_game, app.current_game);
+    assert_eq!(loaded.snapshots, app.snapshots);
+
+    // The loaded game gets a journal of its own.
+    perf(&mut loaded, GameCommand::ChatFromGM("hello".to_string())).unwrap();
+    let reloaded = load_app(&storage, ModuleSource::SavedGame, "game.yaml").expect("must load");
+    assert_eq!(reloaded.current_game, loaded.current_game);
+    assert_eq!(reloaded.snapshots, loaded.snapshots);
+  }
+}
@@ -11,7 +11,11 @@ extern crate uom;
 pub mod app;
 pub mod combat;
 pub mod creature;
+pub mod dice;
 pub mod game;
 pub mod grid;
+pub mod history;
+pub mod journal;
 pub mod scene;
 pub mod types;
+pub mod vision;
@@ -11,15 +11,16 @@ impl Scene {
       background_image_url: creation.background_image_url.clone(),
       background_image_offset: creation.background_image_offset,
       background_image_scale: creation.background_image_scale,
-      terrain: vec![],
-      highlights: HashMap::new(),
-      annotations: HashMap::new(),
+      terrain: Default::default(),
+      highlights: Default::default(),
+      annotations: Default::default(),
       scene_hotspots: HashMap::new(),
       related_scenes: HashSet::new(),
       creatures: HashMap::new(),
       attribute_checks: HashMap::new(),
       inventory: HashMap::new(),
       volume_conditions: HashMap::new(),
+      fog_of_war: creation.fog_of_war,
       focused_creatures: vec![],
     }
   }
@@ -80,16 +81,47 @@ impl Scene {
 
   /// Return a set of points of open terrain which  intersect a volume.
   /// Largely used for previewing the area that will be affected by a volume-affecting ability.
+  /// Like `creatures_in_volume`, this leaves out anything that the effect can't reach from the
+  /// volume's origin.
   pub fn open_terrain_in_volume(
-    &self, game: &Game, pt: Point3, volume: Volume,
-  ) -> Result<Terrain, GameError> {
-    let all_open = self.terrain.iter().map(|pt| (*pt, *pt)).collect();
-    Ok(game.tile_system.items_within_volume(volume, pt, &all_open))
+    &self, game: &Game, pt: Point3, volume: Volume, propagation: Propagation,
+  ) -> Result<Vec<Point3>, GameError> {
+    let reached = game.tile_system.points_reached(&self.terrain, volume, propagation, pt);
+    Ok(reached.into_iter().filter(|open_pt| self.terrain.contains(open_pt)).collect())
   }
 
-  pub fn creatures_in_volume(&self, ts: TileSystem, pt: Point3, volume: Volume) -> Vec<CreatureID> {
-    let creature_locations = self.creatures.iter().map(|(cid, &(pt, _))| (*cid, pt)).collect();
-    ts.items_within_volume(volume, pt, &creature_locations)
+  /// The creatures inside a volume, as long as solid terrain doesn't shield them from the volume's
+  /// origin (see `TileSystem::points_reached`).
+  pub fn creatures_in_volume(
+    &self, ts: TileSystem, pt: Point3, volume: Volume, propagation: Propagation,
+  ) -> Vec<CreatureID> {
+    let reached = ts.points_reached(&self.terrain, volume, propagation, pt);
+    self
+      .creatures
+      .iter()
+      .filter(|(_, (creature_pos, _))| reached.contains(creature_pos))
+      .map(|(cid, _)| *cid)
+      .collect()
+  }
+
+  /// Like `creatures_in_volume`, for each of several places the volume could be put.
+  pub fn creatures_in_volume_at_points(
+    &self, ts: TileSystem, pts: &[Point3], volume: Volume, propagation: Propagation,
+  ) -> Vec<(Point3, Vec<CreatureID>)> {
+    let creature_locations: HashMap<CreatureID, Point3> =
+      self.creatures.iter().map(|(cid, &(pt, _))| (*cid, pt)).collect();
+    // Working out where the effect reaches is slow, so only bother where the volume has somebody
+    // in it.
+    ts.items_within_volume_at_points(volume, pts, &creature_locations)
+      .into_iter()
+      .map(|(pt, mut cids)| {
+        if !cids.is_empty() {
+          let reached = ts.points_reached(&self.terrain, volume, propagation, pt);
+          cids.retain(|cid| reached.contains(&creature_locations[cid]));
+        }
+        (pt, cids)
+      })
+      .collect()
   }
 
   pub fn get_world(&self, game: &Game) -> Result<CollisionWorld, GameError> {
@@ -5,12 +5,13 @@
 #![cfg_attr(feature = "cargo-clippy", allow(large_enum_variant))]
 
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use derive_more::{Add, Div, Mul, Sub};
 use error_chain::bail;
 use failure::Fail;
 use num::Saturating;
-use rand::Rng;
 use serde::{
   de,
   ser::{Error as SerError, SerializeStruct},
@@ -21,9 +22,12 @@ use uom::si::length::{centimeter, meter};
 use uuid::{Error as UuidParseError, Uuid};
 
 use foldertree::{FolderPath, FolderTree, FolderTreeError};
+use ptstorage::StorageError;
 use indexed::{DeriveKey, IndexedHashMap};
 use nonempty;
 
+use crate::game::ChangedGame;
+
 pub mod u32units {
   ISQ!(uom::si, u32, (centimeter, gram, second, ampere, kelvin, mole, candela));
 }
@@ -44,7 +48,6 @@ pub fn up_length(v: u32units::Length) -> i64units::Length { i64cm(v.get::<centim
 
 pub type Color = String;
 pub type Inventory = HashMap<ItemID, u64>;
-pub type Terrain = Vec<Point3>;
 
 /// Point3 holds a position in 3d space in meters (FOR NOW --radix)
 #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
@@ -109,6 +112,32 @@ impl<'de> Deserialize<'de> for Point3 {
   }
 }
 
+/// The open squares in a scene; anything that isn't in the terrain is solid.
+///
+/// Pathfinding checks whether points are open a *lot*, so this is a set. It's serialized as a
+/// plain list of points.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Terrain(pub HashSet<Point3>);
+
+impl Serialize for Terrain {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    // Sorted, so that saving a game twice gives the same result.
+    let mut points: Vec<&Point3> = self.0.iter().collect();
+    points.sort();
+    points.serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for Terrain {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let points: Vec<Point3> = Deserialize::deserialize(deserializer)?;
+    Ok(Terrain(points.into_iter().collect()))
+  }
+}
+
 /// An axis-aligned bounding box.
 #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug, Hash, Serialize, Deserialize)]
 pub struct AABB {
@@ -128,60 +157,32 @@ impl AABB {
   }
 }
 
-#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Hash, Serialize, Deserialize)]
+/// A dice expression. See the `dice` module for rolling and the textual notation.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
 pub enum Dice {
   Expr { num: u8, size: u8 },
   Plus(Box<Dice>, Box<Dice>),
   Flat(i8),
   BestOf(u8, Box<Dice>),
-}
-
-impl Dice {
-  pub fn expr(n: u8, d: u8) -> Dice { Dice::Expr { num: n, size: d } }
-
-  pub fn flat(val: i8) -> Dice { Dice::Flat(val) }
-
-  pub fn plus(&self, d: Dice) -> Dice { Dice::Plus(Box::new(self.clone()), Box::new(d)) }
-
-  /// Roll the dice, returning a vector containing all of the individual die rolls, and then the
-  /// final result.
-  pub fn roll(&self) -> (Vec<i16>, i32) {
-    match *self {
-      Dice::Expr { num, size } => {
-        let mut intermediate = vec![];
-        let mut result = 0i32;
-        let mut rng = rand::thread_rng();
-
-        for _ in 0..num {
-          let val = rng.gen_range(1, i32::from(size) + 1);
-          result += val;
-          intermediate.push(val as i16);
-        }
-        (intermediate, result)
-      }
-      Dice::Flat(val) => (vec![i16::from(val)], i32::from(val)),
-      Dice::Plus(ref l, ref r) => {
-        let (mut intermediate, left_result) = l.roll();
-        let (right_intermediate, right_result) = r.roll();
-        intermediate.extend(right_intermediate);
-        (intermediate, left_result + right_result)
-      }
-      Dice::BestOf(count, ref dice) => {
-        if count == 0 {
-          panic!("Sorry, can't roll best of 0.")
-        }
-        let (mut best_rolls, mut best_result) = dice.roll();
-        for _ in 1..count {
-          let (rolls, result) = dice.roll();
-          if result > best_result {
-            best_rolls = rolls;
-            best_result = result;
-          }
-        }
-        (best_rolls, best_result)
-      }
-    }
-  }
+  WorstOf(u8, Box<Dice>),
+  /// Roll `num` dice and keep the highest `keep` of them, e.g. "4d6kh3".
+  KeepHighest { num: u8, size: u8, keep: u8 },
+  /// Roll `num` dice and keep the lowest `keep` of them, e.g. "4d6kl3".
+  KeepLowest { num: u8, size: u8, keep: u8 },
+  /// Dice that are rolled again, and added, whenever they roll their maximum, e.g. "2d6!".
+  Exploding { num: u8, size: u8 },
+  /// Dice that are rerolled once when they roll `threshold` or lower, e.g. "2d6r1".
+  Reroll { num: u8, size: u8, threshold: u8 },
+}
+
+/// The exact probability distribution of some Dice, as computed by `Dice::stats`.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct DiceStats {
+  pub min: i32,
+  pub max: i32,
+  pub mean: f64,
+  /// Every possible result, in ascending order, with its probability.
+  pub distribution: Vec<(i32, f64)>,
 }
 
 #[derive(
@@ -323,11 +324,7 @@ impl InventoryOwner {
   }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
-pub enum ModuleSource {
-  Module,
-  SavedGame,
-}
+pub use ptstorage::ModuleSource;
 
 /// Top-level commands that can be sent from a client to affect the state of the app.
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -434,7 +431,7 @@ pub enum GameCommand {
   },
   EditSceneTerrain {
     scene_id: SceneID,
-    terrain: Vec<Point3>,
+    terrain: Terrain,
   },
   EditSceneHighlights {
     scene_id: SceneID,
@@ -515,8 +512,29 @@ pub enum GameCommand {
 
   SetActiveScene(Option<SceneID>),
 
-  /// Roll back to a specific snapshot + log index
+  /// Choose which commands from players have to be approved by the GM.
+  SetVettingPolicy(VettingPolicy),
+  /// Apply a player's pending command (see `App::perform_player_command`).
+  ApprovePendingCommand(PlayerID),
+  /// Throw away a player's pending command.
+  RejectPendingCommand(PlayerID),
+  /// Replace a player's pending command with another one, performed as that player. The new
+  /// command is left pending whatever the vetting policy is, so it can be checked before it's
+  /// approved.
+  EditPendingCommand(PlayerID, Box<GameCommand>),
+
+  /// Roll back to a specific snapshot + log index. This starts a new branch of history, leaving
+  /// the current one intact.
   Rollback(usize, usize),
+  /// Switch to another branch of history, by its index in `App::branches`.
+  SwitchBranch(usize),
+  /// Undo the most recent command (that hasn't already been undone).
+  Undo,
+  /// Redo the most recently undone command.
+  Redo,
+  /// Replace the log at a specific snapshot + log index with a different one, re-applying all of
+  /// the logs after it. The history from before the edit is kept as a new branch.
+  EditLog(usize, usize, GameLog),
 }
 
 /// A representation of state change in a Creature. See `GameLog`.
@@ -561,6 +579,7 @@ pub enum GameLog {
   },
 
   SetActiveScene(Option<SceneID>),
+  SetVettingPolicy(VettingPolicy),
 
   // ** Player Manipulation **
   RegisterPlayer(PlayerID),
@@ -650,7 +669,7 @@ pub enum GameLog {
 
   EditSceneTerrain {
     scene_id: SceneID,
-    terrain: Vec<Point3>,
+    terrain: Terrain,
   },
   EditSceneHighlights {
     scene_id: SceneID,
@@ -695,6 +714,17 @@ pub enum GameLog {
   RemoveCreatureFromCombat(CreatureID),
   /// Indexes into snapshots and logs.
   Rollback(usize, usize),
+  SwitchBranch(usize),
+  Undo,
+  Redo,
+  /// The log at the given indexes was replaced with `log`. `invalidated` lists the later logs that
+  /// could no longer be applied, with their indexes in the branch holding the old history.
+  EditLog {
+    snapshot_idx: usize,
+    log_idx: usize,
+    log: Box<GameLog>,
+    invalidated: Vec<InvalidatedLog>,
+  },
 }
 
 pub fn combat_logs_into_game_logs(ls: Vec<CombatLog>) -> Vec<GameLog> {
@@ -751,6 +781,12 @@ pub enum GameError {
   CreatureOutOfRange(CreatureID),
   #[fail(display = "Point {:?} is out of range.", _0)]
   PointOutOfRange(Point3),
+  #[fail(display = "Point {:?} can't be seen.", _0)]
+  PointNotVisible(Point3),
+  #[fail(display = "Point {:?} is solid terrain.", _0)]
+  TargetNotOpenTerrain(Point3),
+  #[fail(display = "At most {} creatures can be targeted.", _0)]
+  TooManyTargets(u8),
   #[fail(display = "There's a bug in the program: {}", _0)]
   BuggyProgram(String),
   #[fail(display = "There is currently no combat.")]
@@ -775,8 +811,26 @@ pub enum GameError {
   PlayerNotFound(PlayerID),
   #[fail(display = "Player ID {:?} does not control creature {:?}.", _0, _1)]
   PlayerDoesntControlCreature(PlayerID, CreatureID),
+  #[fail(display = "Player ID {:?} can't act on behalf of player {:?}.", _0, _1)]
+  NotThatPlayer(PlayerID, PlayerID),
   #[fail(display = "Couldn't find history item at snapshot {} log item {}", _0, _1)]
   HistoryNotFound(usize, usize),
+  #[fail(display = "Couldn't find history branch {}", _0)]
+  BranchNotFound(usize),
+  #[fail(display = "There's nothing to undo")]
+  NothingToUndo,
+  #[fail(display = "There's nothing to redo")]
+  NothingToRedo,
+  #[fail(display = "{:?} can't be put into the history", _0)]
+  CannotEditLog(GameLog),
+  #[fail(display = "Player {:?} has no pending command", _0)]
+  NoPendingCommand(PlayerID),
+  #[fail(display = "Player {:?} already has a command waiting for approval", _0)]
+  PendingCommandExists(PlayerID),
+  #[fail(display = "Players can't perform {:?}", _0)]
+  GMOnlyCommand(GameCommand),
+  #[fail(display = "Scene {:?} is in combat, so creatures there act on their turn", _0)]
+  SceneInCombat(SceneID),
   #[fail(display = "Initiative index {} is out of bounds.", _0)]
   InitiativeOutOfBounds(usize),
   #[fail(display = "The folder {} is not empty", _0)]
@@ -787,17 +841,27 @@ pub enum GameError {
   NoteNotFound(FolderPath, String),
   #[fail(display = "Notes can't be linked or unlinked. '{}' / '{}'", _0, _1)]
   CannotLinkNotes(FolderPath, String),
-  #[fail(display = "Failed to open a file containing an application: {}", _0)]
-  CouldNotOpenAppFile(String, #[cause] ::std::io::Error),
   #[fail(display = "Failed to parse a serialized application: {}", _0)]
   CouldNotParseApp(#[cause] serde_yaml::Error),
-
-  #[fail(display = "No module source found")]
-  NoModuleSource,
+  #[fail(display = "Failed to read the journal {}: {}", _0, _1)]
+  CouldNotReadJournal(String, #[cause] ::std::io::Error),
+  #[fail(display = "Failed to write to the journal {}: {}", _0, _1)]
+  CouldNotWriteJournal(String, #[cause] ::std::io::Error),
+  #[fail(display = "Failed to parse line {} of the journal {}: {}", _1, _0, _2)]
+  CouldNotParseJournal(String, usize, #[cause] serde_json::Error),
+
+  #[fail(display = "Couldn't parse dice expression {:?} at position {}: {}", _0, _1, _2)]
+  InvalidDiceExpression(String, usize, String),
+  #[fail(display = "The dice {} can't be rolled: {}", _0, _1)]
+  InvalidDice(Dice, String),
+  #[fail(display = "The dice {} are too complex to compute a distribution for.", _0)]
+  DiceTooComplex(Dice),
 
   // Wrappers for other errors:
   #[fail(display = "FolderTree error: {}", _0)]
   FolderTreeError(#[cause] FolderTreeError),
+  #[fail(display = "Storage error: {}", _0)]
+  StorageError(#[cause] StorageError),
   #[fail(display = "UUID Parse Error: {}", _0)]
   InvalidID(String, #[cause] UuidParseError),
 }
@@ -806,6 +870,10 @@ impl From<FolderTreeError> for GameError {
   fn from(error: FolderTreeError) -> Self { GameError::FolderTreeError(error) }
 }
 
+impl From<StorageError> for GameError {
+  fn from(error: StorageError) -> Self { GameError::StorageError(error) }
+}
+
 impl<'a> From<&'a str> for GameError {
   fn from(error: &'a str) -> Self { GameError::BuggyProgram(error.to_string()) }
 }
@@ -815,6 +883,8 @@ impl<'a> From<&'a str> for GameError {
 pub enum PotentialTargets {
   CreatureIDs(Vec<CreatureID>),
   Points(Vec<Point3>),
+  /// The places a volume can be put, each with the creatures that could be chosen from inside it.
+  VolumePlacements(Vec<(Point3, Vec<CreatureID>)>),
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -862,10 +932,14 @@ pub enum CreatureTarget {
     /// maximum number of creatures that can be hit
     maximum: u8,
     range: u32units::Length,
+    #[serde(default)]
+    propagation: Propagation,
   },
   AllCreaturesInVolumeInRange {
     volume: Volume,
     range: u32units::Length,
+    #[serde(default)]
+    propagation: Propagation,
   },
 }
 
@@ -951,6 +1025,22 @@ pub enum Volume {
   AABB(AABB),
 }
 
+/// How an area effect spreads out from the origin of its volume, when there's solid terrain in the
+/// way.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Propagation {
+  /// Radiates straight out from the origin, so it only reaches what can be seen from there, like a
+  /// grenade going off.
+  Burst,
+  /// Crawls out from the origin like a gas, reaching anything in the volume that has a path back to
+  /// the origin no longer than the volume's radius. This is how D&D's fireball works.
+  WrapAround,
+}
+
+impl Default for Propagation {
+  fn default() -> Propagation { Propagation::Burst }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct AbilityStatus {
   pub ability_id: AbilityID,
@@ -993,6 +1083,9 @@ pub struct CreatureCreation {
   pub bio: String,
   pub initiative: Dice,
   pub size: AABB,
+  /// How far the creature can see in a scene with fog of war.
+  #[serde(default = "crate::creature::standard_sight")]
+  pub sight: u32units::Length,
 }
 
 /// A Creature.
@@ -1024,6 +1117,9 @@ pub struct Creature {
   pub size: AABB,
   #[serde(default)]
   pub inventory: Inventory,
+  /// How far the creature can see in a scene with fog of war.
+  #[serde(default = "crate::creature::standard_sight")]
+  pub sight: u32units::Length,
 }
 
 /// A definition of an Item, which can be referenced by creatures' inventories.
@@ -1070,6 +1166,31 @@ pub struct Game {
   // goes.
   #[serde(default)]
   pub active_scene: Option<SceneID>,
+  #[serde(default)]
+  pub vetting_policy: VettingPolicy,
+}
+
+/// Which commands from players need to be approved by the GM before they take effect.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum VettingPolicy {
+  /// Player commands always take effect immediately.
+  Never,
+  /// Only commands that affect creatures that aren't controlled by any player need approval.
+  ActionsOnNPCs,
+  /// Every command from a player needs approval.
+  AllCommands,
+}
+
+impl Default for VettingPolicy {
+  fn default() -> VettingPolicy { VettingPolicy::Never }
+}
+
+/// A command from a player that's waiting for the GM's approval.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PendingCommand {
+  pub command: GameCommand,
+  /// The result of the command, as of when it was performed.
+  pub change: ChangedGame,
 }
 
 pub struct Runtime {
@@ -1082,7 +1203,106 @@ pub struct Runtime {
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct App {
   pub current_game: Game,
+  /// The history of the current branch.
+  pub snapshots: VecDeque<(Game, Vec<GameLog>)>,
+  /// Every branch of history, including the current one.
+  #[serde(default = "crate::app::initial_branches")]
+  pub branches: Vec<Branch>,
+  /// The index of the current branch in `branches`.
+  #[serde(default)]
+  pub current_branch: usize,
+  /// Where each command in the current branch starts, as a snapshot and log index. Commands from
+  /// before this was recorded can't be undone.
+  #[serde(default)]
+  pub commands: Vec<(usize, usize)>,
+  /// How many commands at the end of the current branch have been undone. `current_game` is the
+  /// game as of the start of the first undone command.
+  #[serde(default)]
+  pub undone: usize,
+  /// All randomness in the game is derived from this seed and `rng_counter`, so a session can be
+  /// reproduced roll-for-roll.
+  #[serde(default = "crate::app::random_rng_seed")]
+  pub rng_seed: u64,
+  /// The number of commands that have been performed with the RNG so far.
+  #[serde(default)]
+  pub rng_counter: u64,
+  /// Identifies the journal that goes with this app. A new ID is picked whenever the app is saved
+  /// (see `App::new_journal_id`) and written at the start of the new journal, so that a journal
+  /// left over from before the save (if the server crashed in between) isn't replayed onto it.
+  #[serde(default)]
+  pub journal_id: u64,
+  /// Where to journal the logs of each command. See the `journal` module.
+  #[serde(skip)]
+  pub journal_path: Option<PathBuf>,
+  /// Commands from players that are waiting for the GM to approve them. These aren't saved.
+  #[serde(skip)]
+  pub pending: HashMap<PlayerID, PendingCommand>,
+}
+
+/// A timeline of the game's history. Rolling back forks a new branch, so that the history that was
+/// rolled back over is kept around and can be switched back to.
+#[derive(Clone, Default, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Branch {
+  /// Where this branch was forked from, or None for the original branch.
+  pub parent: Option<BranchPoint>,
+  /// The history of this branch, in the same form as `App::snapshots`. This is empty for the
+  /// current branch, since its history lives in `App::snapshots` instead.
   pub snapshots: VecDeque<(Game, Vec<GameLog>)>,
+  /// Like `App::commands`, and also empty for the current branch.
+  #[serde(default)]
+  pub commands: Vec<(usize, usize)>,
+}
+
+/// A point in the history of a particular branch.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BranchPoint {
+  pub branch: usize,
+  pub snapshot_idx: usize,
+  pub log_idx: usize,
+}
+
+/// A description of a branch for clients, without all of its history.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BranchInfo {
+  pub parent: Option<BranchPoint>,
+  /// The number of logs in the branch, including those from before it was forked.
+  pub log_count: usize,
+}
+
+/// A filter over the logs in an `App`'s history. Every field is optional, and a log must match
+/// all of the ones that are given. See `App::query_history`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HistoryQuery {
+  /// The first index (see `HistoryItem::index`) to include.
+  pub start: Option<usize>,
+  /// The index to stop at (exclusive).
+  pub end: Option<usize>,
+  /// Only logs that involve this creature.
+  pub creature_id: Option<CreatureID>,
+  /// Only logs of this kind: the name of a `GameLog` variant, or of the `CombatLog` or
+  /// `CreatureLog` variant it wraps, e.g. `"Damage"`.
+  pub kind: Option<String>,
+}
+
+/// A log from the history, and where it can be found.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HistoryItem {
+  /// The position of this log in the whole history, counting from the first log of the first
+  /// snapshot.
+  pub index: usize,
+  pub snapshot_idx: usize,
+  pub log_idx: usize,
+  pub log: GameLog,
+}
+
+/// A log that could no longer be applied after an earlier log was edited.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct InvalidatedLog {
+  pub snapshot_idx: usize,
+  pub log_idx: usize,
+  pub log: GameLog,
+  /// Why the log couldn't be applied.
+  pub error: String,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
@@ -1090,6 +1310,9 @@ pub struct Player {
   pub player_id: PlayerID,
   pub scene: Option<SceneID>,
   pub creatures: HashSet<CreatureID>,
+  /// The terrain that the player's creatures have seen in each scene with fog of war.
+  #[serde(default)]
+  pub explored: HashMap<SceneID, HashSet<Point3>>,
 }
 
 impl DeriveKey for Player {
@@ -1099,25 +1322,38 @@ impl DeriveKey for Player {
 
 impl Player {
   pub fn new(name: PlayerID) -> Player {
-    Player { player_id: name, scene: None, creatures: HashSet::new() }
+    Player { player_id: name, scene: None, creatures: HashSet::new(), explored: HashMap::new() }
   }
 }
 
+/// What a player can see of a scene with fog of war.
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SceneVision {
+  /// The open terrain that the player's creatures can see right now.
+  pub visible: HashSet<Point3>,
+  /// The open terrain that the player's creatures have ever seen, including what's visible now.
+  pub explored: HashSet<Point3>,
+}
+
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct SceneCreation {
   pub name: String,
   pub background_image_url: String,
   pub background_image_offset: Option<(i32, i32)>,
   pub background_image_scale: (i32, i32),
+  #[serde(default)]
+  pub fog_of_war: bool,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Scene {
   pub id: SceneID,
   pub name: String,
-  pub terrain: Vec<Point3>,
-  pub highlights: HashMap<Point3, (Color, Visibility)>,
-  pub annotations: HashMap<Point3, (String, Visibility)>,
+  // These can get big, so they're shared between copies of the scene (which is copied whenever
+  // anything in it changes).
+  pub terrain: Arc<Terrain>,
+  pub highlights: Arc<HashMap<Point3, (Color, Visibility)>>,
+  pub annotations: Arc<HashMap<Point3, (String, Visibility)>>,
 
   #[serde(default)]
   pub scene_hotspots: HashMap<Point3, SceneID>,
@@ -1138,6 +1374,11 @@ pub struct Scene {
   #[serde(default)]
   pub volume_conditions: HashMap<ConditionID, VolumeCondition>,
 
+  /// If this is true, players only see the parts of the scene that their creatures can see (or
+  /// have seen). See `vision.rs`.
+  #[serde(default)]
+  pub fog_of_war: bool,
+
   /// "Focused" creatures are those which have their portraits rendered over the scene
   /// background
   #[serde(default)]
@@ -1198,13 +1439,63 @@ pub struct DynamicCreature<'creature, 'game: 'creature> {
 pub struct RPIApp<'a>(pub &'a App);
 /// Like `RPIApp` for Game.
 pub struct RPIGame<'a>(pub &'a Game);
+/// Like `RPIApp`, but with only what a player is allowed to see. See `Game::for_player`.
+pub struct RPIPlayerApp<'a> {
+  app: &'a App,
+  player_id: &'a PlayerID,
+  game: Game,
+}
+
+impl<'a> RPIPlayerApp<'a> {
+  pub fn new(app: &'a App, player_id: &'a PlayerID) -> Result<RPIPlayerApp<'a>, GameError> {
+    Ok(RPIPlayerApp { app, player_id, game: app.current_game.for_player(player_id)? })
+  }
+
+  /// The logs in a snapshot that the player gets to see.
+  pub fn visible_logs(logs: &[GameLog]) -> Vec<&GameLog> {
+    logs.iter().filter(|log| log.visible_to_players()).collect()
+  }
+}
 
 impl<'a> Serialize for RPIApp<'a> {
   fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-    let mut str = serializer.serialize_struct("App", 2)?;
+    let mut str = serializer.serialize_struct("App", 6)?;
     let app = self.0;
     str.serialize_field("current_game", &RPIGame(&app.current_game))?;
-    str.serialize_field("snapshots", &app.snapshots)?;
+    // The history is unbounded, so only the logs are sent to the client and not the (very large)
+    // games that they're based on. The tuples are kept so that the indices still line up.
+    let snapshots: Vec<_> = app.snapshots.iter().map(|(_, logs)| ((), logs)).collect();
+    str.serialize_field("snapshots", &snapshots)?;
+    str.serialize_field("branches", &app.list_branches())?;
+    str.serialize_field("current_branch", &app.current_branch)?;
+    str.serialize_field("undone", &app.undone)?;
+    // Players only need to see what their pending commands would do.
+    let pending: HashMap<_, _> =
+      app.pending.iter().map(|(pid, pending)| (pid, &pending.change.logs)).collect();
+    str.serialize_field("pending", &pending)?;
+    str.end()
+  }
+}
+
+impl<'a> Serialize for RPIPlayerApp<'a> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut str = serializer.serialize_struct("App", 6)?;
+    let app = self.app;
+    str.serialize_field("current_game", &RPIGame(&self.game))?;
+    let snapshots: Vec<_> =
+      app.snapshots.iter().map(|(_, logs)| ((), RPIPlayerApp::visible_logs(logs))).collect();
+    str.serialize_field("snapshots", &snapshots)?;
+    // The rest of the history is only for the GM.
+    str.serialize_field("branches", &Vec::<BranchInfo>::new())?;
+    str.serialize_field("current_branch", &app.current_branch)?;
+    str.serialize_field("undone", &app.undone)?;
+    let pending: HashMap<_, _> = app
+      .pending
+      .iter()
+      .filter(|(pid, _)| *pid == self.player_id)
+      .map(|(pid, pending)| (pid, &pending.change.logs))
+      .collect();
+    str.serialize_field("pending", &pending)?;
     str.end()
   }
 }
@@ -1228,6 +1519,7 @@ impl<'a> Serialize for RPIGame<'a> {
     str.serialize_field("campaign", &game.campaign)?;
     str.serialize_field("items", &game.items)?;
     str.serialize_field("players", &game.players)?;
+    str.serialize_field("vetting_policy", &game.vetting_policy)?;
     str.end()
   }
 }
@@ -1306,16 +1598,27 @@ pub mod test {
   use crate::grid::test::*;
   use crate::types::*;
   use maplit::hashmap;
+  use rand::{rngs::StdRng, SeedableRng};
   use std::iter::FromIterator;
 
   use serde_json;
   use serde_yaml;
+
+  /// An RNG with a pinned seed, so tests can roll real dice reproducibly.
+  pub fn t_rng() -> StdRng { StdRng::seed_from_u64(0) }
+
+  /// Storage with nothing in it, for commands that don't load anything.
+  pub fn t_storage() -> ptstorage::FilesystemStorage {
+    ptstorage::FilesystemStorage::new(std::path::PathBuf::from(""), None, 0)
+  }
+
   pub fn uuid_0() -> Uuid { "00000000-0000-0000-0000-000000000000".parse().unwrap() }
   pub fn uuid_1() -> Uuid { "00000000-0000-0000-0000-000000000001".parse().unwrap() }
   pub fn uuid_2() -> Uuid { "00000000-0000-0000-0000-000000000002".parse().unwrap() }
   pub fn uuid_3() -> Uuid { "00000000-0000-0000-0000-000000000003".parse().unwrap() }
   pub fn uuid_4() -> Uuid { "00000000-0000-0000-0000-000000000004".parse().unwrap() }
   pub fn uuid_5() -> Uuid { "00000000-0000-0000-0000-000000000005".parse().unwrap() }
+  pub fn uuid_6() -> Uuid { "00000000-0000-0000-0000-000000000006".parse().unwrap() }
   pub fn cid_cleric() -> CreatureID { CreatureID(uuid_0()) }
   pub fn cid_ranger() -> CreatureID { CreatureID(uuid_1()) }
   pub fn cid_rogue() -> CreatureID { CreatureID(uuid_2()) }
@@ -1330,6 +1633,7 @@ pub mod test {
       icon_url: "".to_string(),
       initiative: Dice::flat(init),
       size: AABB { x: u32cm(100), y: u32cm(100), z: u32cm(100) },
+      sight: crate::creature::standard_sight(),
     })
   }
 
@@ -1354,9 +1658,9 @@ pub mod test {
       background_image_url: "".to_string(),
       background_image_offset: None,
       background_image_scale: (1, 1),
-      terrain: huge_box(),
-      highlights: HashMap::new(),
-      annotations: HashMap::new(),
+      terrain: Arc::new(huge_box()),
+      highlights: Default::default(),
+      annotations: Default::default(),
 
       scene_hotspots: HashMap::new(),
       related_scenes: HashSet::new(),
@@ -1369,6 +1673,7 @@ pub mod test {
       },
       inventory: HashMap::new(),
       volume_conditions: HashMap::new(),
+      fog_of_war: false,
       focused_creatures: vec![],
     }
   }
@@ -1387,6 +1692,7 @@ pub mod test {
   pub fn abid_fireball() -> AbilityID { AbilityID(uuid_3()) }
   pub fn abid_piercing_shot() -> AbilityID { AbilityID(uuid_4()) }
   pub fn abid_thorn_patch() -> AbilityID { AbilityID(uuid_5()) }
+  pub fn abid_ball_lightning() -> AbilityID { AbilityID(uuid_6()) }
 
   pub fn t_punch() -> Ability {
     Ability {
@@ -1437,6 +1743,7 @@ pub mod test {
         target: CreatureTarget::AllCreaturesInVolumeInRange {
           volume: Volume::Sphere(u32cm(1000)),
           range: u32cm(2000),
+          propagation: Propagation::Burst,
         },
         effect: CreatureEffect::Damage(Dice::flat(3)),
       },
@@ -1475,6 +1782,24 @@ pub mod test {
     }
   }
 
+  pub fn t_ball_lightning() -> Ability {
+    Ability {
+      id: abid_ball_lightning(),
+      name: "Ball Lightning".to_string(),
+      cost: Energy(8),
+      usable_ooc: true,
+      action: Action::Creature {
+        target: CreatureTarget::SomeCreaturesInVolumeInRange {
+          volume: Volume::Sphere(u32cm(200)),
+          maximum: 2,
+          range: u32cm(1000),
+          propagation: Propagation::Burst,
+        },
+        effect: CreatureEffect::Damage(Dice::flat(3)),
+      },
+    }
+  }
+
   pub fn t_abilities() -> IndexedHashMap<Ability> {
     IndexedHashMap::from_iter(vec![
       t_punch(),
@@ -1483,6 +1808,7 @@ pub mod test {
       t_fireball(),
       t_piercing_shot(),
       t_thorn_patch(),
+      t_ball_lightning(),
     ])
   }
 
@@ -1507,13 +1833,13 @@ pub mod test {
   #[test]
   fn dice_plus() {
     let d = Dice::flat(1).plus(Dice::flat(1));
-    assert_eq!(d.roll(), (vec![1, 1], 2));
+    assert_eq!(d.roll(&mut t_rng()).unwrap(), (vec![1, 1], 2));
   }
 
   #[test]
   fn dice_negative() {
     let d = Dice::flat(1).plus(Dice::flat(-5));
-    assert_eq!(d.roll(), (vec![1, -5], -4));
+    assert_eq!(d.roll(&mut t_rng()).unwrap(), (vec![1, -5], -4));
   }
 
   #[test]
@@ -1522,4 +1848,13 @@ pub mod test {
     let hm = hashmap! {p => 5};
     assert_eq!(serde_json::to_string(&hm).unwrap(), "{\"0/0/0\":5}");
   }
+
+  /// Terrain is still saved as a list of points, as it was before it became a set.
+  #[test]
+  fn serde_terrain() {
+    let terrain: Terrain = vec![Point3::new(100, 0, 0), Point3::new(0, 0, 0)].into();
+    let serialized = serde_json::to_string(&terrain).unwrap();
+    assert_eq!(serialized, "[\"0/0/0\",\"100/0/0\"]");
+    assert_eq!(serde_json::from_str::<Terrain>("[\"100/0/0\",\"0/0/0\"]").unwrap(), terrain);
+  }
 }
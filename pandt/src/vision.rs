@@ -0,0 +1,490 @@
+//! What players get to see of the game.
+//!
+//! Players only see the scene they're in, and not anything in it that's only for the GM. In a
+//! scene with `fog_of_war` turned on, they only get to see what their creatures can see: the open
+//! terrain within each creature's `sight` that isn't hidden behind solid terrain (see
+//! `TileSystem::line_of_sight`), and whatever is on it. Players also remember the terrain their
+//! creatures have seen before, which is kept in `Player::explored` as logs are applied.
+
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::sync::Arc;
+
+use foldertree::{FolderPath, FolderTree};
+use indexed::IndexedHashMap;
+use nonempty::NonEmptyWithCursor;
+
+use crate::types::*;
+
+impl Game {
+  /// Get a copy of the game with everything that a player isn't allowed to see taken out.
+  ///
+  /// The player gets the scene they're in (see `scene_for_player`), the creatures they can see in
+  /// it, their own creatures, and their own notes from the campaign (in `/Players/{player_id}`).
+  /// The GM's notes on creatures that aren't theirs and what other players have explored are left
+  /// out. If there's combat in their scene, they only get the part of it that they can see (see
+  /// `combat_for_player`).
+  pub fn for_player(&self, player_id: &PlayerID) -> Result<Game, GameError> {
+    let player = self.get_player(player_id)?;
+    let mut scenes = IndexedHashMap::new();
+    if let Some(scene_id) = player.scene.filter(|sid| self.scenes.contains_key(sid)) {
+      scenes.insert(self.scene_for_player(player_id, scene_id)?);
+    }
+
+    let mut creatures = IndexedHashMap::new();
+    for creature in self.creatures.iter() {
+      if player.creatures.contains(&creature.id) {
+        creatures.insert(creature.clone());
+      } else if scenes.iter().any(|scene| scene.creatures.contains_key(&creature.id)) {
+        creatures.insert(Creature { note: "".to_string(), ..creature.clone() });
+      }
+    }
+
+    let players = self
+      .players
+      .iter()
+      .map(|p| {
+        if p.player_id == *player_id {
+          p.clone()
+        } else {
+          Player { explored: HashMap::new(), ..p.clone() }
+        }
+      })
+      .collect();
+
+    let mut campaign = FolderTree::new(Folder::new());
+    let notes_path = FolderPath::from_vec(vec!["Players".to_string(), player_id.0.clone()]);
+    if let Ok(folder) = self.campaign.get(&notes_path) {
+      campaign.make_folders(&notes_path, Folder::new());
+      campaign.get_mut(&notes_path)?.notes = folder.notes.clone();
+    }
+
+    let current_combat = self
+      .current_combat
+      .as_ref()
+      .and_then(|combat| combat_for_player(combat, scenes.get(&combat.scene)?));
+
+    Ok(Game {
+      current_combat,
+      abilities: self.abilities.clone(),
+      creatures,
+      classes: self.classes.clone(),
+      tile_system: self.tile_system,
+      scenes,
+      items: self.items.clone(),
+      campaign,
+      players,
+      active_scene: self.active_scene,
+      vetting_policy: self.vetting_policy,
+    })
+  }
+
+  /// Work out what a player can see of a scene. In a scene without fog of war, everything is
+  /// visible.
+  pub fn player_vision(
+    &self, player_id: &PlayerID, scene_id: SceneID,
+  ) -> Result<SceneVision, GameError> {
+    let player = self.get_player(player_id)?;
+    let scene = self.get_scene(scene_id)?;
+    if !scene.fog_of_war {
+      let everything: HashSet<Point3> = scene.terrain.iter().cloned().collect();
+      return Ok(SceneVision { visible: everything.clone(), explored: everything });
+    }
+    let mut visible = HashSet::new();
+    for (cid, &(pos, _)) in &scene.creatures {
+      if player.creatures.contains(cid) {
+        visible.extend(self.points_seen_from(scene, pos, self.get_creature(*cid)?.creature.sight));
+      }
+    }
+    let mut explored = player.explored.get(&scene_id).cloned().unwrap_or_default();
+    explored.extend(visible.iter().cloned());
+    Ok(SceneVision { visible, explored })
+  }
+
+  /// Get a copy of a scene with everything that a player isn't allowed to see taken out.
+  ///
+  /// Things that only the GM can see are always taken out, unless they're the player's own
+  /// creatures. That includes the scene's attribute checks and inventory, which are the GM's to
+  /// hand out. With fog of war, the player also only gets:
+  /// - the terrain, highlights, annotations and hotspots that their creatures have explored
+  /// - the creatures and volume conditions that their creatures can see right now
+  pub fn scene_for_player(
+    &self, player_id: &PlayerID, scene_id: SceneID,
+  ) -> Result<Scene, GameError> {
+    let player = self.get_player(player_id)?;
+    let mut scene = self.get_scene(scene_id)?.clone();
+    let for_players = |vis: &Visibility| *vis == Visibility::AllPlayers;
+    scene.creatures.retain(|cid, (_, vis)| for_players(vis) || player.creatures.contains(cid));
+    let mut highlights = (*scene.highlights).clone();
+    highlights.retain(|_, (_, vis)| for_players(vis));
+    let mut annotations = (*scene.annotations).clone();
+    annotations.retain(|_, (_, vis)| for_players(vis));
+    scene.attribute_checks.clear();
+    scene.inventory.clear();
+
+    if scene.fog_of_war {
+      let vision = self.player_vision(player_id, scene_id)?;
+      let terrain = scene.terrain.iter().filter(|pt| vision.explored.contains(pt)).cloned();
+      scene.terrain = Arc::new(terrain.collect());
+      scene
+        .creatures
+        .retain(|cid, (pos, _)| player.creatures.contains(cid) || vision.visible.contains(pos));
+      highlights.retain(|pt, _| vision.explored.contains(pt));
+      annotations.retain(|pt, _| vision.explored.contains(pt));
+      scene.scene_hotspots.retain(|pt, _| vision.explored.contains(pt));
+      scene
+        .volume_conditions
+        .retain(|_, volume_condition| vision.visible.contains(&volume_condition.point));
+    }
+    scene.highlights = Arc::new(highlights);
+    scene.annotations = Arc::new(annotations);
+    Ok(scene)
+  }
+
+  /// The open terrain that a creature with some sight can see from a point, along with the point
+  /// itself.
+  fn points_seen_from(
+    &self, scene: &Scene, pos: Point3, sight: u32units::Length,
+  ) -> impl Iterator<Item = Point3> {
+    let ts = self.tile_system;
+    let seen: Vec<Point3> = ts
+      .open_points_in_range(pos, &scene.terrain, sight)
+      .into_iter()
+      .filter(|pt| ts.points_within_distance(pos, *pt, sight))
+      .filter(|pt| ts.line_of_sight(&scene.terrain, pos, *pt))
+      .collect();
+    seen.into_iter().chain(iter::once(pos))
+  }
+
+  /// Update what players have explored after a log that may have changed what their creatures can
+  /// see.
+  pub(crate) fn explore_after(&mut self, log: &GameLog) -> Result<(), GameError> {
+    match *log {
+      GameLog::SetCreaturePos(scene_id, cid, pt) => self.explore(scene_id, cid, &[pt]),
+      GameLog::PathCreature(scene_id, cid, ref pts) => self.explore(scene_id, cid, pts),
+      GameLog::AddCreatureToScene { creature_id, .. } => self.explore_around(&[creature_id]),
+      GameLog::GiveCreaturesToPlayer(_, ref cids) => self.explore_around(cids),
+      GameLog::EditSceneDetails { scene_id, .. } | GameLog::EditSceneTerrain { scene_id, .. } => {
+        let cids: Vec<CreatureID> = self.get_scene(scene_id)?.creatures.keys().cloned().collect();
+        self.explore_around(&cids)
+      }
+      _ => Ok(()),
+    }
+  }
+
+  /// Remember what some creatures can see from where they are now, in every scene they're in.
+  fn explore_around(&mut self, cids: &[CreatureID]) -> Result<(), GameError> {
+    let mut places = vec![];
+    for scene in self.scenes.iter() {
+      for cid in cids {
+        if let Some(&(pos, _)) = scene.creatures.get(cid) {
+          places.push((scene.id, *cid, pos));
+        }
+      }
+    }
+    for (scene_id, cid, pos) in places {
+      self.explore(scene_id, cid, &[pos])?;
+    }
+    Ok(())
+  }
+
+  /// Remember what a creature can see from each of some points in a scene, for every player that
+  /// controls it.
+  fn explore(
+    &mut self, scene_id: SceneID, cid: CreatureID, pts: &[Point3],
+  ) -> Result<(), GameError> {
+    let scene = self.get_scene(scene_id)?;
+    let players: Vec<PlayerID> = self
+      .players
+      .iter()
+      .filter(|p| p.creatures.contains(&cid))
+      .map(|p| p.player_id.clone())
+      .collect();
+    if !scene.fog_of_war || players.is_empty() {
+      return Ok(());
+    }
+    let sight = self.get_creature(cid)?.creature.sight;
+    let seen: HashSet<Point3> =
+      pts.iter().flat_map(|pt| self.points_seen_from(scene, *pt, sight)).collect();
+    for player_id in players {
+      self.players.mutate(&player_id, |p| {
+        p.explored.entry(scene_id).or_default().extend(seen.iter().cloned())
+      });
+    }
+    Ok(())
+  }
+}
+
+/// The part of a combat that a player can see, given the scene as they see it: just the creatures
+/// that are in it. When it's the turn of a creature they can't see, it looks to them like it's
+/// still the turn of the last one they can.
+fn combat_for_player(combat: &Combat, scene: &Scene) -> Option<Combat> {
+  let visible = |(cid, _): &&(CreatureID, i16)| scene.creatures.contains_key(cid);
+  let up_to_current = combat.creatures.iter().take(combat.creatures.get_cursor() + 1);
+  let visible_up_to_current = up_to_current.filter(visible).count();
+  let creatures = combat.creatures.iter().filter(visible).cloned().collect();
+  let mut creatures = NonEmptyWithCursor::from_vec(creatures)?;
+  let cursor = visible_up_to_current.checked_sub(1).unwrap_or(creatures.len() - 1);
+  creatures.set_cursor(cursor)?;
+  Some(Combat { creatures, ..combat.clone() })
+}
+
+#[cfg(test)]
+mod test {
+  use foldertree::FolderPath;
+  use maplit::hashmap;
+  use nonempty::NonEmptyWithCursor;
+
+  use crate::game::test::*;
+  use crate::grid::test::huge_box;
+  use crate::types::test::*;
+  use crate::types::*;
+
+  fn pid() -> PlayerID { PlayerID("bob".to_string()) }
+
+  /// A game where bob controls the rogue, who's standing at 0,0 on the west side of a wall that
+  /// runs all the way across the test scene. The ranger is on the other side of it.
+  fn foggy_game(fog_of_war: bool) -> Game {
+    let mut terrain = huge_box();
+    for y in -20..20 {
+      terrain.0.remove(&Point3::new(200, y * 100, 0));
+    }
+    let details = SceneCreation {
+      name: "Test Scene".to_string(),
+      background_image_url: "".to_string(),
+      background_image_offset: None,
+      background_image_scale: (1, 1),
+      fog_of_war,
+    };
+    let mut game = t_game();
+    for cmd in vec![
+      GameCommand::EditSceneTerrain { scene_id: t_scene_id(), terrain },
+      GameCommand::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(300, 0, 0)),
+      GameCommand::RegisterPlayer(pid()),
+      GameCommand::GiveCreaturesToPlayer(pid(), vec![cid_rogue()]),
+      GameCommand::EditSceneDetails { scene_id: t_scene_id(), details },
+    ] {
+      game = t_perform(&game, cmd);
+    }
+    game
+  }
+
+  #[test]
+  fn fog_hides_what_creatures_cant_see() {
+    let mut game = foggy_game(true);
+    let highlights = hashmap! {
+      Point3::new(100, 0, 0) => ("red".to_string(), Visibility::AllPlayers),
+      Point3::new(300, 0, 0) => ("red".to_string(), Visibility::AllPlayers),
+    };
+    game =
+      t_perform(&game, GameCommand::EditSceneHighlights { scene_id: t_scene_id(), highlights });
+
+    let scene = game.scene_for_player(&pid(), t_scene_id()).unwrap();
+    let mut cids: Vec<CreatureID> = scene.creatures.keys().cloned().collect();
+    cids.sort();
+    assert_eq!(cids, vec![cid_cleric(), cid_rogue()]);
+    assert!(scene.terrain.contains(&Point3::new(100, 0, 0)));
+    assert!(scene.terrain.contains(&Point3::new(-1000, 0, 0)));
+    // behind the wall
+    assert!(!scene.terrain.contains(&Point3::new(300, 0, 0)));
+    // too far away
+    assert!(!scene.terrain.contains(&Point3::new(-1100, 0, 0)));
+    assert_eq!(scene.highlights.keys().collect::<Vec<_>>(), vec![&Point3::new(100, 0, 0)]);
+  }
+
+  /// Players remember terrain that their creatures have seen, but not what's on it.
+  #[test]
+  fn explored_terrain_is_remembered() {
+    let game = foggy_game(true);
+    let game = t_perform(
+      &game,
+      GameCommand::SetCreaturePos(t_scene_id(), cid_cleric(), Point3::new(-500, 0, 0)),
+    );
+    let game = t_perform(
+      &game,
+      GameCommand::SetCreaturePos(t_scene_id(), cid_rogue(), Point3::new(-1500, 0, 0)),
+    );
+
+    let vision = game.player_vision(&pid(), t_scene_id()).unwrap();
+    assert!(!vision.visible.contains(&Point3::new(0, 0, 0)));
+    assert!(vision.explored.contains(&Point3::new(0, 0, 0)));
+    assert!(vision.visible.contains(&Point3::new(-500, 0, 0)));
+    assert!(vision.visible.is_subset(&vision.explored));
+
+    let scene = game.scene_for_player(&pid(), t_scene_id()).unwrap();
+    assert!(scene.terrain.contains(&Point3::new(0, 0, 0)));
+    assert!(scene.creatures.contains_key(&cid_cleric()));
+    let game = t_perform(
+      &game,
+      GameCommand::SetCreaturePos(t_scene_id(), cid_cleric(), Point3::new(100, 0, 0)),
+    );
+    let scene = game.scene_for_player(&pid(), t_scene_id()).unwrap();
+    assert!(!scene.creatures.contains_key(&cid_cleric()));
+  }
+
+  #[test]
+  fn without_fog_only_gm_only_things_are_hidden() {
+    let game = foggy_game(false);
+    let game = t_perform(
+      &game,
+      GameCommand::SetSceneCreatureVisibility {
+        scene_id: t_scene_id(),
+        creature_id: cid_cleric(),
+        visibility: Visibility::GMOnly,
+      },
+    );
+    let scene = game.scene_for_player(&pid(), t_scene_id()).unwrap();
+    let mut cids: Vec<CreatureID> = scene.creatures.keys().cloned().collect();
+    cids.sort();
+    assert_eq!(cids, vec![cid_ranger(), cid_rogue()]);
+    assert_eq!(scene.terrain, game.get_scene(t_scene_id()).unwrap().terrain);
+    assert!(game.get_player(&pid()).unwrap().explored.is_empty());
+  }
+
+  #[test]
+  fn games_for_players_only_have_their_stuff() {
+    let game = foggy_game(false);
+    let note = |name: &str| Note { name: name.to_string(), content: "secret".to_string() };
+    let bob_notes = FolderPath::from_vec(vec!["Players".to_string(), "bob".to_string()]);
+    let mut game = t_perform(&game, GameCommand::CreateFolder(bob_notes.clone()));
+    for cmd in vec![
+      GameCommand::CreateNote(bob_notes.clone(), note("Bob's Note")),
+      GameCommand::CreateNote(FolderPath::root(), note("GM Note")),
+      GameCommand::RegisterPlayer(PlayerID("alice".to_string())),
+      GameCommand::SetSceneCreatureVisibility {
+        scene_id: t_scene_id(),
+        creature_id: cid_cleric(),
+        visibility: Visibility::GMOnly,
+      },
+    ] {
+      game = t_perform(&game, cmd);
+    }
+    game.creatures.mutate(&cid_ranger(), |c| c.note = "a spy".to_string());
+    game.creatures.mutate(&cid_rogue(), |c| c.note = "mine".to_string());
+
+    // bob isn't in any scene yet
+    let bobs = game.for_player(&pid()).unwrap();
+    assert!(bobs.scenes.is_empty());
+    assert_eq!(bobs.creatures.keys().collect::<Vec<_>>(), vec![&cid_rogue()]);
+
+    let game = t_perform(&game, GameCommand::SetPlayerScene(pid(), Some(t_scene_id())));
+    let bobs = game.for_player(&pid()).unwrap();
+    assert_eq!(bobs.scenes.keys().collect::<Vec<_>>(), vec![&t_scene_id()]);
+    let mut cids: Vec<CreatureID> = bobs.creatures.keys().cloned().collect();
+    cids.sort();
+    assert_eq!(cids, vec![cid_ranger(), cid_rogue()]);
+    assert_eq!(bobs.get_creature(cid_ranger()).unwrap().creature.note, "");
+    assert_eq!(bobs.get_creature(cid_rogue()).unwrap().creature.note, "mine");
+
+    assert_eq!(bobs.campaign.get(&FolderPath::root()).unwrap().notes.len(), 0);
+    assert_eq!(bobs.campaign.get(&bob_notes).unwrap().notes.len(), 1);
+    assert_eq!(bobs.players.len(), 2);
+    match game.for_player(&PlayerID("carol".to_string())) {
+      Err(GameError::PlayerNotFound(_)) => {}
+      r => panic!("Expected PlayerNotFound, got {:?}", r),
+    }
+  }
+
+  /// Players don't find out about creatures they can't see from the order of combat.
+  #[test]
+  fn combat_only_has_creatures_players_can_see() {
+    let mut game = foggy_game(false);
+    for cmd in vec![
+      GameCommand::SetSceneCreatureVisibility {
+        scene_id: t_scene_id(),
+        creature_id: cid_cleric(),
+        visibility: Visibility::GMOnly,
+      },
+      GameCommand::SetPlayerScene(pid(), Some(t_scene_id())),
+      GameCommand::StartCombat(t_scene_id(), vec![cid_rogue(), cid_cleric(), cid_ranger()]),
+    ] {
+      game = t_perform(&game, cmd);
+    }
+    let mut combat = game.current_combat.clone().unwrap();
+    combat.creatures = NonEmptyWithCursor::new_with_rest(
+      (cid_rogue(), 20),
+      vec![(cid_cleric(), 10), (cid_ranger(), 5)],
+    );
+    combat.creatures.set_cursor(1).unwrap();
+    game.current_combat = Some(combat);
+
+    let combat = game.for_player(&pid()).unwrap().current_combat.unwrap();
+    let creatures: Vec<(CreatureID, i16)> = combat.creatures.iter().cloned().collect();
+    assert_eq!(creatures, vec![(cid_rogue(), 20), (cid_ranger(), 5)]);
+    // It's the cleric's turn, but as far as bob can tell the rogue is still going.
+    assert_eq!(combat.creatures.get_cursor(), 0);
+  }
+
+  #[test]
+  fn scene_details_are_for_the_gm() {
+    let mut game = foggy_game(true);
+    let volume_condition = |x| VolumeCondition {
+      point: Point3::new(x, 0, 0),
+      volume: Volume::Sphere(u32cm(100)),
+      remaining: Duration::Interminate,
+      condition: Condition::Dead,
+    };
+    game.scenes.mutate(&t_scene_id(), |scene| {
+      let check = AttributeCheck {
+        reliable: false,
+        attr: AttrID("perception".to_string()),
+        target: SkillLevel::Skilled,
+      };
+      scene.attribute_checks.insert("Spot the trap".to_string(), check);
+      scene.inventory.insert(ItemID::gen(), 1);
+      scene.volume_conditions.insert(ConditionID::gen(), volume_condition(100));
+      // behind the wall
+      scene.volume_conditions.insert(ConditionID::gen(), volume_condition(300));
+    });
+
+    let scene = game.scene_for_player(&pid(), t_scene_id()).unwrap();
+    assert!(scene.attribute_checks.is_empty());
+    assert!(scene.inventory.is_empty());
+    let points: Vec<Point3> = scene.volume_conditions.values().map(|vc| vc.point).collect();
+    assert_eq!(points, vec![Point3::new(100, 0, 0)]);
+  }
+
+  /// Players' movement and target options don't reach into the fog, even when the GM's would.
+  #[test]
+  fn options_stay_out_of_the_fog() {
+    let mut game = foggy_game(true);
+    for cmd in vec![
+      GameCommand::GiveCreaturesToPlayer(pid(), vec![cid_cleric()]),
+      // Within range of a fireball, but out of sight.
+      GameCommand::SetCreaturePos(t_scene_id(), cid_ranger(), Point3::new(-1500, 0, 0)),
+    ] {
+      game = t_perform(&game, cmd);
+    }
+    let explored = game.player_vision(&pid(), t_scene_id()).unwrap().explored;
+    let app = App::new(game);
+    let hidden = Point3::new(-1500, 0, 0);
+
+    let targets = |player_id| match app.get_target_options(
+      player_id,
+      t_scene_id(),
+      cid_cleric(),
+      abid_fireball(),
+    ) {
+      Ok(PotentialTargets::Points(pts)) => pts,
+      r => panic!("Unexpected result: {:?}", r),
+    };
+    assert!(targets(None).contains(&hidden));
+    let player_targets = targets(Some(&pid()));
+    assert!(player_targets.contains(&Point3::new(-500, 0, 0)));
+    assert!(!player_targets.contains(&hidden));
+
+    let preview = |player_id| {
+      let target = Point3::new(-1100, 0, 0);
+      app.preview_volume_targets(player_id, t_scene_id(), cid_cleric(), abid_fireball(), target)
+    };
+    let (cids, tiles) = preview(None).unwrap();
+    assert!(cids.contains(&cid_ranger()));
+    assert!(tiles.contains(&hidden));
+    let (cids, tiles) = preview(Some(&pid())).unwrap();
+    assert!(!cids.contains(&cid_ranger()));
+    assert!(!tiles.is_empty() && tiles.iter().all(|pt| explored.contains(pt)));
+
+    let moves = app.get_movement_options(Some(&pid()), t_scene_id(), cid_rogue()).unwrap();
+    assert!(moves.iter().all(|pt| explored.contains(pt)));
+  }
+}
@@ -1,10 +1,11 @@
+use std::collections::HashSet;
 use std::fs;
-use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
-use failure::{Error, Fail};
+use failure::{bail, Error, Fail};
 use futures::channel::oneshot;
 use log::{debug, error, info};
 use serde_json;
@@ -13,25 +14,109 @@ use tokio::sync::Mutex;
 use tokio::time::timeout;
 
 use foldertree;
-use pandt::game::load_app_from_path;
+use pandt::game::load_app;
 use pandt::types;
+use ptstorage::{check_name, Metadata, Storage};
+
+use crate::events::{Cursor, Follower, Format};
+
+/// When and how games get saved.
+#[derive(Clone, Debug, Default)]
+pub struct SavePolicy {
+  /// Autosave a session after this many commands.
+  pub autosave_commands: Option<usize>,
+  /// Autosave all the sessions this often; see `Sessions::autosave`.
+  pub autosave_interval: Option<Duration>,
+}
+
+/// The names of the saved games that sessions are playing, shared between all the sessions so
+/// that two of them don't write to the same game (and journal) at once.
+#[derive(Clone, Default)]
+pub struct SaveTargets(Arc<std::sync::Mutex<HashSet<String>>>);
 
 /// Not really an actor for now, we're just pretending.
 #[derive(Clone)]
 pub struct AppActor {
   pub app: Arc<Mutex<types::App>>,
   pub waiters: Arc<Mutex<Vec<oneshot::Sender<()>>>>,
-  pub saved_game_path: PathBuf,
-  pub module_path: Option<PathBuf>,
+  pub storage: Arc<dyn Storage>,
+  /// The saved game that this session was loaded from or last saved as, if any.
+  pub save_target: Arc<Mutex<Option<String>>>,
+  save_targets: SaveTargets,
+  policy: SavePolicy,
+  /// How many commands have been performed since the game was last saved.
+  unsaved_commands: Arc<AtomicUsize>,
+  closed: Arc<AtomicBool>,
 }
 
 impl AppActor {
-  pub fn new(app: types::App, saved_game_path: PathBuf, module_path: Option<PathBuf>) -> AppActor {
+  pub fn new(
+    app: types::App, storage: Arc<dyn Storage>, save_targets: SaveTargets, policy: SavePolicy,
+  ) -> AppActor {
     AppActor {
       app: Arc::new(Mutex::new(app)),
-      saved_game_path,
-      module_path,
+      storage,
       waiters: Arc::new(Mutex::new(vec![])),
+      save_target: Arc::new(Mutex::new(None)),
+      save_targets,
+      policy,
+      unsaved_commands: Arc::new(AtomicUsize::new(0)),
+      closed: Arc::new(AtomicBool::new(false)),
+    }
+  }
+
+  /// Make this session save to the named game from now on, as long as no other session is.
+  pub async fn set_save_target(&self, name: Option<String>) -> Result<(), Error> {
+    let mut save_target = self.save_target.lock().await;
+    if name == *save_target {
+      return Ok(());
+    }
+    let mut targets = self.save_targets.0.lock().expect("Save targets lock was poisoned");
+    if let Some(ref name) = name {
+      if !targets.insert(name.clone()) {
+        bail!(SaveTargetInUse { name: name.clone() });
+      }
+    }
+    if let Some(ref old) = *save_target {
+      targets.remove(old);
+    }
+    *save_target = name;
+    Ok(())
+  }
+
+  /// Stop the session: anyone following it is told that it's gone, and its saved game is free to
+  /// be played by another session.
+  pub async fn close(&self) -> Result<(), Error> {
+    self.closed.store(true, Ordering::SeqCst);
+    self.set_save_target(None).await?;
+    self.ping_waiters().await;
+    Ok(())
+  }
+
+  /// Save the game if anything has happened since it was last saved. Only games that have been
+  /// saved (or loaded) before are autosaved, since otherwise there's no name to save them as.
+  pub async fn autosave(&self) -> Result<(), Error> {
+    if self.unsaved_commands.load(Ordering::SeqCst) == 0 {
+      return Ok(());
+    }
+    let save_target = self.save_target.lock().await.clone();
+    match save_target {
+      Some(name) => {
+        info!("Autosaving {}", name);
+        self.save_game(name).await?;
+      }
+      None => debug!("Not autosaving a game that hasn't been saved yet"),
+    }
+    Ok(())
+  }
+
+  /// Count a command towards the next autosave, and autosave if it's due.
+  async fn count_command(&self) {
+    let unsaved = self.unsaved_commands.fetch_add(1, Ordering::SeqCst) + 1;
+    if matches!(self.policy.autosave_commands, Some(n) if unsaved >= n) {
+      if let Err(e) = self.autosave().await {
+        error!("Couldn't autosave: {}", e);
+      }
     }
   }
 
@@ -43,110 +128,242 @@ impl AppActor {
     app_to_string(&app)
   }
 
-  /// Wait for an app to change and then return it.
-  pub async fn poll_app(&self, snapshot_len: usize, log_len: usize) -> Result<String, Error> {
-    // First, if the app has already changed, return it immediately.
-    debug!("poll_app:start");
-    {
-      let app = self.app.lock().await;
-      if app.snapshots.len() != snapshot_len
-        || app.snapshots.back().map(|&(_, ref ls)| ls.len()).unwrap_or(0) != log_len
+  /// Get a player's view of the app, which leaves out anything that's only for the GM.
+  pub async fn get_player_app(&self, player_id: &types::PlayerID) -> Result<String, Error> {
+    let app = self.app.lock().await;
+    player_app_to_string(&app, player_id)
+  }
+
+  /// The next event for a client that's following the app. If the client is up to date, this
+  /// waits for the app to change, but gives up after a while with a comment that keeps the
+  /// connection alive.
+  pub async fn next_event(&self, follower: &mut Follower) -> Result<String, Error> {
+    loop {
+      // Start waiting before looking at the app, so that a change in between isn't missed.
+      let (sender, receiver) = oneshot::channel();
       {
-        return app_to_string(&app);
+        let mut waiters = self.waiters.lock().await;
+        // Followers that stopped waiting (after a keep-alive, say) leave their senders behind.
+        waiters.retain(|waiter| !waiter.is_canceled());
+        waiters.push(sender);
+      }
+      if self.closed.load(Ordering::SeqCst) {
+        bail!(SessionClosed);
+      }
+      if let Some((cursor, data)) = follower.catch_up(&*self.app.lock().await)? {
+        return Ok(format!("id: {}\ndata: {}\n\n", cursor, data));
+      }
+      if timeout(Duration::from_secs(30), receiver).await.is_err() {
+        return Ok(": keep-alive\n\n".to_string());
       }
     }
-    // Now, we wait.
-    let (sender, receiver) = oneshot::channel();
-    {
-      let mut waiters = self.waiters.lock().await;
-      waiters.push(sender);
+  }
+
+  /// What changed in the app since `cursor`, or `null` if nothing did.
+  pub async fn changes(
+    &self, player_id: Option<types::PlayerID>, cursor: Cursor, format: Format,
+  ) -> Result<String, Error> {
+    let mut follower = Follower::new(player_id, Some(cursor), format);
+    Ok(match follower.catch_up(&*self.app.lock().await)? {
+      Some((_, data)) => data,
+      None => "null".to_string(),
+    })
+  }
+
+  /// A single creature, as the GM or the given player sees it.
+  pub async fn get_creature(
+    &self, player_id: Option<types::PlayerID>, creature_id: types::CreatureID,
+  ) -> Result<String, Error> {
+    let app = self.app.lock().await;
+    match player_id {
+      None => Ok(serde_json::to_string(&app.current_game.get_creature(creature_id)?)?),
+      Some(player_id) => {
+        let game = app.current_game.for_player(&player_id)?;
+        Ok(serde_json::to_string(&game.get_creature(creature_id)?)?)
+      }
+    }
+  }
+
+  /// A single scene, as the GM or the given player sees it.
+  pub async fn get_scene(
+    &self, player_id: Option<types::PlayerID>, scene_id: types::SceneID,
+  ) -> Result<String, Error> {
+    let app = self.app.lock().await;
+    match player_id {
+      None => Ok(serde_json::to_string(app.current_game.get_scene(scene_id)?)?),
+      Some(player_id) => {
+        let game = app.current_game.for_player(&player_id)?;
+        Ok(serde_json::to_string(game.get_scene(scene_id)?)?)
+      }
     }
-    let event = timeout(Duration::from_secs(30), receiver).await;
-    event.expect("timing out failed?").expect("receiving an event failed?");
-    self.get_app().await
   }
 
   async fn ping_waiters(&self) {
     for sender in self.waiters.lock().await.drain(0..) {
-      if let Err(e) = sender.send(()) {
-        error!("ping_waiters:receiver-unavailable when sending {:?}", e);
-      }
+      // Clients that have gone away leave their receivers behind; that's fine.
+      let _ = sender.send(());
     }
   }
 
   pub async fn perform_command(&self, command: types::GameCommand) -> Result<String, Error> {
-    let module_path = self.module_path.as_ref().map(|b| b.as_path());
     let log_cmd = command.clone();
     info!("perform_command:start: {:?}", &log_cmd);
-    let result = {
+    let (ok, result) = {
       let mut app = self.app.lock().await;
-      let result = app.perform_command(command, &self.saved_game_path, module_path);
-      // Convert the rich error into a generic string error to serialize back to the client
-      let result = result.map_err(|e| format!("Error: {}", e));
-      let result = result.map(|(g, l)| (types::RPIGame(g), l));
-      serde_json::to_string(&result)?
+      let result = app.perform_command(command, &*self.storage);
+      // Convert the rich error into a generic string error to serialize back to the client.
+      // Clients find out about the new game by following the app.
+      let result = result.map(|(_, logs)| logs).map_err(|e| format!("Error: {}", e));
+      (result.is_ok(), serde_json::to_string(&result)?)
     };
     self.ping_waiters().await;
+    if ok {
+      self.count_command().await;
+    }
     debug!("perform_command:done: {:?}", &log_cmd);
     Ok(result)
   }
 
+  pub async fn perform_player_command(
+    &self, player_id: types::PlayerID, command: types::GameCommand,
+  ) -> Result<String, Error> {
+    info!("perform_player_command:start: {:?} {:?}", &player_id, &command);
+    let (ok, result) = {
+      let mut app = self.app.lock().await;
+      // Players only get back the logs that they'd see in their copy of the history.
+      let result = app
+        .perform_player_command(player_id, command, &*self.storage)
+        .map(|(_, logs)| {
+          types::RPIPlayerApp::visible_logs(&logs).into_iter().cloned().collect::<Vec<_>>()
+        })
+        .map_err(|e| format!("Error: {}", e));
+      (result.is_ok(), serde_json::to_string(&result)?)
+    };
+    // Even if the command is left pending, the GM needs to find out about it.
+    self.ping_waiters().await;
+    if ok {
+      self.count_command().await;
+    }
+    Ok(result)
+  }
+
   pub async fn movement_options(
-    &self, scene_id: types::SceneID, creature_id: types::CreatureID,
+    &self, player_id: Option<types::PlayerID>, scene_id: types::SceneID,
+    creature_id: types::CreatureID,
   ) -> Result<String, Error> {
     let app = self.app.lock().await;
-    let options = app.get_movement_options(scene_id, creature_id)?;
+    let options = app.get_movement_options(player_id.as_ref(), scene_id, creature_id)?;
     Ok(serde_json::to_string(&options)?)
   }
 
-  pub async fn combat_movement_options(&self) -> Result<String, Error> {
+  pub async fn combat_movement_options(
+    &self, player_id: Option<types::PlayerID>,
+  ) -> Result<String, Error> {
     let app = self.app.lock().await;
-    let options = app.get_combat_movement_options()?;
+    let options = app.get_combat_movement_options(player_id.as_ref())?;
     Ok(serde_json::to_string(&options)?)
   }
 
   pub async fn target_options(
-    &self, scene_id: types::SceneID, creature_id: types::CreatureID, ability_id: types::AbilityID,
+    &self, player_id: Option<types::PlayerID>, scene_id: types::SceneID,
+    creature_id: types::CreatureID, ability_id: types::AbilityID,
   ) -> Result<String, Error> {
     let app = self.app.lock().await;
-    let options = app.get_target_options(scene_id, creature_id, ability_id)?;
+    let options = app.get_target_options(player_id.as_ref(), scene_id, creature_id, ability_id)?;
     Ok(serde_json::to_string(&options)?)
   }
 
   pub async fn preview_volume_targets(
-    &self, scene_id: types::SceneID, actor_id: types::CreatureID, ability_id: types::AbilityID,
-    point: types::Point3,
+    &self, player_id: Option<types::PlayerID>, scene_id: types::SceneID,
+    actor_id: types::CreatureID, ability_id: types::AbilityID, point: types::Point3,
   ) -> Result<String, Error> {
     let app = self.app.lock().await;
-    let targets = app.preview_volume_targets(scene_id, actor_id, ability_id, point)?;
+    let targets =
+      app.preview_volume_targets(player_id.as_ref(), scene_id, actor_id, ability_id, point)?;
     Ok(serde_json::to_string(&targets)?)
   }
 
+  pub async fn history(&self, query: types::HistoryQuery) -> Result<String, Error> {
+    let app = self.app.lock().await;
+    Ok(serde_json::to_string(&app.query_history(&query))?)
+  }
+
   pub async fn load_saved_game(&self, name: String, source: types::ModuleSource) -> Result<String, Error> {
-    let module_path = self.module_path.as_ref().map(|b| b.as_path());
-    let app = load_app_from_path(&self.saved_game_path, module_path, source, &name)?;
+    // Claim the saved game before loading it, since loading it starts writing to its journal.
+    let save_target = match source {
+      types::ModuleSource::SavedGame => Some(name.clone()),
+      types::ModuleSource::Module => None,
+    };
+    let old_save_target = self.save_target.lock().await.clone();
+    self.set_save_target(save_target).await?;
+    let app = match load_app(&*self.storage, source, &name) {
+      Ok(app) => app,
+      Err(e) => {
+        self.set_save_target(old_save_target).await?;
+        return Err(e.into());
+      }
+    };
     let result = app_to_string(&app);
     *self.app.lock().await = app;
+    self.unsaved_commands.store(0, Ordering::SeqCst);
     self.ping_waiters().await;
     result
   }
 
   pub async fn save_game(&self, name: String) -> Result<String, Error> {
-    save_app(&*self.app.lock().await, &name, &self.saved_game_path)?;
+    // Check the name before claiming it, so that a bad name doesn't stay claimed.
+    check_name(&name)?;
+    self.set_save_target(Some(name.clone())).await?;
+    let mut app = self.app.lock().await;
+    app.new_journal_id();
+    save_app(&*self.storage, &app, &name)?;
+    // Everything in the old journal is now in the saved game.
+    if let Some(journal_path) = self.storage.journal_path(&name) {
+      app.start_journal(journal_path)?;
+    }
+    self.unsaved_commands.store(0, Ordering::SeqCst);
     Ok("{}".to_string())
   }
 
+  /// Export part of the game as a new saved game, unless a session is playing one by that name.
   pub async fn save_module(&self, name: String, folder_path: foldertree::FolderPath) -> Result<String, Error> {
     let new_game = self.app.lock().await.current_game.export_module(&folder_path)?;
     let new_app = types::App::new(new_game);
-    save_app(&new_app, &name, &self.saved_game_path)?;
+    // Keep the name from being claimed until the module is saved.
+    let targets = self.save_targets.0.lock().expect("Save targets lock was poisoned");
+    if targets.contains(&name) {
+      bail!(SaveTargetInUse { name });
+    }
+    save_app(&*self.storage, &new_app, &name)?;
+    // Any journal is for the game that the module replaced.
+    if let Some(journal_path) = self.storage.journal_path(&name) {
+      match fs::remove_file(journal_path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+        r => r?,
+      }
+    }
+    Ok("{}".to_string())
+  }
+
+  pub fn list_saved_games(&self, source: types::ModuleSource) -> Result<Vec<Metadata>, Error> {
+    Ok(self.storage.list(source)?)
+  }
+
+  /// Delete a saved game, unless a session is playing it.
+  pub fn delete_saved_game(&self, name: String) -> Result<String, Error> {
+    if self.save_targets.0.lock().expect("Save targets lock was poisoned").contains(&name) {
+      bail!(SaveTargetInUse { name });
+    }
+    self.storage.delete(types::ModuleSource::SavedGame, &name)?;
     Ok("{}".to_string())
   }
 
   pub async fn new_game(&self) -> Result<String, Error> {
     let new_game = Default::default();
+    self.set_save_target(None).await?;
     let mut app = self.app.lock().await;
     *app = types::App::new(new_game);
+    self.unsaved_commands.store(0, Ordering::SeqCst);
     self.ping_waiters().await;
     app_to_string(&app)
   }
@@ -156,30 +373,125 @@ fn app_to_string(app: &types::App) -> Result<String, Error> {
   Ok(serde_json::to_string(&types::RPIApp(app))?)
 }
 
-fn save_app(app: &types::App, name: &str, file_path: &PathBuf) -> Result<(), Error> {
-  let new_path = child_path(file_path, name)?;
+fn player_app_to_string(app: &types::App, player_id: &types::PlayerID) -> Result<String, Error> {
+  Ok(serde_json::to_string(&types::RPIPlayerApp::new(app, player_id)?)?)
+}
+
+fn save_app(storage: &dyn Storage, app: &types::App, name: &str) -> Result<(), Error> {
   // Note that we *don't* use RPIApp here, so we're getting plain-old-data serialization of the app,
   // without the extra magic that decorates the data with dynamic data for clients.
   let yaml = serde_yaml::to_string(app)?;
-  fs::File::create(new_path)?.write_all(yaml.as_bytes())?;
+  storage.save(types::ModuleSource::SavedGame, name, &yaml)?;
   Ok(())
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Fail, Debug)]
-#[fail(display = "Path is insecure: {}", name)]
-struct InsecurePathError {
+#[fail(display = "Another session is already playing {}", name)]
+struct SaveTargetInUse {
   name: String,
 }
 
-fn child_path(parent: &Path, name: &str) -> Result<PathBuf, InsecurePathError> {
-  if name.contains('/') || name.contains(':') || name.contains('\\') {
-    return Err(InsecurePathError { name: name.to_string() });
+#[derive(PartialEq, Eq, PartialOrd, Ord, Fail, Debug)]
+#[fail(display = "The session was closed")]
+struct SessionClosed;
+
+#[cfg(test)]
+pub mod test {
+  use std::fs;
+  use std::path::PathBuf;
+
+  use futures::executor::block_on;
+  use ptstorage::FilesystemStorage;
+
+  use super::*;
+
+  /// A fresh, empty directory to keep saved games in.
+  pub fn t_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ptrpi-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    dir
+  }
+
+  fn t_actor(dir: PathBuf, policy: SavePolicy) -> AppActor {
+    let storage = Arc::new(FilesystemStorage::new(dir, None, 0));
+    AppActor::new(types::App::new(Default::default()), storage, Default::default(), policy)
   }
-  let new_path = parent.join(name);
-  for p in &new_path {
-    if p == "." || p == ".." {
-      return Err(InsecurePathError { name: name.to_string() });
+
+  fn chat(actor: &AppActor, msg: &str) {
+    block_on(actor.perform_command(types::GameCommand::ChatFromGM(msg.to_string()))).unwrap();
+  }
+
+  #[test]
+  fn autosave_after_commands() {
+    let dir = t_dir("autosave");
+    let policy = SavePolicy { autosave_commands: Some(2), ..Default::default() };
+    let actor = t_actor(dir.clone(), policy);
+    // Games without a name aren't autosaved.
+    chat(&actor, "unnamed");
+    chat(&actor, "game");
+    assert!(!dir.join("game.yaml").exists());
+
+    block_on(actor.save_game("game.yaml".to_string())).unwrap();
+    let saved = || fs::read_to_string(dir.join("game.yaml")).unwrap();
+    chat(&actor, "hello");
+    assert!(!saved().contains("hello"));
+    chat(&actor, "there");
+    assert!(saved().contains("there"));
+  }
+
+  #[test]
+  fn games_being_played_arent_deleted() {
+    let dir = t_dir("delete");
+    let actor = t_actor(dir.clone(), Default::default());
+    block_on(actor.save_game("game.yaml".to_string())).unwrap();
+    assert!(actor.delete_saved_game("game.yaml".to_string()).is_err());
+    block_on(actor.new_game()).unwrap();
+    actor.delete_saved_game("game.yaml".to_string()).unwrap();
+    assert!(!dir.join("game.yaml").exists());
+  }
+
+  #[test]
+  fn players_only_get_back_logs_they_can_see() {
+    let actor = t_actor(t_dir("player-logs"), Default::default());
+    let alice = types::PlayerID("alice".to_string());
+    let perform = |cmd| block_on(actor.perform_player_command(alice.clone(), cmd)).unwrap();
+    assert_eq!(perform(types::GameCommand::RegisterPlayer(alice.clone())), r#"{"Ok":[]}"#);
+    let chat = perform(types::GameCommand::ChatFromPlayer(alice.clone(), "hello".to_string()));
+    assert!(chat.contains("hello"));
+  }
+
+  #[test]
+  fn followers_dont_leave_waiters_behind() {
+    let actor = t_actor(t_dir("waiters"), Default::default());
+    for _ in 0..3 {
+      let mut follower = Follower::new(None, None, Format::Logs);
+      block_on(actor.next_event(&mut follower)).unwrap();
     }
+    assert_eq!(block_on(actor.waiters.lock()).len(), 1);
+  }
+
+  #[test]
+  fn modules_replace_saved_games_and_their_journals() {
+    let dir = t_dir("module");
+    let storage = Arc::new(FilesystemStorage::new(dir.clone(), None, 0));
+    let actor = AppActor::new(
+      types::App::new(Default::default()),
+      storage.clone(),
+      Default::default(),
+      Default::default(),
+    );
+    let save_module = |actor: &AppActor| {
+      block_on(actor.save_module("game.yaml".to_string(), foldertree::FolderPath::root()))
+    };
+    block_on(actor.save_game("game.yaml".to_string())).unwrap();
+    chat(&actor, "journaled");
+    assert!(storage.journal_path("game.yaml").unwrap().exists());
+    assert!(save_module(&actor).is_err());
+
+    block_on(actor.new_game()).unwrap();
+    save_module(&actor).unwrap();
+    assert!(!storage.journal_path("game.yaml").unwrap().exists());
+    load_app(&*storage, types::ModuleSource::SavedGame, "game.yaml").unwrap();
   }
-  Ok(new_path)
 }
@@ -0,0 +1,352 @@
+//! Working out who made a request. Clients send a token in an `Authorization: Bearer ...` header
+//! (or a `token` query parameter, for event streams, which can't have headers), and the server's
+//! credentials map each token to the GM or to one of the players of the request's session.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use actix_web::dev::Payload;
+use actix_web::error::{ErrorForbidden, ErrorUnauthorized};
+use actix_web::{web, FromRequest, HttpRequest};
+use failure::Error;
+use futures::future::{ready, Ready};
+use http::header;
+use log::debug;
+use serde_derive::Deserialize;
+
+use pandt::types::PlayerID;
+
+use crate::sessions::SessionID;
+
+/// Whoever is making a request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Principal {
+  GM,
+  /// The GM of a single session, who only gets at the saved games of that session.
+  SessionGM(SessionID),
+  Player(PlayerID),
+}
+
+/// The tokens that identify the GM and the players, loaded from a YAML file like this:
+///
+/// ```yaml
+/// gm: some-secret
+/// players:
+///   alice: another-secret
+/// sessions:
+///   tuesday:
+///     gm: tuesday-secret
+///     players:
+///       bob: yet-another-secret
+/// ```
+///
+/// The top-level GM is the GM of every session, and can start and close sessions. The top-level
+/// players only play in the default session, and the GM and players listed under a session only
+/// get into that session. Saved games are shared by all the sessions, but a session's own GM only
+/// sees the ones they saved in it (see `GM::saved_game_name`), and can't change modules.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Credentials {
+  gm: String,
+  #[serde(default)]
+  players: HashMap<PlayerID, String>,
+  #[serde(default)]
+  sessions: HashMap<SessionID, SessionCredentials>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct SessionCredentials {
+  gm: Option<String>,
+  #[serde(default)]
+  players: HashMap<PlayerID, String>,
+}
+
+impl Credentials {
+  pub fn load(path: &Path) -> Result<Credentials, Error> {
+    Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+  }
+
+  fn principal(&self, session_id: &SessionID, token: &str) -> Option<Principal> {
+    let session = self.sessions.get(session_id);
+    if token == self.gm {
+      return Some(Principal::GM);
+    }
+    if session.and_then(|s| s.gm.as_ref()).map_or(false, |gm| gm == token) {
+      return Some(Principal::SessionGM(session_id.clone()));
+    }
+    let default_players = Some(&self.players).filter(|_| session_id.is_default());
+    default_players
+      .into_iter()
+      .chain(session.map(|s| &s.players))
+      .flat_map(|players| players.iter())
+      .find(|&(_, t)| t == token)
+      .map(|(pid, _)| Principal::Player(pid.clone()))
+  }
+}
+
+/// How requests get authenticated. Without any credentials, everybody is the GM, which is only
+/// suitable for a server that nobody untrusted can reach.
+#[derive(Clone)]
+pub struct Auth(pub Option<Credentials>);
+
+impl Principal {
+  /// The player making the request, or `None` for the GM.
+  pub fn player_id(self) -> Option<PlayerID> {
+    match self {
+      Principal::GM | Principal::SessionGM(_) => None,
+      Principal::Player(player_id) => Some(player_id),
+    }
+  }
+
+  /// Work out who made a request, as long as they're allowed into the session it's for.
+  pub fn authenticate(req: &HttpRequest) -> Result<Principal, actix_web::Error> {
+    let auth = req
+      .app_data::<web::Data<Auth>>()
+      .ok_or_else(|| ErrorUnauthorized("Authentication isn't configured"))?;
+    let principal = match auth.0 {
+      None => Principal::GM,
+      Some(ref credentials) => {
+        let query = web::Query::<HashMap<String, String>>::from_query(req.query_string()).ok();
+        let token = req
+          .headers()
+          .get(header::AUTHORIZATION)
+          .and_then(|value| value.to_str().ok())
+          .and_then(|value| value.strip_prefix("Bearer "))
+          .or_else(|| query.as_ref().and_then(|q| q.get("token")).map(|t| t.as_str()))
+          .ok_or_else(|| ErrorUnauthorized("A token is required"))?;
+        let session_id = SessionID::of_request(req);
+        credentials
+          .principal(&session_id, token)
+          .ok_or_else(|| ErrorUnauthorized("Unknown token for this session"))?
+      }
+    };
+    debug!("{} {} by {:?}", req.method(), req.path(), principal);
+    Ok(principal)
+  }
+}
+
+impl FromRequest for Principal {
+  type Error = actix_web::Error;
+  type Future = Ready<Result<Principal, actix_web::Error>>;
+  type Config = ();
+
+  fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+    ready(Principal::authenticate(req))
+  }
+}
+
+/// What goes between a session's ID and the name of a saved game that its GM saved.
+const SESSION_SEPARATOR: char = '@';
+
+/// Only lets a GM make a request: either the top-level GM, or the GM of the request's session,
+/// whose session is given.
+pub struct GM(pub Option<SessionID>);
+
+impl GM {
+  /// The name that a saved game which this GM calls `name` is kept under. A session GM's saved
+  /// games are named after the session, so that they're kept apart from other campaigns'.
+  pub fn saved_game_name(&self, name: String) -> String {
+    match self.0 {
+      None => name,
+      Some(ref session_id) => format!("{}{}{}", session_id.0, SESSION_SEPARATOR, name),
+    }
+  }
+
+  /// What this GM calls the saved game kept under `name`, if it's one of theirs.
+  pub fn own_saved_game(&self, name: String) -> Option<String> {
+    match self.0 {
+      None => Some(name),
+      Some(ref session_id) => name
+        .strip_prefix(&session_id.0)
+        .and_then(|rest| rest.strip_prefix(SESSION_SEPARATOR))
+        .map(|rest| rest.to_string()),
+    }
+  }
+}
+
+impl FromRequest for GM {
+  type Error = actix_web::Error;
+  type Future = Ready<Result<GM, actix_web::Error>>;
+  type Config = ();
+
+  fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+    ready(Principal::authenticate(req).and_then(|principal| match principal {
+      Principal::GM => Ok(GM(None)),
+      Principal::SessionGM(session_id) => Ok(GM(Some(session_id))),
+      Principal::Player(_) => Err(ErrorForbidden("Only the GM can do that")),
+    }))
+  }
+}
+
+/// Only lets the top-level GM make a request, for things that affect every session.
+pub struct ServerGM;
+
+impl FromRequest for ServerGM {
+  type Error = actix_web::Error;
+  type Future = Ready<Result<ServerGM, actix_web::Error>>;
+  type Config = ();
+
+  fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+    ready(Principal::authenticate(req).and_then(|principal| match principal {
+      Principal::GM => Ok(ServerGM),
+      _ => Err(ErrorForbidden("Only the server's GM can do that")),
+    }))
+  }
+}
+
+/// The player named by the `player_id` in a request's path. Only that player (or the GM, on their
+/// behalf) can make the request.
+pub struct ActingPlayer(pub PlayerID);
+
+impl FromRequest for ActingPlayer {
+  type Error = actix_web::Error;
+  type Future = Ready<Result<ActingPlayer, actix_web::Error>>;
+  type Config = ();
+
+  fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+    ready(Principal::authenticate(req).and_then(|principal| {
+      let player_id = PlayerID(req.match_info().query("player_id").to_string());
+      match principal {
+        Principal::GM | Principal::SessionGM(_) => Ok(ActingPlayer(player_id)),
+        Principal::Player(ref pid) if *pid == player_id => Ok(ActingPlayer(player_id)),
+        Principal::Player(_) => Err(ErrorForbidden("You can't act as another player")),
+      }
+    }))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use actix_web::test::TestRequest;
+
+  use super::*;
+
+  fn t_auth() -> Auth {
+    Auth(Some(
+      serde_yaml::from_str("gm: gm-secret\nplayers:\n  alice: alice-secret\n").unwrap(),
+    ))
+  }
+
+  fn request(auth: Auth, token: Option<&str>) -> HttpRequest {
+    request_as(TestRequest::default().data(auth), token)
+  }
+
+  fn request_as(req: TestRequest, token: Option<&str>) -> HttpRequest {
+    match token {
+      Some(token) => req.header(header::AUTHORIZATION, format!("Bearer {}", token)),
+      None => req,
+    }
+    .to_http_request()
+  }
+
+  #[test]
+  fn tokens_identify_principals() {
+    let principal = |token| Principal::authenticate(&request(t_auth(), token)).ok();
+    assert_eq!(principal(Some("gm-secret")), Some(Principal::GM));
+    assert_eq!(
+      principal(Some("alice-secret")),
+      Some(Principal::Player(PlayerID("alice".to_string())))
+    );
+    assert_eq!(principal(Some("guess")), None);
+    assert_eq!(principal(None), None);
+  }
+
+  #[test]
+  fn everyone_is_the_gm_without_credentials() {
+    let req = request(Auth(None), None);
+    assert_eq!(Principal::authenticate(&req).ok(), Some(Principal::GM));
+  }
+
+  #[test]
+  fn players_only_act_as_themselves() {
+    let acting = |token, pid: &'static str| {
+      let req = TestRequest::default().data(t_auth()).param("player_id", pid);
+      let req = request_as(req, Some(token));
+      futures::executor::block_on(ActingPlayer::extract(&req)).ok().map(|ActingPlayer(p)| p.0)
+    };
+    assert_eq!(acting("alice-secret", "alice"), Some("alice".to_string()));
+    assert_eq!(acting("alice-secret", "bob"), None);
+    assert_eq!(acting("gm-secret", "bob"), Some("bob".to_string()));
+    let gm = |token| {
+      futures::executor::block_on(GM::extract(&request(t_auth(), Some(token)))).is_ok()
+    };
+    assert!(gm("gm-secret"));
+    assert!(!gm("alice-secret"));
+  }
+
+  #[test]
+  fn tokens_are_for_sessions() {
+    let auth = Auth(Some(
+      serde_yaml::from_str(
+        "gm: gm-secret
+players:
+  alice: alice-secret
+sessions:
+  tuesday:
+    gm: tuesday-secret
+    players:
+      bob: bob-secret
+",
+      )
+      .unwrap(),
+    ));
+    let principal = |session: Option<&'static str>, token| {
+      let req = TestRequest::default().data(auth.clone());
+      let req = match session {
+        Some(session) => req.param("session_id", session),
+        None => req,
+      };
+      Principal::authenticate(&request_as(req, Some(token))).ok()
+    };
+    let player = |pid: &str| Some(Principal::Player(PlayerID(pid.to_string())));
+    assert_eq!(principal(None, "alice-secret"), player("alice"));
+    assert_eq!(principal(Some("tuesday"), "alice-secret"), None);
+    assert_eq!(principal(Some("tuesday"), "bob-secret"), player("bob"));
+    assert_eq!(principal(None, "bob-secret"), None);
+    assert_eq!(principal(Some("friday"), "bob-secret"), None);
+    assert_eq!(
+      principal(Some("tuesday"), "tuesday-secret"),
+      Some(Principal::SessionGM(SessionID("tuesday".to_string())))
+    );
+    assert_eq!(principal(None, "tuesday-secret"), None);
+    assert_eq!(principal(Some("friday"), "gm-secret"), Some(Principal::GM));
+  }
+
+  #[test]
+  fn session_gms_only_get_into_their_session() {
+    let auth = Auth(Some(
+      serde_yaml::from_str(
+        "gm: gm-secret
+sessions:
+  tuesday:
+    gm: tuesday-secret
+",
+      )
+      .unwrap(),
+    ));
+    let req = |token| {
+      request_as(TestRequest::default().data(auth.clone()).param("session_id", "tuesday"), token)
+    };
+    let gm = |token| futures::executor::block_on(GM::extract(&req(Some(token)))).ok().map(|g| g.0);
+    let server_gm =
+      |token| futures::executor::block_on(ServerGM::extract(&req(Some(token)))).is_ok();
+    assert_eq!(gm("tuesday-secret"), Some(Some(SessionID("tuesday".to_string()))));
+    assert_eq!(gm("gm-secret"), Some(None));
+    assert!(!server_gm("tuesday-secret"));
+    assert!(server_gm("gm-secret"));
+  }
+
+  #[test]
+  fn session_gms_have_their_own_saved_games() {
+    let tuesday = GM(Some(SessionID("tuesday".to_string())));
+    assert_eq!(tuesday.saved_game_name("game.yaml".to_string()), "tuesday@game.yaml");
+    assert_eq!(tuesday.own_saved_game("tuesday@game.yaml".to_string()), Some("game.yaml".into()));
+    assert_eq!(tuesday.own_saved_game("game.yaml".to_string()), None);
+    assert_eq!(tuesday.own_saved_game("friday@game.yaml".to_string()), None);
+    assert_eq!(tuesday.own_saved_game("tuesday2@game.yaml".to_string()), None);
+    // The top-level GM sees every saved game by its real name.
+    assert_eq!(GM(None).saved_game_name("game.yaml".to_string()), "game.yaml");
+    let name = "tuesday@game.yaml".to_string();
+    assert_eq!(GM(None).own_saved_game(name.clone()), Some(name));
+  }
+}
@@ -0,0 +1,337 @@
+//! Pushing changes to the app out to clients as server-sent events, so that they don't have to
+//! keep downloading the whole app.
+//!
+//! The ID of each event is a `Cursor` saying how far through the app's history a client has got.
+//! Clients start following from the cursor of the app they already have (or, when their browser
+//! reconnects, from the `Last-Event-ID` it sends), and then get:
+//!
+//! - `Logs` events with the logs that were added to the history since, any commands waiting for
+//!   the GM's approval and (unless the client asked for just the logs) a patch to bring their copy
+//!   of the game up to date; or
+//! - an `App` event with the whole app, when the history changed in a way that new logs can't
+//!   describe, like undoing, switching branches or loading another game.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use failure::{format_err, Error};
+use serde::Serialize;
+use serde_derive::Deserialize;
+use serde_json::Value;
+
+use pandt::types::{App, GameLog, PlayerID, RPIApp, RPIGame, RPIPlayerApp};
+
+use crate::patch::{diff, Operation};
+
+/// How far through the app's history a client is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cursor {
+  pub branch: usize,
+  pub undone: usize,
+  pub snapshot_len: usize,
+  /// The number of logs in the last snapshot that the client has. This counts all of the logs,
+  /// even for players who can't see most of them, since those still change their game.
+  pub log_len: usize,
+}
+
+impl fmt::Display for Cursor {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}.{}.{}.{}", self.branch, self.undone, self.snapshot_len, self.log_len)
+  }
+}
+
+impl FromStr for Cursor {
+  type Err = Error;
+  fn from_str(s: &str) -> Result<Cursor, Error> {
+    let parts = s.split('.').map(|p| p.parse()).collect::<Result<Vec<usize>, _>>()?;
+    match *parts.as_slice() {
+      [branch, undone, snapshot_len, log_len] => {
+        Ok(Cursor { branch, undone, snapshot_len, log_len })
+      }
+      _ => Err(format_err!("Invalid cursor {:?}", s)),
+    }
+  }
+}
+
+/// How clients want to hear about new logs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Format {
+  /// Just the logs, for clients that can apply them to the game themselves.
+  Logs,
+  /// The logs along with a JSON Patch (see the `patch` module) of the game, for clients that
+  /// can't.
+  #[default]
+  Patch,
+}
+
+#[derive(Serialize)]
+enum Event<'a, A: Serialize> {
+  App(A),
+  Logs {
+    cursor: String,
+    logs: &'a [&'a GameLog],
+    pending: &'a Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    patch: Option<Vec<Operation>>,
+  },
+}
+
+/// A client that's following the app, either as the GM or as a player.
+pub struct Follower {
+  player_id: Option<PlayerID>,
+  format: Format,
+  /// How far through the history the client has got, if it's said.
+  cursor: Option<Cursor>,
+  /// The pending commands that the client knows about, which aren't part of the history.
+  pending: Option<Value>,
+  /// The client's copy of the game, once it's known.
+  game: Option<Value>,
+}
+
+impl Follower {
+  pub fn new(player_id: Option<PlayerID>, cursor: Option<Cursor>, format: Format) -> Follower {
+    Follower { player_id, format, cursor, pending: None, game: None }
+  }
+
+  /// The event that brings this follower up to date, if it isn't already, along with the cursor
+  /// that it brings them to.
+  pub fn catch_up(&mut self, app: &App) -> Result<Option<(Cursor, String)>, Error> {
+    let logs: &[GameLog] = match app.snapshots.back() {
+      None => &[],
+      Some((_, logs)) => logs,
+    };
+    let pending: HashMap<&PlayerID, &Vec<GameLog>> = app
+      .pending
+      .iter()
+      .filter(|&(pid, _)| self.player_id.iter().all(|player_id| player_id == pid))
+      .map(|(pid, pending)| (pid, &pending.change.logs))
+      .collect();
+    let pending = serde_json::to_value(&pending)?;
+    let cursor = Cursor {
+      branch: app.current_branch,
+      undone: app.undone,
+      snapshot_len: app.snapshots.len(),
+      log_len: logs.len(),
+    };
+    if self.cursor == Some(cursor) && self.pending.as_ref() == Some(&pending) {
+      return Ok(None);
+    }
+
+    // Logs are only ever added to the end of the last snapshot; anything else needs the whole app.
+    let old_cursor = self.cursor.filter(|old| {
+      Cursor { log_len: old.log_len, ..cursor } == *old && old.log_len <= cursor.log_len
+    });
+    let data = match (old_cursor, self.format) {
+      (Some(old_cursor), Format::Logs) => {
+        let new_logs = &logs[old_cursor.log_len..];
+        let visible = self.visible_logs(new_logs);
+        // Players can't apply logs they can't see, so they need the whole app instead.
+        if visible.len() == new_logs.len() {
+          Some(logs_event(cursor, &visible, &pending, None)?)
+        } else {
+          None
+        }
+      }
+      (Some(old_cursor), Format::Patch) => {
+        let game = self.game_json(app)?;
+        let old_game = self.old_game_json(app, old_cursor, cursor, &game)?;
+        let patch = old_game.map(|old| diff(&old, &game));
+        self.game = Some(game);
+        match patch {
+          Some(patch) => {
+            let visible = self.visible_logs(&logs[old_cursor.log_len..]);
+            Some(logs_event(cursor, &visible, &pending, Some(patch))?)
+          }
+          None => None,
+        }
+      }
+      (None, _) => None,
+    };
+    let data = match data {
+      Some(data) => data,
+      None => self.app_event(app)?,
+    };
+    self.cursor = Some(cursor);
+    self.pending = Some(pending);
+    Ok(Some((cursor, data)))
+  }
+
+  /// The logs that this follower is allowed to see.
+  fn visible_logs<'a>(&self, logs: &'a [GameLog]) -> Vec<&'a GameLog> {
+    match self.player_id {
+      None => logs.iter().collect(),
+      Some(_) => RPIPlayerApp::visible_logs(logs),
+    }
+  }
+
+  fn app_event(&mut self, app: &App) -> Result<String, Error> {
+    if self.format == Format::Patch {
+      self.game = Some(self.game_json(app)?);
+    }
+    Ok(match self.player_id {
+      None => serde_json::to_string(&Event::App(RPIApp(app)))?,
+      Some(ref player_id) => {
+        serde_json::to_string(&Event::App(RPIPlayerApp::new(app, player_id)?))?
+      }
+    })
+  }
+
+  /// The follower's view of the current game, as JSON.
+  fn game_json(&self, app: &App) -> Result<Value, Error> {
+    Ok(match self.player_id {
+      None => serde_json::to_value(RPIGame(&app.current_game))?,
+      Some(ref player_id) => {
+        serde_json::to_value(RPIGame(&app.current_game.for_player(player_id)?))?
+      }
+    })
+  }
+
+  /// The game that the client had at `old_cursor`, as JSON, if it can be worked out.
+  fn old_game_json(
+    &mut self, app: &App, old_cursor: Cursor, cursor: Cursor, game: &Value,
+  ) -> Result<Option<Value>, Error> {
+    if let Some(old) = self.game.take() {
+      return Ok(Some(old));
+    }
+    if old_cursor == cursor {
+      return Ok(Some(game.clone()));
+    }
+    if old_cursor.undone > 0 || old_cursor.snapshot_len == 0 {
+      return Ok(None);
+    }
+    // Cursors count all of the logs, so players' old games can be rebuilt from the history too.
+    let old = app.game_at(old_cursor.snapshot_len - 1, old_cursor.log_len)?;
+    Ok(Some(match self.player_id {
+      None => serde_json::to_value(RPIGame(&old))?,
+      Some(ref player_id) => serde_json::to_value(RPIGame(&old.for_player(player_id)?))?,
+    }))
+  }
+}
+
+fn logs_event(
+  cursor: Cursor, logs: &[&GameLog], pending: &Value, patch: Option<Vec<Operation>>,
+) -> Result<String, Error> {
+  let event: Event<()> = Event::Logs { cursor: cursor.to_string(), logs, pending, patch };
+  Ok(serde_json::to_string(&event)?)
+}
+
+#[cfg(test)]
+mod test {
+  use std::path::PathBuf;
+
+  use pandt::types::{App, CreatureID, GameCommand, Point3, Visibility};
+  use ptstorage::FilesystemStorage;
+
+  use super::*;
+
+  fn t_storage() -> FilesystemStorage { FilesystemStorage::new(PathBuf::from(""), None, 0) }
+
+  fn chat(app: &mut App, msg: &str) {
+    app.perform_command(GameCommand::ChatFromGM(msg.to_string()), &t_storage()).unwrap();
+  }
+
+  fn event(follower: &mut Follower, app: &App) -> Option<serde_json::Value> {
+    let (_, data) = follower.catch_up(app).unwrap()?;
+    Some(serde_json::from_str(&data).unwrap())
+  }
+
+  #[test]
+  fn cursors_round_trip() {
+    let cursor = Cursor { branch: 1, undone: 2, snapshot_len: 3, log_len: 4 };
+    assert_eq!(cursor.to_string().parse::<Cursor>().unwrap(), cursor);
+    assert!("1.2.3".parse::<Cursor>().is_err());
+  }
+
+  #[test]
+  fn followers_get_new_logs() {
+    let mut app = App::new(Default::default());
+    chat(&mut app, "hello");
+    let cursor = Cursor { branch: 0, undone: 0, snapshot_len: 1, log_len: 1 };
+    let mut follower = Follower::new(None, Some(cursor), Format::Logs);
+    // The follower doesn't know what's pending yet.
+    assert_eq!(event(&mut follower, &app).unwrap()["Logs"]["logs"], serde_json::json!([]));
+    assert_eq!(event(&mut follower, &app), None);
+
+    chat(&mut app, "there");
+    chat(&mut app, "friends");
+    let logs = &event(&mut follower, &app).unwrap()["Logs"]["logs"];
+    assert_eq!(logs, &serde_json::json!([{"ChatFromGM": "there"}, {"ChatFromGM": "friends"}]));
+    assert_eq!(event(&mut follower, &app), None);
+
+    // Undoing can't be described with new logs.
+    app.perform_command(GameCommand::Undo, &t_storage()).unwrap();
+    let app_event = event(&mut follower, &app).unwrap();
+    assert_eq!(app_event["App"]["undone"], serde_json::json!(1));
+  }
+
+  #[test]
+  fn followers_from_elsewhere_start_over() {
+    let mut app = App::new(Default::default());
+    chat(&mut app, "hello");
+    let cursor = Cursor { branch: 3, undone: 0, snapshot_len: 1, log_len: 0 };
+    let mut follower = Follower::new(None, Some(cursor), Format::Logs);
+    assert!(event(&mut follower, &app).unwrap().get("App").is_some());
+    let mut follower = Follower::new(None, None, Format::Logs);
+    assert!(event(&mut follower, &app).unwrap().get("App").is_some());
+  }
+
+  #[test]
+  fn followers_get_patches() {
+    let mut app = App::new(Default::default());
+    chat(&mut app, "hello");
+    let cursor = Cursor { branch: 0, undone: 0, snapshot_len: 1, log_len: 0 };
+    let mut follower = Follower::new(None, Some(cursor), Format::Patch);
+    // The GM's old game can be worked out from the history.
+    let logs_event = &event(&mut follower, &app).unwrap()["Logs"];
+    assert_eq!(logs_event["cursor"], serde_json::json!("0.0.1.1"));
+    assert_eq!(logs_event["logs"], serde_json::json!([{"ChatFromGM": "hello"}]));
+    assert_eq!(logs_event["patch"], serde_json::json!([]));
+
+    let folder = GameCommand::CreateFolder("/Notes".parse().unwrap());
+    app.perform_command(folder, &t_storage()).unwrap();
+    let patch = &event(&mut follower, &app).unwrap()["Logs"]["patch"];
+    let ops = patch.as_array().unwrap();
+    assert!(!ops.is_empty());
+    assert!(ops.iter().all(|op| op["path"].as_str().unwrap().starts_with("/campaign")));
+  }
+
+  /// Players hear about changes to their game even when they can't see the logs for them.
+  #[test]
+  fn players_get_hidden_changes() {
+    let yaml = std::fs::read_to_string("sample_games/samplegame.yaml").unwrap();
+    let mut app: App = serde_yaml::from_str(&yaml).unwrap();
+    let for_players = |(_, (_, vis)): &(&CreatureID, &(Point3, Visibility))| {
+      *vis == Visibility::AllPlayers
+    };
+    let (scene_id, cid) = app
+      .current_game
+      .scenes
+      .iter()
+      .find_map(|scene| scene.creatures.iter().find(for_players).map(|(cid, _)| (scene.id, *cid)))
+      .unwrap();
+    let alice = PlayerID("alice".to_string());
+    app.perform_command(GameCommand::RegisterPlayer(alice.clone()), &t_storage()).unwrap();
+    let set_scene = GameCommand::SetPlayerScene(alice.clone(), Some(scene_id));
+    app.perform_command(set_scene, &t_storage()).unwrap();
+    let mut follower = Follower::new(Some(alice.clone()), None, Format::Patch);
+    let mut logs_follower = Follower::new(Some(alice.clone()), None, Format::Logs);
+    assert!(event(&mut follower, &app).unwrap().get("App").is_some());
+    assert!(event(&mut logs_follower, &app).unwrap().get("App").is_some());
+
+    let pos = GameCommand::SetCreaturePos(scene_id, cid, Point3::new(1000, 0, 0));
+    app.perform_command(pos, &t_storage()).unwrap();
+    let logs_event = &event(&mut follower, &app).unwrap()["Logs"];
+    assert_eq!(logs_event["logs"], serde_json::json!([]));
+    assert!(!logs_event["patch"].as_array().unwrap().is_empty());
+    // Players that only get logs can't work out the change from them, so they get the whole app.
+    assert!(event(&mut logs_follower, &app).unwrap().get("App").is_some());
+
+    // Players asking for the changes since an earlier cursor get a patch too.
+    let cursor = follower.cursor.unwrap();
+    let old_cursor = Cursor { log_len: cursor.log_len - 1, ..cursor };
+    let mut follower = Follower::new(Some(alice), Some(old_cursor), Format::Patch);
+    let logs_event = &event(&mut follower, &app).unwrap()["Logs"];
+    assert!(!logs_event["patch"].as_array().unwrap().is_empty());
+  }
+}
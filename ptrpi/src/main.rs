@@ -2,18 +2,29 @@
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::needless_pass_by_value))]
 
 mod actor;
+mod auth;
+mod events;
+mod patch;
+mod sessions;
 mod web;
 
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 use actix_web::{App as WebApp, middleware::Logger};
-use log::info;
+use log::{info, warn};
 use structopt::StructOpt;
 
-use pandt::game::load_app_from_path;
+use pandt::game::load_app;
 use pandt::types::{App, ModuleSource};
+use ptstorage::{FilesystemStorage, Storage};
+
+/// Like actix's default request log, but with only the path of each request, since tokens can be
+/// in the query string (see `auth`), and without the referer, whose query string can have them too.
+const LOG_FORMAT: &str = r#"%a "%U" %s %b "%{User-Agent}i" %T"#;
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
@@ -25,54 +36,175 @@ async fn main() -> std::io::Result<()> {
 
   info!("Starting up the P&T Remote Programming Interface HTTP server!");
   let opts = Opts::from_args();
-  let saved_game_path =
-    fs::canonicalize(opts.saved_game_path).expect("Couldn't canonicalize game dir");
-  let module_path =
-    opts.module_path.map(|p| fs::canonicalize(p).expect("Couldn't canonicalize module dir"));
+  let storage = open_storage(&opts);
+  import_modules(&*storage, &opts.import_modules).expect("Couldn't import modules");
 
   let app = match opts.load_game {
-    Some(initial_file) => {
-      load_app_from_path(&saved_game_path, None, ModuleSource::SavedGame, &initial_file)
+    Some(ref initial_file) => {
+      load_app(&*storage, ModuleSource::SavedGame, initial_file)
         .expect("Couldn't load app from file")
     }
     None => App::new(Default::default()),
   };
 
-  let actor = actor::AppActor::new(app, saved_game_path.clone(), module_path.clone());
-  let server =
-    actix_web::HttpServer::new(move || WebApp::new().wrap(Logger::default()).configure(|c| web::router(actor.clone(), c)));
-  server.bind("0.0.0.0:1337")?.run().await
+  let credentials = opts
+    .credentials
+    .map(|p| auth::Credentials::load(&p).expect("Couldn't load credentials"));
+  if credentials.is_none() {
+    warn!("No credentials were given, so anyone who can reach the server is the GM!");
+  }
+  let auth = auth::Auth(credentials);
+
+  let policy = actor::SavePolicy {
+    autosave_commands: opts.autosave_commands,
+    autosave_interval: opts.autosave_minutes.map(|minutes| Duration::from_secs(minutes * 60)),
+  };
+  let sessions = sessions::Sessions::new(storage, policy.clone());
+  let actor = sessions
+    .create(sessions::SessionID::default_session(), app)
+    .expect("Couldn't create the default session");
+  actor.set_save_target(opts.load_game).await.expect("Couldn't claim the loaded game");
+  if let Some(interval) = policy.autosave_interval {
+    let sessions = sessions.clone();
+    actix_rt::spawn(async move {
+      let mut ticks = tokio::time::interval(interval);
+      // The first tick is immediate, and there's nothing to save yet.
+      ticks.tick().await;
+      loop {
+        ticks.tick().await;
+        sessions.autosave().await;
+      }
+    });
+  }
+
+  let web_sessions = sessions.clone();
+  let server = actix_web::HttpServer::new(move || {
+    WebApp::new()
+      .wrap(Logger::new(LOG_FORMAT))
+      .configure(|c| web::router(web_sessions.clone(), auth.clone(), c))
+  });
+  let result = server.bind("0.0.0.0:1337")?.run().await;
+  // The server stops gracefully when it's interrupted or terminated, leaving time to save.
+  info!("Saving games before shutting down");
+  sessions.autosave().await;
+  result
+}
+
+/// Keep games in a SQLite database if one was given, and otherwise in directories.
+fn open_storage(opts: &Opts) -> Arc<dyn Storage> {
+  if let Some(ref database) = opts.sqlite {
+    return open_sqlite(database, opts.backups);
+  }
+  let saved_game_path = opts.saved_game_path.as_ref().expect("--saved-games is required");
+  let saved_game_path =
+    fs::canonicalize(saved_game_path).expect("Couldn't canonicalize game dir");
+  let module_path = opts
+    .module_path
+    .as_ref()
+    .map(|p| fs::canonicalize(p).expect("Couldn't canonicalize module dir"));
+  Arc::new(FilesystemStorage::new(saved_game_path, module_path, opts.backups))
+}
+
+/// Copy games from files into the storage's modules, named after the files. This is how modules get
+/// into a SQLite database, which has no directory of them.
+fn import_modules(storage: &dyn Storage, paths: &[PathBuf]) -> Result<(), failure::Error> {
+  for path in paths {
+    let name = path
+      .file_name()
+      .and_then(|name| name.to_str())
+      .ok_or_else(|| failure::format_err!("Can't name a module after {:?}", path))?;
+    let yaml = fs::read_to_string(path)?;
+    // Check that it's really a game, so it can be loaded later.
+    let app: App = serde_yaml::from_str(&yaml)?;
+    app.current_game.validate_campaign()?;
+    storage.save(ModuleSource::Module, name, &yaml)?;
+    info!("Imported module {}", name);
+  }
+  Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn open_sqlite(database: &Path, backups: usize) -> Arc<dyn Storage> {
+  warn!("Games in SQLite aren't journaled, so a crash loses the commands since they were saved");
+  Arc::new(ptstorage::SqliteStorage::open(database, backups).expect("Couldn't open database"))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_sqlite(_database: &Path, _backups: usize) -> Arc<dyn Storage> {
+  panic!("This server was built without SQLite support; build it with `--features sqlite`")
 }
 
 #[derive(StructOpt)]
 #[structopt(name = "basic")]
 struct Opts {
   /// The directory where saved games should be stored
-  #[structopt(long = "saved-games", parse(from_os_str))]
-  saved_game_path: PathBuf,
+  #[structopt(long = "saved-games", parse(from_os_str), required_unless = "sqlite")]
+  saved_game_path: Option<PathBuf>,
 
   /// The directory where read-only modules should be loaded from
   #[structopt(long = "modules", parse(from_os_str))]
   module_path: Option<PathBuf>,
 
+  /// A SQLite database to keep saved games and modules in, instead of directories
+  #[structopt(
+    long = "sqlite",
+    parse(from_os_str),
+    conflicts_with_all = &["saved_game_path", "module_path"]
+  )]
+  sqlite: Option<PathBuf>,
+
+  /// A game to copy into the modules before starting; may be given more than once
+  #[structopt(long = "import-module", parse(from_os_str))]
+  import_modules: Vec<PathBuf>,
+
   #[structopt(long = "load-game")]
   load_game: Option<String>,
+
+  /// Autosave games after this many commands
+  #[structopt(long = "autosave-commands")]
+  autosave_commands: Option<usize>,
+
+  /// Autosave games this many minutes apart
+  #[structopt(long = "autosave-minutes")]
+  autosave_minutes: Option<u64>,
+
+  /// How many old versions of each saved game to keep
+  #[structopt(long = "backups", default_value = "3")]
+  backups: usize,
+
+  /// A YAML file with the tokens that identify the GM and players (see `auth::Credentials`)
+  #[structopt(long = "credentials", parse(from_os_str))]
+  credentials: Option<PathBuf>,
 }
 
 #[cfg(test)]
 mod test {
-  use std::path::Path;
+  use std::fs;
+  use std::path::PathBuf;
 
   use pandt::types::ModuleSource;
+  use ptstorage::{FilesystemStorage, Storage};
 
   #[test]
   fn load_samplegame_yaml() {
-    crate::load_app_from_path(
-      Path::new("sample_games"),
-      None,
-      ModuleSource::SavedGame,
-      "samplegame.yaml",
-    )
-    .unwrap();
+    let storage = FilesystemStorage::new(PathBuf::from("sample_games"), None, 0);
+    crate::load_app(&storage, ModuleSource::SavedGame, "samplegame.yaml").unwrap();
+  }
+
+  #[test]
+  fn import_and_load_module() {
+    let dir = std::env::temp_dir().join(format!("ptrpi-test-{}-modules", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("modules")).unwrap();
+    let storage = FilesystemStorage::new(dir.clone(), Some(dir.join("modules")), 0);
+    crate::import_modules(&storage, &[PathBuf::from("sample_games/samplegame.yaml")]).unwrap();
+    crate::load_app(&storage, ModuleSource::Module, "samplegame.yaml").unwrap();
+
+    // Files that aren't games aren't imported.
+    fs::write(dir.join("notes.txt"), "not a game").unwrap();
+    assert!(crate::import_modules(&storage, &[dir.join("notes.txt")]).is_err());
+    let modules: Vec<String> =
+      storage.list(ModuleSource::Module).unwrap().into_iter().map(|m| m.name).collect();
+    assert_eq!(modules, vec!["samplegame.yaml".to_string()]);
   }
 }
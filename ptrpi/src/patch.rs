@@ -0,0 +1,86 @@
+//! Describing the difference between two JSON documents as a JSON Patch (RFC 6902), so clients
+//! can update their copy of the game without downloading all of it again.
+//!
+//! Only `add`, `remove` and `replace` operations are produced. Arrays that change length are
+//! replaced outright rather than diffed, which keeps this simple; the big parts of the game are
+//! all objects.
+
+use serde_derive::Serialize;
+use serde_json::{Map, Value};
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+  Add { path: String, value: Value },
+  Remove { path: String },
+  Replace { path: String, value: Value },
+}
+
+/// The operations that turn `from` into `to`.
+pub fn diff(from: &Value, to: &Value) -> Vec<Operation> {
+  let mut ops = vec![];
+  diff_at("", from, to, &mut ops);
+  ops
+}
+
+fn diff_at(path: &str, from: &Value, to: &Value, ops: &mut Vec<Operation>) {
+  match (from, to) {
+    (Value::Object(from), Value::Object(to)) => diff_objects(path, from, to, ops),
+    (Value::Array(from), Value::Array(to)) if from.len() == to.len() => {
+      for (idx, (from, to)) in from.iter().zip(to).enumerate() {
+        diff_at(&format!("{}/{}", path, idx), from, to, ops);
+      }
+    }
+    _ if from != to => ops.push(Operation::Replace { path: path.to_string(), value: to.clone() }),
+    _ => {}
+  }
+}
+
+fn diff_objects(
+  path: &str, from: &Map<String, Value>, to: &Map<String, Value>, ops: &mut Vec<Operation>,
+) {
+  for (key, from_value) in from {
+    let path = child_path(path, key);
+    match to.get(key) {
+      Some(to_value) => diff_at(&path, from_value, to_value, ops),
+      None => ops.push(Operation::Remove { path }),
+    }
+  }
+  for (key, to_value) in to {
+    if !from.contains_key(key) {
+      ops.push(Operation::Add { path: child_path(path, key), value: to_value.clone() });
+    }
+  }
+}
+
+/// Keys are escaped as JSON Pointer requires.
+fn child_path(path: &str, key: &str) -> String {
+  format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"))
+}
+
+#[cfg(test)]
+mod test {
+  use serde_json::json;
+
+  use super::*;
+
+  #[test]
+  fn diff_objects_and_arrays() {
+    let from = json!({"a": 1, "b": {"c": [1, 2], "d": "x"}, "gone": true, "list": [1]});
+    let to = json!({"a": 1, "b": {"c": [1, 3], "d": "x"}, "new/key": null, "list": [1, 2]});
+    assert_eq!(
+      diff(&from, &to),
+      vec![
+        Operation::Replace { path: "/b/c/1".to_string(), value: json!(3) },
+        Operation::Remove { path: "/gone".to_string() },
+        Operation::Replace { path: "/list".to_string(), value: json!([1, 2]) },
+        Operation::Add { path: "/new~1key".to_string(), value: json!(null) },
+      ]
+    );
+    assert_eq!(diff(&to, &to), vec![]);
+    assert_eq!(
+      serde_json::to_value(diff(&json!(1), &json!(2))).unwrap(),
+      json!([{"op": "replace", "path": "", "value": 2}])
+    );
+  }
+}
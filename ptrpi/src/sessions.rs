@@ -0,0 +1,224 @@
+//! Hosting several games at once. Each session has its own app, followers and saved game, and
+//! requests name the session they're for in the URL, like `/sessions/tuesday/player/alice`.
+//! Requests that don't name one go to the default session, which is where the server starts.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use actix_web::dev::Payload;
+use actix_web::error::{ErrorInternalServerError, ErrorNotFound};
+use actix_web::{web, FromRequest, HttpRequest};
+use failure::{bail, Error, Fail};
+use futures::future::{ready, Ready};
+use log::error;
+use serde_derive::{Deserialize, Serialize};
+
+use pandt::types::App;
+use ptstorage::Storage;
+
+use crate::actor::{AppActor, SavePolicy, SaveTargets};
+use crate::auth::Principal;
+
+pub const DEFAULT_SESSION: &str = "default";
+
+/// Names a session. These show up in URLs, so they're limited to letters, digits, `-` and `_`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct SessionID(pub String);
+
+impl SessionID {
+  pub fn default_session() -> SessionID { SessionID(DEFAULT_SESSION.to_string()) }
+
+  pub fn is_default(&self) -> bool { self.0 == DEFAULT_SESSION }
+
+  /// The session named in a request's path, or the default session if it doesn't name one.
+  pub fn of_request(req: &HttpRequest) -> SessionID {
+    SessionID(req.match_info().get("session_id").unwrap_or(DEFAULT_SESSION).to_string())
+  }
+
+  fn validate(&self) -> Result<(), Error> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if self.0.is_empty() || !self.0.chars().all(valid) {
+      bail!(InvalidSessionID { id: self.clone() });
+    }
+    Ok(())
+  }
+}
+
+/// What clients are told about each session.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SessionInfo {
+  pub id: SessionID,
+  pub save_target: Option<String>,
+}
+
+/// All of the sessions that the server is hosting.
+#[derive(Clone)]
+pub struct Sessions {
+  sessions: Arc<RwLock<HashMap<SessionID, AppActor>>>,
+  storage: Arc<dyn Storage>,
+  save_targets: SaveTargets,
+  policy: SavePolicy,
+}
+
+impl Sessions {
+  pub fn new(storage: Arc<dyn Storage>, policy: SavePolicy) -> Sessions {
+    Sessions {
+      sessions: Arc::new(RwLock::new(HashMap::new())),
+      storage,
+      save_targets: Default::default(),
+      policy,
+    }
+  }
+
+  /// Start a new session playing `app`.
+  pub fn create(&self, id: SessionID, app: App) -> Result<AppActor, Error> {
+    id.validate()?;
+    let mut sessions = self.sessions.write().expect("Sessions lock was poisoned");
+    if sessions.contains_key(&id) {
+      bail!(SessionExists { id });
+    }
+    let actor =
+      AppActor::new(app, self.storage.clone(), self.save_targets.clone(), self.policy.clone());
+    sessions.insert(id, actor.clone());
+    Ok(actor)
+  }
+
+  pub fn get(&self, id: &SessionID) -> Result<AppActor, Error> {
+    let sessions = self.sessions.read().expect("Sessions lock was poisoned");
+    match sessions.get(id) {
+      Some(actor) => Ok(actor.clone()),
+      None => bail!(NoSuchSession { id: id.clone() }),
+    }
+  }
+
+  fn actors(&self) -> Vec<(SessionID, AppActor)> {
+    let mut actors: Vec<(SessionID, AppActor)> = self
+      .sessions
+      .read()
+      .expect("Sessions lock was poisoned")
+      .iter()
+      .map(|(id, actor)| (id.clone(), actor.clone()))
+      .collect();
+    actors.sort_by(|a, b| a.0.cmp(&b.0));
+    actors
+  }
+
+  pub async fn list(&self) -> Vec<SessionInfo> {
+    let mut result = vec![];
+    for (id, actor) in self.actors() {
+      let save_target = actor.save_target.lock().await.clone();
+      result.push(SessionInfo { id, save_target });
+    }
+    result
+  }
+
+  /// Autosave every session that needs it. This happens every so often (see
+  /// `SavePolicy::autosave_interval`) and when the server shuts down.
+  pub async fn autosave(&self) {
+    for (id, actor) in self.actors() {
+      if let Err(e) = actor.autosave().await {
+        error!("Couldn't autosave session {:?}: {}", id.0, e);
+      }
+    }
+  }
+
+  /// Stop hosting a session. Anything that wasn't saved is lost.
+  pub async fn close(&self, id: &SessionID) -> Result<(), Error> {
+    let actor = self.sessions.write().expect("Sessions lock was poisoned").remove(id);
+    match actor {
+      Some(actor) => actor.close().await,
+      None => bail!(NoSuchSession { id: id.clone() }),
+    }
+  }
+}
+
+/// Handlers get the actor for the session named in the request's path, as long as whoever made
+/// the request is allowed into that session.
+impl FromRequest for AppActor {
+  type Error = actix_web::Error;
+  type Future = Ready<Result<AppActor, actix_web::Error>>;
+  type Config = ();
+
+  fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+    ready(Principal::authenticate(req).and_then(|_| match req.app_data::<web::Data<Sessions>>() {
+      None => Err(ErrorInternalServerError("Sessions aren't configured")),
+      Some(sessions) => sessions.get(&SessionID::of_request(req)).map_err(ErrorNotFound),
+    }))
+  }
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "There's no session called {:?}", id)]
+struct NoSuchSession {
+  id: SessionID,
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "There's already a session called {:?}", id)]
+struct SessionExists {
+  id: SessionID,
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "{:?} can't be used as a session ID", id)]
+struct InvalidSessionID {
+  id: SessionID,
+}
+
+#[cfg(test)]
+mod test {
+  use futures::executor::block_on;
+  use std::path::PathBuf;
+
+  use pandt::types::ModuleSource;
+  use ptstorage::FilesystemStorage;
+
+  use super::*;
+
+  fn sid(id: &str) -> SessionID { SessionID(id.to_string()) }
+
+  fn t_sessions() -> Sessions {
+    let storage = FilesystemStorage::new(PathBuf::from("sample_games"), None, 0);
+    Sessions::new(Arc::new(storage), Default::default())
+  }
+
+  #[test]
+  fn sessions_are_separate() {
+    let sessions = t_sessions();
+    let tuesday = sessions.create(sid("tuesday"), App::new(Default::default())).unwrap();
+    sessions.create(sid("friday"), App::new(Default::default())).unwrap();
+    assert!(sessions.create(sid("friday"), App::new(Default::default())).is_err());
+    assert!(sessions.create(sid("../etc"), App::new(Default::default())).is_err());
+
+    block_on(tuesday.load_saved_game("samplegame.yaml".to_string(), ModuleSource::SavedGame))
+      .unwrap();
+    let friday = sessions.get(&sid("friday")).unwrap();
+    assert!(!block_on(tuesday.app.lock()).current_game.creatures().unwrap().is_empty());
+    assert_eq!(block_on(friday.app.lock()).current_game.creatures().unwrap().len(), 0);
+    assert_eq!(
+      block_on(sessions.list()),
+      vec![
+        SessionInfo { id: sid("friday"), save_target: None },
+        SessionInfo { id: sid("tuesday"), save_target: Some("samplegame.yaml".to_string()) },
+      ]
+    );
+  }
+
+  #[test]
+  fn sessions_dont_share_saved_games() {
+    let sessions = t_sessions();
+    let tuesday = sessions.create(sid("tuesday"), App::new(Default::default())).unwrap();
+    let friday = sessions.create(sid("friday"), App::new(Default::default())).unwrap();
+    let load = |actor: &AppActor| {
+      block_on(actor.load_saved_game("samplegame.yaml".to_string(), ModuleSource::SavedGame))
+    };
+    load(&tuesday).unwrap();
+    assert!(load(&friday).is_err());
+    assert_eq!(block_on(friday.save_target.lock()).clone(), None);
+
+    // Once the session that was playing it is closed, another can pick it up.
+    block_on(sessions.close(&sid("tuesday"))).unwrap();
+    assert!(sessions.get(&sid("tuesday")).is_err());
+    load(&friday).unwrap();
+  }
+}
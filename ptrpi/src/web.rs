@@ -1,134 +1,492 @@
-use std::fs;
-use std::path::Path;
-
-use actix_cors::Cors;
-use actix_web::{web, HttpResponse, Responder};
+use actix_cors::{Cors, CorsFactory};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use failure::Error;
+use futures::stream;
 use http::header;
-use log::{error, info};
+use log::info;
+use serde_derive::Deserialize;
+
+use pandt::types::{
+  AbilityID, App, CreatureID, Dice, DiceStats, GameCommand, HistoryQuery, ModuleSource, PlayerID,
+  Point3, SceneID,
+};
 
-use pandt::types::{AbilityID, CreatureID, GameCommand, ModuleSource, Point3, SceneID};
+use ptstorage::Metadata;
 
 use crate::actor::AppActor;
+use crate::auth::{ActingPlayer, Auth, Principal, ServerGM, GM};
+use crate::events::{Follower, Format};
+use crate::sessions::{SessionID, Sessions};
+
+pub fn router(sessions: Sessions, auth: Auth, config: &mut web::ServiceConfig) {
+  config
+    .data(sessions)
+    .data(auth)
+    .service(
+      web::scope("/sessions")
+        .wrap(cors())
+        .service(
+          web::resource("")
+            .route(web::get().to(list_sessions))
+            .route(web::post().to(create_session)),
+        )
+        .service(web::resource("{session_id}/close").route(web::post().to(close_session)))
+        .service(web::scope("/{session_id}").configure(session_routes)),
+    )
+    // Without a session in the path, requests go to the default session.
+    .service(web::scope("/").wrap(cors()).configure(session_routes));
+}
 
-pub fn router(actor: AppActor, config: &mut web::ServiceConfig) {
-  let corsm = Cors::new()
+fn cors() -> CorsFactory {
+  Cors::new()
     .send_wildcard()
     .allowed_header(header::CONTENT_TYPE)
+    .allowed_header(header::AUTHORIZATION)
     .allowed_methods(vec!["POST", "GET", "OPTIONS"])
-    .finish();
-  config.data(actor).service(
-    web::scope("/")
-      .wrap(corsm)
-      .service(web::resource("").route(web::get().to(get_app)).route(web::post().to(post_command)))
-      .service(web::resource("poll/{snapshot_len}/{log_len}").route(web::get().to(poll_app)))
-      .service(
-        web::resource("movement_options/{scene_id}/{cid}").route(web::get().to(movement_options)),
-      )
-      .service(
-        web::resource("combat_movement_options").route(web::get().to(combat_movement_options)),
-      )
-      .service(
-        web::resource("target_options/{scene_id}/{cid}/{abid}")
-          .route(web::get().to(target_options)),
-      )
-      .service(
-        web::resource("preview_volume_targets/{scene_id}/{actor_id}/{ability_id}/{x}/{y}/{z}")
-          .route(web::post().to(preview_volume_targets)),
-      )
-      .service(web::resource("saved_games").route(web::get().to(list_saved_games)))
-      .service(web::resource("saved_games/module/{name}/load").route(web::post().to(load_module_as_game)))
-      .service(web::resource("saved_games/user/{name}/load").route(web::post().to(load_saved_game)))
-      .service(web::resource("saved_games/user/{name}").route(web::post().to(save_game)))
-      .service(web::resource("modules/{name}").route(web::post().to(save_module)))
-      .service(web::resource("new_game").route(web::post().to(new_game)))
-  );
+    .finish()
+}
+
+/// The routes for everything to do with a single session.
+fn session_routes(config: &mut web::ServiceConfig) {
+  config
+    .service(web::resource("").route(web::get().to(get_app)).route(web::post().to(post_command)))
+    .service(
+      web::resource("player/{player_id}")
+        .route(web::get().to(get_player_app))
+        .route(web::post().to(post_player_command)),
+    )
+    .service(web::resource("player/{player_id}/events").route(web::get().to(player_events)))
+    .service(web::resource("player/{player_id}/changes").route(web::get().to(player_changes)))
+    .service(
+      web::resource("player/{player_id}/creatures/{cid}").route(web::get().to(player_creature)),
+    )
+    .service(
+      web::resource("player/{player_id}/scenes/{scene_id}").route(web::get().to(player_scene)),
+    )
+    .service(web::resource("events").route(web::get().to(events)))
+    .service(web::resource("changes").route(web::get().to(changes)))
+    .service(web::resource("creatures/{cid}").route(web::get().to(creature)))
+    .service(web::resource("scenes/{scene_id}").route(web::get().to(scene)))
+    .service(
+      web::resource("movement_options/{scene_id}/{cid}").route(web::get().to(movement_options)),
+    )
+    .service(
+      web::resource("combat_movement_options").route(web::get().to(combat_movement_options)),
+    )
+    .service(
+      web::resource("target_options/{scene_id}/{cid}/{abid}").route(web::get().to(target_options)),
+    )
+    .service(
+      web::resource("preview_volume_targets/{scene_id}/{actor_id}/{ability_id}/{x}/{y}/{z}")
+        .route(web::post().to(preview_volume_targets)),
+    )
+    .service(web::resource("history").route(web::get().to(history)))
+    .service(web::resource("dice_stats").route(web::post().to(dice_stats)))
+    .service(web::resource("saved_games").route(web::get().to(list_saved_games)))
+    .service(web::resource("saved_games/details").route(web::get().to(saved_game_details)))
+    .service(
+      web::resource("saved_games/module/{name}/load").route(web::post().to(load_module_as_game)),
+    )
+    .service(web::resource("saved_games/user/{name}/load").route(web::post().to(load_saved_game)))
+    .service(web::resource("saved_games/user/{name}").route(web::post().to(save_game)))
+    .service(
+      web::resource("saved_games/user/{name}/delete").route(web::post().to(delete_saved_game)),
+    )
+    .service(web::resource("modules/{name}").route(web::post().to(save_module)))
+    .service(web::resource("new_game").route(web::post().to(new_game)));
+}
+
+async fn list_sessions(_: ServerGM, sessions: web::Data<Sessions>) -> impl Responder {
+  web::Json(sessions.list().await)
+}
+
+/// Start a new session with an empty game. Games can then be loaded into it as usual, through
+/// `/sessions/{session_id}/saved_games/...`.
+async fn create_session(
+  _: ServerGM, sessions: web::Data<Sessions>, id: web::Json<SessionID>,
+) -> impl Responder {
+  let actor = sessions.create(id.into_inner(), App::new(Default::default()))?;
+  string_json_response(actor.get_app().await?)
 }
 
-async fn get_app(actor: web::Data<AppActor>) -> impl Responder {
+#[derive(Deserialize)]
+struct SessionPath {
+  session_id: SessionID,
+}
+
+async fn close_session(
+  _: ServerGM, sessions: web::Data<Sessions>, path: web::Path<SessionPath>,
+) -> impl Responder {
+  sessions.close(&path.session_id).await?;
+  string_json_response("{}".to_string())
+}
+
+async fn get_app(_: GM, actor: AppActor) -> impl Responder {
   string_json_response(actor.get_app().await?)
 }
 
-async fn poll_app(actor: web::Data<AppActor>, path: web::Path<(usize, usize)>) -> impl Responder {
-  string_json_response(actor.poll_app(path.0, path.1).await?)
+/// Follow the changes to the app as a stream of server-sent events; see the `events` module.
+async fn events(
+  _: GM, actor: AppActor, req: HttpRequest, query: web::Query<FollowQuery>,
+) -> impl Responder {
+  let query = query.into_inner();
+  follow(actor, None, &req, query.from, query.format)
+}
+
+/// What changed in the app since a cursor, as a single event like those that `events` streams, or
+/// `null` if nothing did.
+async fn changes(
+  _: GM, actor: AppActor, query: web::Query<ChangesQuery>,
+) -> impl Responder {
+  string_json_response(actor.changes(None, query.from.parse()?, query.format).await?)
+}
+
+async fn creature(_: GM, actor: AppActor, path: web::Path<CreaturePath>) -> impl Responder {
+  string_json_response(actor.get_creature(None, path.cid).await?)
+}
+
+async fn scene(_: GM, actor: AppActor, path: web::Path<ScenePath>) -> impl Responder {
+  string_json_response(actor.get_scene(None, path.scene_id).await?)
+}
+
+/// A player's view of the app, without anything that's only for the GM.
+async fn get_player_app(player: ActingPlayer, actor: AppActor) -> impl Responder {
+  string_json_response(actor.get_player_app(&player.0).await?)
+}
+
+async fn player_events(
+  player: ActingPlayer, actor: AppActor, req: HttpRequest, query: web::Query<FollowQuery>,
+) -> impl Responder {
+  let query = query.into_inner();
+  follow(actor, Some(player.0), &req, query.from, query.format)
+}
+
+async fn player_changes(
+  player: ActingPlayer, actor: AppActor, query: web::Query<ChangesQuery>,
+) -> impl Responder {
+  string_json_response(actor.changes(Some(player.0), query.from.parse()?, query.format).await?)
+}
+
+async fn player_creature(
+  player: ActingPlayer, actor: AppActor, path: web::Path<CreaturePath>,
+) -> impl Responder {
+  string_json_response(actor.get_creature(Some(player.0), path.cid).await?)
+}
+
+async fn player_scene(
+  player: ActingPlayer, actor: AppActor, path: web::Path<ScenePath>,
+) -> impl Responder {
+  string_json_response(actor.get_scene(Some(player.0), path.scene_id).await?)
+}
+
+// Path parameters are picked out by name rather than position, since paths may start with a
+// session ID.
+
+#[derive(Deserialize)]
+struct CreaturePath {
+  cid: CreatureID,
+}
+
+#[derive(Deserialize)]
+struct ScenePath {
+  scene_id: SceneID,
+}
+
+#[derive(Deserialize)]
+struct SceneCreaturePath {
+  scene_id: SceneID,
+  cid: CreatureID,
+}
+
+#[derive(Deserialize)]
+struct TargetPath {
+  scene_id: SceneID,
+  cid: CreatureID,
+  abid: AbilityID,
+}
+
+#[derive(Deserialize)]
+struct PreviewPath {
+  scene_id: SceneID,
+  actor_id: CreatureID,
+  ability_id: AbilityID,
+  x: i64,
+  y: i64,
+  z: i64,
+}
+
+#[derive(Deserialize)]
+struct NamePath {
+  name: String,
+}
+
+#[derive(Deserialize)]
+struct FollowQuery {
+  /// The cursor of the app that the client already has, if any. Without one, the first event is
+  /// the whole app.
+  from: Option<String>,
+  #[serde(default)]
+  format: Format,
+}
+
+#[derive(Deserialize)]
+struct ChangesQuery {
+  from: String,
+  #[serde(default)]
+  format: Format,
+}
+
+fn follow(
+  actor: AppActor, player_id: Option<PlayerID>, req: &HttpRequest,
+  from: Option<String>, format: Format,
+) -> Result<HttpResponse, Error> {
+  // When a browser reconnects, it says which event it got last.
+  let last_event_id = req.headers().get("Last-Event-ID").and_then(|id| id.to_str().ok());
+  let from = match last_event_id.map(|id| id.to_string()).or(from) {
+    Some(from) => Some(from.parse()?),
+    None => None,
+  };
+  let follower = Follower::new(player_id, from, format);
+  let events = stream::unfold(Some((actor, follower)), |state| async move {
+    let (actor, mut follower) = state?;
+    match actor.next_event(&mut follower).await {
+      Ok(event) => Some((Ok(web::Bytes::from(event)), Some((actor, follower)))),
+      Err(e) => Some((Err(e), None)),
+    }
+  });
+  Ok(HttpResponse::Ok().content_type("text/event-stream").streaming(events))
 }
 
 async fn post_command(
-  actor: web::Data<AppActor>, command: web::Json<GameCommand>,
+  gm: GM, actor: AppActor, command: web::Json<GameCommand>,
 ) -> impl Responder {
-  string_json_response(actor.perform_command(command.into_inner()).await?)
+  let command = match command.into_inner() {
+    GameCommand::LoadModule { name, path, source: ModuleSource::SavedGame } => {
+      let name = gm.saved_game_name(name);
+      GameCommand::LoadModule { name, path, source: ModuleSource::SavedGame }
+    }
+    command => command,
+  };
+  string_json_response(actor.perform_command(command).await?)
+}
+
+/// Perform a command as a player, which may need to be approved by the GM.
+async fn post_player_command(
+  player: ActingPlayer, actor: AppActor, command: web::Json<GameCommand>,
+) -> impl Responder {
+  string_json_response(actor.perform_player_command(player.0, command.into_inner()).await?)
 }
 
 async fn movement_options(
-  actor: web::Data<AppActor>, path: web::Path<(SceneID, CreatureID)>,
+  principal: Principal, actor: AppActor, path: web::Path<SceneCreaturePath>,
 ) -> impl Responder {
-  string_json_response(actor.movement_options(path.0, path.1).await?)
+  let options = actor.movement_options(principal.player_id(), path.scene_id, path.cid);
+  string_json_response(options.await?)
 }
 
-async fn combat_movement_options(actor: web::Data<AppActor>) -> impl Responder {
-  string_json_response(actor.combat_movement_options().await?)
+async fn combat_movement_options(principal: Principal, actor: AppActor) -> impl Responder {
+  string_json_response(actor.combat_movement_options(principal.player_id()).await?)
 }
 
 async fn target_options(
-  actor: web::Data<AppActor>, path: web::Path<(SceneID, CreatureID, AbilityID)>,
+  principal: Principal, actor: AppActor, path: web::Path<TargetPath>,
 ) -> impl Responder {
-  string_json_response(actor.target_options(path.0, path.1, path.2).await?)
+  let options = actor.target_options(principal.player_id(), path.scene_id, path.cid, path.abid);
+  string_json_response(options.await?)
 }
 
 async fn preview_volume_targets(
-  actor: web::Data<AppActor>, path: web::Path<(SceneID, CreatureID, AbilityID, i64, i64, i64)>,
+  principal: Principal, actor: AppActor, path: web::Path<PreviewPath>,
+) -> impl Responder {
+  let point = Point3::new(path.x, path.y, path.z);
+  let path = path.into_inner();
+  let targets = actor.preview_volume_targets(
+    principal.player_id(),
+    path.scene_id,
+    path.actor_id,
+    path.ability_id,
+    point,
+  );
+  string_json_response(targets.await?)
+}
+
+/// Search the game's history, e.g. `history?creature_id=...&kind=Damage&start=100`.
+async fn history(
+  _: GM, actor: AppActor, query: web::Query<HistoryQuery>,
 ) -> impl Responder {
-  let point = Point3::new(path.3, path.4, path.5);
-  string_json_response(actor.preview_volume_targets(path.0, path.1, path.2, point).await?)
+  string_json_response(actor.history(query.into_inner()).await?)
+}
+
+/// Compute the probability distribution of some dice, e.g. `"best 2 of (1d20+3)"`.
+async fn dice_stats(_: Principal, dice: web::Json<Dice>) -> Result<web::Json<DiceStats>, Error> {
+  // This does not require access to the app, so we don't dispatch to the actor.
+  Ok(web::Json(dice.stats()?))
 }
 
 async fn list_saved_games(
-  actor: web::Data<AppActor>,
+  gm: GM, actor: AppActor,
 ) -> Result<web::Json<(Vec<String>, Vec<String>)>, Error> {
   // This does not require access to the app, so we don't dispatch to the actor.
+  let (modules, saved_games) = own_saved_games(&gm, &actor)?;
+  let names = |games: Vec<Metadata>| games.into_iter().map(|metadata| metadata.name).collect();
+  Ok(web::Json((names(modules), names(saved_games))))
+}
 
-  fn list_dir_into_strings(path: &Path) -> Result<Vec<String>, Error> {
-    let mut result = vec![];
-    for mpath in fs::read_dir(path)? {
-      let path = mpath?;
-      if path.file_type()?.is_file() {
-        match path.file_name().into_string() {
-          Ok(s) => result.push(s),
-          Err(x) => error!("Couldn't parse filename as unicode: {:?}", x),
-        }
-      }
-    }
-    Ok(result)
-  }
+/// Like `saved_games`, but with the size and modification time of each game.
+async fn saved_game_details(
+  gm: GM, actor: AppActor,
+) -> Result<web::Json<(Vec<Metadata>, Vec<Metadata>)>, Error> {
+  Ok(web::Json(own_saved_games(&gm, &actor)?))
+}
 
-  let modules = match actor.module_path {
-    Some(ref path) => list_dir_into_strings(path.as_ref())?,
-    None => vec![],
-  };
-  let result = (modules, list_dir_into_strings(&actor.saved_game_path)?);
-  Ok(web::Json(result))
+/// All of the modules, and the saved games that belong to the GM, named as they know them.
+fn own_saved_games(gm: &GM, actor: &AppActor) -> Result<(Vec<Metadata>, Vec<Metadata>), Error> {
+  let modules = actor.list_saved_games(ModuleSource::Module)?;
+  let saved_games = actor
+    .list_saved_games(ModuleSource::SavedGame)?
+    .into_iter()
+    .filter_map(|metadata| {
+      let name = gm.own_saved_game(metadata.name)?;
+      Some(Metadata { name, ..metadata })
+    })
+    .collect();
+  Ok((modules, saved_games))
 }
 
-async fn load_saved_game(actor: web::Data<AppActor>, path: web::Path<String>) -> impl Responder {
-  string_json_response(actor.load_saved_game(path.into_inner(), ModuleSource::SavedGame).await?)
+async fn load_saved_game(
+  gm: GM, actor: AppActor, path: web::Path<NamePath>,
+) -> impl Responder {
+  let name = gm.saved_game_name(path.into_inner().name);
+  string_json_response(actor.load_saved_game(name, ModuleSource::SavedGame).await?)
 }
 
-async fn load_module_as_game(actor: web::Data<AppActor>, path: web::Path<String>) -> impl Responder {
-  string_json_response(actor.load_saved_game(path.into_inner(), ModuleSource::Module).await?)
+async fn load_module_as_game(
+  _: GM, actor: AppActor, path: web::Path<NamePath>,
+) -> impl Responder {
+  string_json_response(actor.load_saved_game(path.into_inner().name, ModuleSource::Module).await?)
 }
 
-async fn save_game(actor: web::Data<AppActor>, path: web::Path<String>) -> impl Responder {
-  string_json_response(actor.save_game(path.into_inner()).await?)
+async fn save_game(
+  gm: GM, actor: AppActor, path: web::Path<NamePath>,
+) -> impl Responder {
+  string_json_response(actor.save_game(gm.saved_game_name(path.into_inner().name)).await?)
 }
 
-async fn save_module(actor: web::Data<AppActor>, path: web::Path<String>, folder_path: web::Json<::foldertree::FolderPath>) -> impl Responder {
-  string_json_response(actor.save_module(path.into_inner(), folder_path.into_inner()).await?)
+async fn delete_saved_game(
+  gm: GM, actor: AppActor, path: web::Path<NamePath>,
+) -> impl Responder {
+  string_json_response(actor.delete_saved_game(gm.saved_game_name(path.into_inner().name))?)
 }
 
-async fn new_game(actor: web::Data<AppActor>) -> impl Responder {
+/// Modules are shared by every session, so only the top-level GM can write them.
+async fn save_module(
+  _: ServerGM, actor: AppActor, path: web::Path<NamePath>,
+  folder_path: web::Json<::foldertree::FolderPath>,
+) -> impl Responder {
+  string_json_response(actor.save_module(path.into_inner().name, folder_path.into_inner()).await?)
+}
+
+async fn new_game(_: GM, actor: AppActor) -> impl Responder {
   string_json_response(actor.new_game().await?)
 }
 
 fn string_json_response(body: String) -> Result<HttpResponse, Error> {
   Ok(HttpResponse::Ok().content_type("application/json").body(body))
 }
+
+#[cfg(test)]
+mod test {
+  use std::path::PathBuf;
+  use std::sync::Arc;
+
+  use actix_web::http::StatusCode;
+  use actix_web::test::{call_service, init_service, read_body, TestRequest};
+  use actix_web::App as WebApp;
+  use ptstorage::FilesystemStorage;
+
+  use super::*;
+
+  #[actix_rt::test]
+  async fn requests_go_to_their_session() {
+    let storage = FilesystemStorage::new(PathBuf::from("sample_games"), None, 0);
+    let sessions = Sessions::new(Arc::new(storage), Default::default());
+    sessions.create(SessionID::default_session(), App::new(Default::default())).unwrap();
+    let mut service = init_service(
+      WebApp::new().configure(|c| router(sessions.clone(), Auth(None), c)),
+    )
+    .await;
+    macro_rules! status {
+      ($req:expr) => {
+        call_service(&mut service, $req.to_request()).await.status()
+      };
+    }
+
+    let create = TestRequest::post().uri("/sessions").set_json(&SessionID("tuesday".to_string()));
+    assert_eq!(status!(create), StatusCode::OK);
+    let load = TestRequest::post().uri("/sessions/tuesday/saved_games/user/samplegame.yaml/load");
+    assert_eq!(status!(load), StatusCode::OK);
+
+    let tuesday = sessions.get(&SessionID("tuesday".to_string())).unwrap();
+    let cid = *tuesday.app.lock().await.current_game.creatures().unwrap().keys().next().unwrap();
+    let uri = format!("/sessions/tuesday/creatures/{}", cid.0);
+    assert_eq!(status!(TestRequest::get().uri(&uri)), StatusCode::OK);
+    // The default session is still playing an empty game.
+    let uri = format!("/creatures/{}", cid.0);
+    assert_ne!(status!(TestRequest::get().uri(&uri)), StatusCode::OK);
+
+    assert_eq!(status!(TestRequest::post().uri("/sessions/tuesday/close")), StatusCode::OK);
+    assert_eq!(status!(TestRequest::get().uri("/sessions/tuesday")), StatusCode::NOT_FOUND);
+    assert_eq!(status!(TestRequest::get().uri("/")), StatusCode::OK);
+  }
+
+  #[actix_rt::test]
+  async fn session_gms_stay_in_their_session() {
+    let dir = crate::actor::test::t_dir("session-gms");
+    std::fs::copy("sample_games/samplegame.yaml", dir.join("samplegame.yaml")).unwrap();
+    let storage = FilesystemStorage::new(dir.clone(), None, 0);
+    let sessions = Sessions::new(Arc::new(storage), Default::default());
+    sessions.create(SessionID("tuesday".to_string()), App::new(Default::default())).unwrap();
+    let credentials = "gm: gm-secret\nsessions:\n  tuesday:\n    gm: tuesday-secret\n";
+    let auth = Auth(Some(serde_yaml::from_str(credentials).unwrap()));
+    let mut service =
+      init_service(WebApp::new().configure(|c| router(sessions.clone(), auth, c))).await;
+    macro_rules! call {
+      ($req:expr, $token:expr) => {{
+        let req = $req.header(header::AUTHORIZATION, format!("Bearer {}", $token));
+        call_service(&mut service, req.to_request()).await
+      }};
+    }
+    let status = |response: actix_web::dev::ServiceResponse| response.status();
+
+    // Only the top-level GM can manage sessions and modules.
+    // Listing sessions isn't in any session but the default one, which the token isn't for.
+    let list_sessions = TestRequest::get().uri("/sessions");
+    assert_eq!(status(call!(list_sessions, "tuesday-secret")), StatusCode::UNAUTHORIZED);
+    let close = TestRequest::post().uri("/sessions/tuesday/close");
+    assert_eq!(status(call!(close, "tuesday-secret")), StatusCode::FORBIDDEN);
+    let module =
+      TestRequest::post().uri("/sessions/tuesday/modules/samplegame.yaml").set_json(&"/");
+    assert_eq!(status(call!(module, "tuesday-secret")), StatusCode::FORBIDDEN);
+
+    // The session's GM can't get at saved games from outside the session...
+    let load = TestRequest::post().uri("/sessions/tuesday/saved_games/user/samplegame.yaml/load");
+    assert_ne!(status(call!(load, "tuesday-secret")), StatusCode::OK);
+    let delete =
+      TestRequest::post().uri("/sessions/tuesday/saved_games/user/samplegame.yaml/delete");
+    assert_ne!(status(call!(delete, "tuesday-secret")), StatusCode::OK);
+    assert!(dir.join("samplegame.yaml").exists());
+
+    // ... but has saved games of their own, which the top-level GM sees by their full names.
+    let save = TestRequest::post().uri("/sessions/tuesday/saved_games/user/game.yaml");
+    assert_eq!(status(call!(save, "tuesday-secret")), StatusCode::OK);
+    assert!(dir.join("tuesday@game.yaml").exists());
+    let list = || TestRequest::get().uri("/sessions/tuesday/saved_games");
+    let saved_games = |body: actix_web::web::Bytes| {
+      serde_json::from_slice::<(Vec<String>, Vec<String>)>(&body).unwrap().1
+    };
+    assert_eq!(saved_games(read_body(call!(list(), "tuesday-secret")).await), vec!["game.yaml"]);
+    let mut saved = saved_games(read_body(call!(list(), "gm-secret")).await);
+    saved.sort();
+    assert_eq!(saved, vec!["samplegame.yaml".to_string(), "tuesday@game.yaml".to_string()]);
+  }
+}
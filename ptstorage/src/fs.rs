@@ -0,0 +1,206 @@
+//! Keeping games as files in a directory: one for saved games, and optionally another for modules.
+//!
+//! Each saved game may have a journal (see `pandt::journal`) and some backups next to it, which
+//! aren't listed as games themselves.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use log::error;
+
+use crate::{
+  check_name, Metadata, ModuleSource, Storage, StorageError, BACKUP_EXTENSION, JOURNAL_EXTENSION,
+  TEMP_EXTENSION,
+};
+
+pub struct FilesystemStorage {
+  saved_games: PathBuf,
+  modules: Option<PathBuf>,
+  /// How many old versions of each game to keep, from `{name}.1.bak` (the most recent) up.
+  backups: usize,
+}
+
+impl FilesystemStorage {
+  pub fn new(saved_games: PathBuf, modules: Option<PathBuf>, backups: usize) -> FilesystemStorage {
+    FilesystemStorage { saved_games, modules, backups }
+  }
+
+  fn path(&self, source: ModuleSource, name: &str) -> Result<PathBuf, StorageError> {
+    check_name(name)?;
+    let dir = match source {
+      ModuleSource::SavedGame => &self.saved_games,
+      ModuleSource::Module => self.modules.as_ref().ok_or(StorageError::NoModuleSource)?,
+    };
+    Ok(dir.join(name))
+  }
+}
+
+impl Storage for FilesystemStorage {
+  fn list(&self, source: ModuleSource) -> Result<Vec<Metadata>, StorageError> {
+    let dir = match source {
+      ModuleSource::SavedGame => &self.saved_games,
+      ModuleSource::Module => match self.modules {
+        Some(ref modules) => modules,
+        None => return Ok(vec![]),
+      },
+    };
+    let mut result = vec![];
+    for entry in fs::read_dir(dir)? {
+      let entry = entry?;
+      let metadata = entry.metadata()?;
+      if !metadata.is_file() {
+        continue;
+      }
+      match entry.file_name().into_string() {
+        // Journals and backups are implementation details of saved games, not games themselves.
+        Ok(ref name) if check_name(name).is_err() => {}
+        Ok(name) => {
+          let modified = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+          result.push(Metadata { name, size: metadata.len(), modified: modified.as_secs() });
+        }
+        Err(x) => error!("Couldn't parse filename as unicode: {:?}", x),
+      }
+    }
+    result.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(result)
+  }
+
+  fn load(&self, source: ModuleSource, name: &str) -> Result<String, StorageError> {
+    fs::read_to_string(self.path(source, name)?).map_err(|e| not_found(e, name))
+  }
+
+  fn save(&self, source: ModuleSource, name: &str, data: &str) -> Result<(), StorageError> {
+    write_atomically(&self.path(source, name)?, data.as_bytes(), self.backups)?;
+    Ok(())
+  }
+
+  fn delete(&self, source: ModuleSource, name: &str) -> Result<(), StorageError> {
+    let path = self.path(source, name)?;
+    fs::remove_file(&path).map_err(|e| not_found(e, name))?;
+    let extras = (1..=self.backups).map(|n| backup_path(&path, n)).chain(Some(journal_path(&path)));
+    for extra in extras {
+      match fs::remove_file(extra) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+        r => r?,
+      }
+    }
+    Ok(())
+  }
+
+  fn journal_path(&self, name: &str) -> Option<PathBuf> {
+    Some(journal_path(&self.saved_games.join(name)))
+  }
+}
+
+fn not_found(error: io::Error, name: &str) -> StorageError {
+  match error.kind() {
+    io::ErrorKind::NotFound => StorageError::NotFound(name.to_string()),
+    _ => StorageError::IO(error),
+  }
+}
+
+/// The path of the journal for the game saved at `saved_game`.
+pub fn journal_path(saved_game: &Path) -> PathBuf { with_extension(saved_game, JOURNAL_EXTENSION) }
+
+/// Write a file without ever leaving it half-written, even if we crash: the data goes to a
+/// temporary file first, which then replaces the old file. Up to `backups` old versions of the
+/// file are kept next to it.
+fn write_atomically(path: &Path, data: &[u8], backups: usize) -> Result<(), io::Error> {
+  let temp_path = with_extension(path, TEMP_EXTENSION);
+  let mut file = fs::File::create(&temp_path)?;
+  file.write_all(data)?;
+  file.sync_all()?;
+  if backups > 0 && path.exists() {
+    for n in (1..backups).rev() {
+      let backup = backup_path(path, n);
+      if backup.exists() {
+        fs::rename(&backup, backup_path(path, n + 1))?;
+      }
+    }
+    // Copying rather than renaming means there's always a saved game at `path`.
+    fs::copy(path, backup_path(path, 1))?;
+  }
+  fs::rename(&temp_path, path)?;
+  Ok(())
+}
+
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+  with_extension(path, &format!("{}.{}", n, BACKUP_EXTENSION))
+}
+
+/// Add an extension to a path, keeping any that it already has.
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+  let mut name = path.file_name().unwrap_or_default().to_os_string();
+  name.push(".");
+  name.push(extension);
+  path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  /// A fresh, empty directory to keep games in.
+  fn t_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ptstorage-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    dir
+  }
+
+  fn files(dir: &Path) -> Vec<String> {
+    let mut files: Vec<String> =
+      fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+    files.sort();
+    files
+  }
+
+  #[test]
+  fn saves_keep_backups() {
+    let dir = t_dir("backups");
+    let storage = FilesystemStorage::new(dir.clone(), None, 2);
+    for version in &["one", "two", "three"] {
+      storage.save(ModuleSource::SavedGame, "game", version).unwrap();
+    }
+    let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+    assert_eq!(read("game"), "three");
+    assert_eq!(read("game.1.bak"), "two");
+    assert_eq!(read("game.2.bak"), "one");
+    assert_eq!(files(&dir), vec!["game", "game.1.bak", "game.2.bak"]);
+  }
+
+  #[test]
+  fn list_load_and_delete() {
+    let dir = t_dir("list");
+    let storage = FilesystemStorage::new(dir.clone(), None, 1);
+    storage.save(ModuleSource::SavedGame, "b.yaml", "bee").unwrap();
+    storage.save(ModuleSource::SavedGame, "a.yaml", "a").unwrap();
+    storage.save(ModuleSource::SavedGame, "a.yaml", "ay").unwrap();
+    fs::write(journal_path(&dir.join("a.yaml")), "").unwrap();
+
+    let names: Vec<(String, u64)> = storage
+      .list(ModuleSource::SavedGame)
+      .unwrap()
+      .into_iter()
+      .map(|metadata| (metadata.name, metadata.size))
+      .collect();
+    assert_eq!(names, vec![("a.yaml".to_string(), 2), ("b.yaml".to_string(), 3)]);
+    assert_eq!(storage.load(ModuleSource::SavedGame, "a.yaml").unwrap(), "ay");
+    assert!(storage.list(ModuleSource::Module).unwrap().is_empty());
+    match storage.load(ModuleSource::Module, "a.yaml") {
+      Err(StorageError::NoModuleSource) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+
+    // Deleting a game deletes its journal and backups too.
+    storage.delete(ModuleSource::SavedGame, "a.yaml").unwrap();
+    assert_eq!(files(&dir), vec!["b.yaml"]);
+    match storage.load(ModuleSource::SavedGame, "a.yaml") {
+      Err(StorageError::NotFound(_)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+    assert!(storage.save(ModuleSource::SavedGame, "../a.yaml", "").is_err());
+  }
+}
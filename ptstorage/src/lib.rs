@@ -0,0 +1,102 @@
+//! Where saved games and modules are kept, behind the `Storage` trait so that the server can keep
+//! them somewhere other than a directory on local disk.
+//!
+//! Games are stored as the YAML that `pandt` serializes them to; this crate doesn't know anything
+//! more about them than that.
+
+mod fs;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use std::io;
+use std::path::PathBuf;
+
+use failure_derive::Fail;
+use serde_derive::{Deserialize, Serialize};
+
+pub use crate::fs::{journal_path, FilesystemStorage};
+#[cfg(feature = "sqlite")]
+pub use crate::sqlite::SqliteStorage;
+
+pub const JOURNAL_EXTENSION: &str = "journal";
+pub const BACKUP_EXTENSION: &str = "bak";
+pub const TEMP_EXTENSION: &str = "tmp";
+
+/// Which kind of game to look for: a module, which is only ever read from, or a saved game.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ModuleSource {
+  Module,
+  SavedGame,
+}
+
+/// What's known about a stored game without loading it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Metadata {
+  pub name: String,
+  /// The size of the serialized game, in bytes.
+  pub size: u64,
+  /// When the game was last saved, in seconds since the Unix epoch.
+  pub modified: u64,
+}
+
+#[derive(Debug, Fail)]
+pub enum StorageError {
+  #[fail(display = "{:?} can't be used as the name of a game", _0)]
+  InvalidName(String),
+  #[fail(display = "There's no game called {:?}", _0)]
+  NotFound(String),
+  #[fail(display = "No module source found")]
+  NoModuleSource,
+  #[fail(display = "Couldn't access stored games: {}", _0)]
+  IO(#[cause] io::Error),
+  #[fail(display = "Couldn't access the database of games: {}", _0)]
+  Database(String),
+}
+
+impl From<io::Error> for StorageError {
+  fn from(error: io::Error) -> StorageError { StorageError::IO(error) }
+}
+
+pub trait Storage: Send + Sync {
+  /// All the games of one kind, sorted by name.
+  fn list(&self, source: ModuleSource) -> Result<Vec<Metadata>, StorageError>;
+  fn load(&self, source: ModuleSource, name: &str) -> Result<String, StorageError>;
+  /// Save a game, replacing any with the same name. Games are never left half-saved, and the
+  /// versions they replace may be kept around as backups.
+  fn save(&self, source: ModuleSource, name: &str, data: &str) -> Result<(), StorageError>;
+  fn delete(&self, source: ModuleSource, name: &str) -> Result<(), StorageError>;
+
+  /// Where to journal the commands performed on a saved game (see `pandt::journal`). Journals are
+  /// files, so only storage that keeps games in files has them.
+  fn journal_path(&self, _name: &str) -> Option<PathBuf> { None }
+}
+
+/// Games are named like files wherever they're stored, so names can't be paths, or clash with the
+/// extra files that are kept next to saved games.
+pub fn check_name(name: &str) -> Result<(), StorageError> {
+  let reserved = [JOURNAL_EXTENSION, BACKUP_EXTENSION, TEMP_EXTENSION];
+  if name.is_empty()
+    || name == "."
+    || name == ".."
+    || name.contains(&['/', ':', '\\'][..])
+    || reserved.iter().any(|ext| name.ends_with(&format!(".{}", ext)))
+  {
+    return Err(StorageError::InvalidName(name.to_string()));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn names_arent_paths() {
+    assert!(check_name("game.yaml").is_ok());
+    let names =
+      ["", ".", "..", "../game.yaml", "/etc/passwd", "c:game", "a\\b", "game.yaml.journal"];
+    for name in &names {
+      assert!(check_name(name).is_err(), "{:?} should be invalid", name);
+    }
+  }
+}
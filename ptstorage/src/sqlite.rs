@@ -0,0 +1,196 @@
+//! Keeping games in an embedded SQLite database, so the server doesn't need a directory that it
+//! can write arbitrary files to. Saving a game is a single transaction, so it's all-or-nothing,
+//! and old versions are kept in a table of backups.
+//!
+//! Games aren't journaled here (see `Storage::journal_path`), so anything done since a game was
+//! last saved is lost if the server crashes.
+
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::{check_name, Metadata, ModuleSource, Storage, StorageError};
+
+pub struct SqliteStorage {
+  connection: Mutex<Connection>,
+  /// How many old versions of each game to keep.
+  backups: usize,
+}
+
+impl From<rusqlite::Error> for StorageError {
+  fn from(error: rusqlite::Error) -> StorageError { StorageError::Database(error.to_string()) }
+}
+
+impl SqliteStorage {
+  /// Open the database at `path`, creating it if it doesn't exist yet.
+  pub fn open(path: &Path, backups: usize) -> Result<SqliteStorage, StorageError> {
+    SqliteStorage::new(Connection::open(path)?, backups)
+  }
+
+  fn new(connection: Connection, backups: usize) -> Result<SqliteStorage, StorageError> {
+    connection.execute_batch(
+      "CREATE TABLE IF NOT EXISTS games (
+         source TEXT NOT NULL,
+         name TEXT NOT NULL,
+         data TEXT NOT NULL,
+         modified INTEGER NOT NULL,
+         PRIMARY KEY (source, name)
+       );
+       CREATE TABLE IF NOT EXISTS backups (
+         source TEXT NOT NULL,
+         name TEXT NOT NULL,
+         version INTEGER NOT NULL,
+         data TEXT NOT NULL,
+         modified INTEGER NOT NULL,
+         PRIMARY KEY (source, name, version)
+       );",
+    )?;
+    Ok(SqliteStorage { connection: Mutex::new(connection), backups })
+  }
+}
+
+fn source_key(source: ModuleSource) -> &'static str {
+  match source {
+    ModuleSource::Module => "module",
+    ModuleSource::SavedGame => "saved_game",
+  }
+}
+
+impl Storage for SqliteStorage {
+  fn list(&self, source: ModuleSource) -> Result<Vec<Metadata>, StorageError> {
+    let connection = self.connection.lock().expect("Database lock was poisoned");
+    // length() counts the characters of text, so it's cast to get the size in bytes.
+    let mut statement = connection.prepare(
+      "SELECT name, length(CAST(data AS BLOB)), modified FROM games WHERE source = ?1
+       ORDER BY name",
+    )?;
+    let rows = statement.query_map(params![source_key(source)], |row| {
+      Ok(Metadata {
+        name: row.get(0)?,
+        size: row.get::<_, i64>(1)? as u64,
+        modified: row.get::<_, i64>(2)? as u64,
+      })
+    })?;
+    Ok(rows.collect::<Result<Vec<Metadata>, _>>()?)
+  }
+
+  fn load(&self, source: ModuleSource, name: &str) -> Result<String, StorageError> {
+    check_name(name)?;
+    let connection = self.connection.lock().expect("Database lock was poisoned");
+    connection
+      .query_row(
+        "SELECT data FROM games WHERE source = ?1 AND name = ?2",
+        params![source_key(source), name],
+        |row| row.get(0),
+      )
+      .optional()?
+      .ok_or_else(|| StorageError::NotFound(name.to_string()))
+  }
+
+  fn save(&self, source: ModuleSource, name: &str, data: &str) -> Result<(), StorageError> {
+    check_name(name)?;
+    let source = source_key(source);
+    let modified = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let mut connection = self.connection.lock().expect("Database lock was poisoned");
+    let transaction = connection.transaction()?;
+    if self.backups > 0 {
+      let backups = self.backups as i64;
+      transaction.execute(
+        "DELETE FROM backups WHERE source = ?1 AND name = ?2 AND version >= ?3",
+        params![source, name, backups],
+      )?;
+      // Going through negative versions keeps them unique while they're renumbered.
+      transaction.execute(
+        "UPDATE backups SET version = -(version + 1) WHERE source = ?1 AND name = ?2",
+        params![source, name],
+      )?;
+      transaction.execute(
+        "UPDATE backups SET version = -version WHERE source = ?1 AND name = ?2",
+        params![source, name],
+      )?;
+      transaction.execute(
+        "INSERT INTO backups (source, name, version, data, modified)
+         SELECT source, name, 1, data, modified FROM games WHERE source = ?1 AND name = ?2",
+        params![source, name],
+      )?;
+    }
+    transaction.execute(
+      "INSERT OR REPLACE INTO games (source, name, data, modified) VALUES (?1, ?2, ?3, ?4)",
+      params![source, name, data, modified as i64],
+    )?;
+    transaction.commit()?;
+    Ok(())
+  }
+
+  fn delete(&self, source: ModuleSource, name: &str) -> Result<(), StorageError> {
+    check_name(name)?;
+    let source = source_key(source);
+    let mut connection = self.connection.lock().expect("Database lock was poisoned");
+    let transaction = connection.transaction()?;
+    let deleted = transaction
+      .execute("DELETE FROM games WHERE source = ?1 AND name = ?2", params![source, name])?;
+    if deleted == 0 {
+      return Err(StorageError::NotFound(name.to_string()));
+    }
+    transaction
+      .execute("DELETE FROM backups WHERE source = ?1 AND name = ?2", params![source, name])?;
+    transaction.commit()?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn t_storage(backups: usize) -> SqliteStorage {
+    SqliteStorage::new(Connection::open_in_memory().unwrap(), backups).unwrap()
+  }
+
+  fn backups(storage: &SqliteStorage) -> Vec<(i64, String)> {
+    let connection = storage.connection.lock().unwrap();
+    let mut statement =
+      connection.prepare("SELECT version, data FROM backups ORDER BY version").unwrap();
+    let rows = statement.query_map(params![], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+    rows.collect::<Result<Vec<_>, _>>().unwrap()
+  }
+
+  #[test]
+  fn save_load_and_delete() {
+    let storage = t_storage(2);
+    for version in &["one", "two", "three"] {
+      storage.save(ModuleSource::SavedGame, "game", version).unwrap();
+    }
+    assert_eq!(storage.load(ModuleSource::SavedGame, "game").unwrap(), "three");
+    assert_eq!(backups(&storage), vec![(1, "two".to_string()), (2, "one".to_string())]);
+    let names: Vec<String> =
+      storage.list(ModuleSource::SavedGame).unwrap().into_iter().map(|m| m.name).collect();
+    assert_eq!(names, vec!["game".to_string()]);
+    assert!(storage.list(ModuleSource::Module).unwrap().is_empty());
+
+    storage.delete(ModuleSource::SavedGame, "game").unwrap();
+    assert!(backups(&storage).is_empty());
+    match storage.load(ModuleSource::SavedGame, "game") {
+      Err(StorageError::NotFound(_)) => {}
+      r => panic!("Unexpected result: {:?}", r),
+    }
+  }
+
+  #[test]
+  fn sizes_are_in_bytes() {
+    let storage = t_storage(0);
+    storage.save(ModuleSource::SavedGame, "game", "caf\u{e9}").unwrap();
+    assert_eq!(storage.list(ModuleSource::SavedGame).unwrap()[0].size, 5);
+  }
+
+  #[test]
+  fn modules_are_kept_apart_from_saved_games() {
+    let storage = t_storage(0);
+    storage.save(ModuleSource::Module, "game", "module").unwrap();
+    storage.save(ModuleSource::SavedGame, "game", "saved").unwrap();
+    assert_eq!(storage.load(ModuleSource::Module, "game").unwrap(), "module");
+    assert_eq!(storage.load(ModuleSource::SavedGame, "game").unwrap(), "saved");
+  }
+}
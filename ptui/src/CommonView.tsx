@@ -30,9 +30,11 @@ const NARROW_THRESHOLD = 500;
 
 interface MainProps {
   rpi_url: string;
+  /// The player to play as from the start, instead of picking one from the full game.
+  player_id?: T.PlayerID;
 }
 export class Main extends React.Component<MainProps,
-  { store: "Unfetched" | "Error" | Redux.Store<M.PTUI> }> {
+  { store: "Unfetched" | Redux.Store<M.PTUI> }> {
 
   constructor(props: MainProps) {
     super(props);
@@ -40,27 +42,24 @@ export class Main extends React.Component<MainProps,
   }
 
   componentDidMount() {
-    // kick off a fetch of the app
-    M.decodeFetch(this.props.rpi_url, undefined, T.decodeApp).then(
-      app => {
-        const ptui = new M.PTUI(this.props.rpi_url, app);
-        const store = Redux.createStore(M.update, ptui, Redux.applyMiddleware(thunk));
-        ptui.startPoll(store.dispatch);
-        this.setState({ store });
-      }
-    ).catch(err => {
-      console.log("[Main.componentDidMount] [error]", err);
-      this.setState({ store: "Error" });
-      this.componentDidMount();
-    });
+    // Start following the app. The first event has the whole app, which the store starts with.
+    const { rpi_url, player_id } = this.props;
+    M.follow(
+      action => {
+        const store = this.state.store;
+        if (typeof store !== "string") {
+          store.dispatch(action);
+        } else if (action.type === "RefreshApp") {
+          const ptui = new M.PTUI(rpi_url, action.app, { ...M.default_state, player_id });
+          this.setState({ store: Redux.createStore(M.update, ptui, Redux.applyMiddleware(thunk)) });
+        }
+      },
+      rpi_url, player_id);
   }
 
   render(): JSX.Element {
     if (this.state.store === "Unfetched") {
       return <div>Waiting for initial data from server.</div>;
-    } else if (this.state.store === "Error") {
-      return <div>There was an error fetching the application state from {this.props.rpi_url}.
-         Trying again...</div>;
     } else {
       return <Provider store={this.state.store}>{this.props.children}</Provider>;
     }
@@ -437,15 +436,18 @@ interface CombatProps {
 }
 export const Combat = M.connectRedux(
   function Combat({ combat, card, ptui, initiative }: CombatProps & M.ReduxProps): JSX.Element {
+    // Players only get the creatures they can see, so skip any that are missing rather than
+    // trusting the indices to line up with the cursor.
     const creatures_with_init = M.filterMap(combat.creatures.data,
       ([cid, init]) => {
         const creature = ptui.getCreature(cid);
         if (creature) { return [creature, init]; }
       }) as Array<[T.Creature, number]>;
+    const current_cid = ptui.getCurrentCombatCreatureID(combat);
 
     const Card = card ? card : CreatureCard;
     return <Segment.Group>
-      {creatures_with_init.map(([creature, init], index) => {
+      {creatures_with_init.map(([creature, init]) => {
         const show_init = initiative ? initiative(creature, init) : null;
         return <Segment.Group key={creature.id} horizontal={true}>
           <Segment compact={true}
@@ -453,7 +455,7 @@ export const Combat = M.connectRedux(
               width: "25px", paddingLeft: 0, paddingRight: 0,
               display: "flex", flexDirection: "column", alignItems: "center",
             }}>
-            <div style={{ height: "25px" }}>{index === combat.creatures.cursor ? "▶️" : ""}</div>
+            <div style={{ height: "25px" }}>{creature.id === current_cid ? "▶️" : ""}</div>
             <div>{show_init}</div>
           </Segment>
           <Card creature={creature} />
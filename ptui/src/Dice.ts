@@ -5,7 +5,7 @@ function spaced<X>(parser: P.Parser<X>): P.Parser<X> {
   return P.optWhitespace.then(parser).skip(P.optWhitespace);
 }
 
-const dicep = P.lazy(() => dicep_);
+const dicep: P.Parser<T.Dice> = P.lazy(() => dicep_);
 
 const digits =
   spaced(
@@ -15,40 +15,71 @@ const digits =
 const flat =
   spaced(
     digits
-      .map(n => ({ t: 'Flat', val: n }))
+      .map((val): T.Dice => ({ t: 'Flat', val }))
       .desc('Flat'));
 const expr =
   spaced(
-    P.seq(spaced(digits).skip(spaced(P.string('d'))), spaced(digits))
-      .map(([num, size]): T.Dice => ({ t: "Expr", num, size }))
+    P.seq(
+      digits.or(P.succeed(1)).skip(spaced(P.regexp(/d/i))),
+      digits,
+      P.regexp(/(kh|kl|dl|dh|k|r)\d+|!/i).or(P.succeed("")))
+      .map(([num, size, modifier]) => withModifier(num, size, modifier))
       .desc('Expr'));
 
-const plus: P.Parser<T.Dice> =
-  spaced(
-    P.seq(spaced(expr).skip(spaced(P.string('+'))), spaced(dicep))
-      .map(([left, right]): T.Dice => ({ t: "Plus", left, right }))
-      .desc('Plus'));
-
-const minus: P.Parser<T.Dice> =
-  // TODO: the data model doesn't support subtraction *in general* --
-  // i.e. 1d20-1d8 is not representable, but we can support 1d20-2 at least.
-  spaced(
-    P.seq(spaced(expr).skip(spaced(P.string('-'))), spaced(digits))
-      .map(([left, num]): T.Dice => ({ t: "Plus", left, right: { t: "Flat", val: -num } }))
-      .desc('Minus'));
+/// Interpret the modifier after NdM, like the "kh3" in "4d6kh3".
+function withModifier(num: number, size: number, modifier: string): T.Dice {
+  if (modifier === "!") {
+    return { t: "Exploding", num, size };
+  }
+  const match = /^([a-z]+)(\d+)$/i.exec(modifier);
+  if (!match) {
+    return { t: "Expr", num, size };
+  }
+  const arg = Number(match[2]);
+  switch (match[1].toLowerCase()) {
+    case "k":
+    case "kh": return { t: "KeepHighest", num, size, keep: arg };
+    case "kl": return { t: "KeepLowest", num, size, keep: arg };
+    case "dl": return { t: "KeepHighest", num, size, keep: num - arg };
+    case "dh": return { t: "KeepLowest", num, size, keep: num - arg };
+    default: return { t: "Reroll", num, size, threshold: arg };
+  }
+}
 
-const sum = P.alt(plus, minus);
+const parens: P.Parser<T.Dice> =
+  spaced(P.string("(")).then(dicep).skip(spaced(P.string(")")));
 
 const bestof: P.Parser<T.Dice> =
   spaced(
-    P.seq(
-      spaced(P.string("BestOf")), spaced(P.string("(")),
-      spaced(digits),
-      spaced(P.string(",")), spaced(dicep), spaced(P.string(")")))
-      .map(([_, _2, num, _3, dice]): T.Dice => ({ t: "BestOf", num, dice }))
+    P.alt(
+      P.seq(
+        spaced(P.regexp(/best/i)).then(digits).skip(spaced(P.regexp(/of/i))),
+        P.lazy(() => term)),
+      // The old syntax that this parser used to accept.
+      P.seq(
+        spaced(P.string("BestOf")).then(spaced(P.string("("))).then(digits),
+        spaced(P.string(",")).then(dicep).skip(spaced(P.string(")")))))
+      .map(([num, dice]): T.Dice => ({ t: "BestOf", num, dice }))
       .desc("BestOf"));
 
-const dicep_ = P.alt(bestof, sum, expr, flat);
+const worstof: P.Parser<T.Dice> =
+  spaced(
+    P.seq(
+      spaced(P.regexp(/worst/i)).then(digits).skip(spaced(P.regexp(/of/i))),
+      P.lazy(() => term))
+      .map(([num, dice]): T.Dice => ({ t: "WorstOf", num, dice }))
+      .desc("WorstOf"));
+
+const term: P.Parser<T.Dice> = P.alt(parens, bestof, worstof, expr, flat);
+
+// The data model doesn't support subtraction *in general* -- i.e. 1d20-1d8 is not representable,
+// but we can support 1d20-2 at least.
+const negative: P.Parser<T.Dice> =
+  spaced(P.string("-")).then(digits).map((num): T.Dice => ({ t: "Flat", val: -num }));
+
+const dicep_: P.Parser<T.Dice> =
+  P.seq(P.alt(negative, term), P.alt(spaced(P.string("+")).then(term), negative).many())
+    .map(([first, rest]) => rest.reduce((left, right): T.Dice => ({ t: "Plus", left, right }), first));
 
 export function parse(input: string): T.Dice {
   return dicep.tryParse(input);
@@ -71,9 +102,16 @@ export function format(d: T.Dice): string {
           } else {
             return def;
           }
+        case "Plus": return format(d.left) + "+(" + format(d.right) + ")";
         default: return def;
       }
     case "BestOf":
-      return "BestOf(" + d.num.toString() + ", " + format(d.dice) + ")";
+      return "best " + d.num.toString() + " of (" + format(d.dice) + ")";
+    case "WorstOf":
+      return "worst " + d.num.toString() + " of (" + format(d.dice) + ")";
+    case "KeepHighest": return d.num + "d" + d.size + "kh" + d.keep;
+    case "KeepLowest": return d.num + "d" + d.size + "kl" + d.keep;
+    case "Exploding": return d.num + "d" + d.size + "!";
+    case "Reroll": return d.num + "d" + d.size + "r" + d.threshold;
   }
 }
@@ -106,7 +106,7 @@ export const CreateScene = M.connectRedux(class CreateScene
     // default values here and the user can edit the map after creation
     const spec = {
       name, background_image_url, background_image_offset: undefined,
-      background_image_scale: [0, 0] as [number, number],
+      background_image_scale: [0, 0] as [number, number], fog_of_war: false,
     };
     dispatch(M.sendCommand({ t: "CreateScene", path, spec }));
     onDone();
@@ -132,6 +132,7 @@ class EditSceneName
           background_image_url: scene.background_image_url,
           background_image_offset: scene.background_image_offset,
           background_image_scale: scene.background_image_scale,
+          fog_of_war: scene.fog_of_war,
         },
       }));
     this.props.onDone();
@@ -140,10 +141,13 @@ class EditSceneName
 
 class EditSceneBackground
   extends React.Component<{ scene: T.Scene; onDone: () => void } & M.DispatchProps,
-  { pinned: boolean }> {
+  { pinned: boolean; fog_of_war: boolean }> {
   constructor(props: { scene: T.Scene; onDone: () => void } & M.DispatchProps) {
     super(props);
-    this.state = { pinned: props.scene.background_image_offset !== undefined };
+    this.state = {
+      pinned: props.scene.background_image_offset !== undefined,
+      fog_of_war: props.scene.fog_of_war,
+    };
   }
   render(): JSX.Element {
     const { scene } = this.props;
@@ -168,6 +172,8 @@ class EditSceneBackground
           style={{ width: "100px" }}
           default={scene.background_image_offset ? scene.background_image_offset[1] : 0} />
       </Form.Group>
+      <Form.Checkbox label='Fog of war' checked={this.state.fog_of_war}
+        onChange={(_, d) => this.setState({ fog_of_war: d.checked as boolean })} />
       <Submit onClick={data => this.save(data)}>Save</Submit>
     </CoolForm>;
   }
@@ -179,6 +185,7 @@ class EditSceneBackground
       ? [offset_x, offset_y] : undefined;
     const details = {
       name: scene.name, background_image_url, background_image_scale, background_image_offset,
+      fog_of_war: this.state.fog_of_war,
     };
     this.props.dispatch(M.sendCommand({ t: "EditSceneDetails", scene_id: scene.id, details }));
     this.props.onDone();
@@ -981,7 +988,7 @@ export const CreateCreature = M.connectRedux(
     const init: T.Dice = { t: "Expr", num: 1, size: 20 };
     const creature_data = {
       name: "", note: "", bio: "", portrait_url: "", initiative: init, class_: "",
-      size: { x: 1, y: 1, z: 1 }, icon_url: "",
+      size: { x: 1, y: 1, z: 1 }, icon_url: "", sight: 1000,
     };
     return <EditCreatureData creature={creature_data}
       onSave={cdata => save(cdata)} onClose={props.onClose} />;
@@ -1010,6 +1017,7 @@ const GMEditCreature = M.connectRedux(
         icon_url: creature_data.icon_url,
         initiative: creature_data.initiative,
         size: creature_data.size,
+        sight: creature_data.sight,
       };
       ptui.sendCommand(dispatch, { t: "EditCreatureDetails", creature_id: creature.id, details });
       onClose();
@@ -1027,7 +1035,7 @@ class EditCreatureDataComp
   {
     name: string; portrait_url: string; note: string; bio: string;
     initiative_string: string; class_: string;
-    size: number; icon_url: string;
+    size: number; icon_url: string; sight: number;
   }> {
   constructor(props: EditCreatureDataProps & M.ReduxProps) {
     super(props);
@@ -1039,6 +1047,7 @@ class EditCreatureDataComp
       class_: props.creature.class_,
       size: props.creature.size.x,
       icon_url: props.creature.icon_url,
+      sight: props.creature.sight,
     };
   }
 
@@ -1103,6 +1112,12 @@ class EditCreatureDataComp
             { key: 'huge', text: 'Huge', value: 3 },
             ]} />
         </Form.Field>
+        <Form.Field style={{ flex: 2 }}>
+          <Form.Input label="Sight (meters)" type="number" min={0}
+            value={this.state.sight / 100}
+            onChange={(_, data) =>
+              this.setState({ sight: Math.round(Number(data.value) * 100) })} />
+        </Form.Field>
       </Form.Group>
       {
         parsed_initiative.status
@@ -1136,6 +1151,7 @@ class EditCreatureDataComp
       initiative: Dice.parse(this.state.initiative_string),
       size: { x: this.state.size, y: this.state.size, z: this.state.size },
       icon_url: this.state.icon_url,
+      sight: this.state.sight,
     };
     this.props.onSave(creature);
     this.props.onClose();
@@ -21,6 +21,7 @@ interface SceneGridState {
   targeting_point?: T.Point3;
   affected_points?: Array<T.Point3>;
   affected_creatures?: Array<T.CreatureID>;
+  chosen_creatures?: Array<T.CreatureID>;
   painting?: "Opening" | "Closing";
 }
 export const SceneGrid = M.connectRedux(class SceneGrid
@@ -315,6 +316,7 @@ export const SceneGrid = M.connectRedux(class SceneGrid
     switch (action.t) {
       case "Creature":
         switch (action.target.t) {
+          case "SomeCreaturesInVolumeInRange":
           case "AllCreaturesInVolumeInRange":
             return svgVolume("target-volume", action.target.volume, target);
           case "LineFromActor":
@@ -355,7 +357,7 @@ export const SceneGrid = M.connectRedux(class SceneGrid
           default: return;
         }
     }
-    this.setState({ targeting_point: point });
+    this.setState({ targeting_point: point, chosen_creatures: [] });
     M.fetchAbilityTargets(dispatch, ptui.rpi_url, this.props.scene.id, options.cid,
       options.ability_id, point).then(
         ({ points, creatures }) =>
@@ -470,16 +472,19 @@ export const SceneGrid = M.connectRedux(class SceneGrid
   getTargetTiles(
     options: T.PotentialTargets,
     onClick: (pt: T.Point3) => void): JSX.Element[] | undefined {
+    function tiles(points: Array<T.Point3>) {
+      return points.map(pt => {
+        const rprops = tile_props("pink", pt, { x: 1, y: 1 }, 0.3);
+        function clickTile() {
+          onClick(pt);
+        }
+        return <rect key={pointKey("target", pt)} {...rprops} onClick={clickTile} />;
+      });
+    }
     switch (options.t) {
       case "CreatureIDs": return undefined;
-      case "Points":
-        return options.points.map(pt => {
-          const rprops = tile_props("pink", pt, { x: 1, y: 1 }, 0.3);
-          function clickTile() {
-            onClick(pt);
-          }
-          return <rect key={pointKey("target", pt)} {...rprops} onClick={clickTile} />;
-        });
+      case "Points": return tiles(options.points);
+      case "VolumePlacements": return tiles(options.placements.map(([pt]) => pt));
     }
   }
 
@@ -497,6 +502,19 @@ export const SceneGrid = M.connectRedux(class SceneGrid
 
   topBar(): JSX.Element {
     const { ptui, dispatch } = this.props;
+    const maximum = this.maximumChoices();
+    if (this.state.targeting_point && maximum !== undefined) {
+      const chosen = this.state.chosen_creatures || [];
+      const candidates = ptui.getCreatures(this.state.affected_creatures || []);
+      return <div>Choose up to {maximum} creatures:
+        {candidates.map(c =>
+          <Button key={c.id} active={LD.includes(chosen, c.id)}
+            onClick={() => this.toggleChosen(c.id)}>{c.name}</Button>)}
+        <Button disabled={chosen.length === 0 || chosen.length > maximum}
+          onClick={() => this.executeCreaturesTargetedAbility()}>Act</Button>
+        <Button onClick={() => this.clearTargets()}>Cancel</Button>
+      </div>;
+    }
     if (this.state.targeting_point) {
       return <div>Proceed with action?
         <Button onClick={() => this.executePointTargetedAbility()}>Act</Button>
@@ -521,9 +539,36 @@ export const SceneGrid = M.connectRedux(class SceneGrid
   clearTargets() {
     this.setState({
       affected_points: undefined, affected_creatures: undefined, targeting_point: undefined,
+      chosen_creatures: undefined,
     });
   }
 
+  /** How many creatures can be chosen, if the ability being targeted lets them be chosen. */
+  maximumChoices(): number | undefined {
+    const { ptui } = this.props;
+    const options = ptui.state.grid.target_options;
+    if (!options) { return; }
+    const ability = ptui.getAbility(options.ability_id);
+    if (ability && ability.action.t === "Creature"
+      && ability.action.target.t === "SomeCreaturesInVolumeInRange") {
+      return ability.action.target.maximum;
+    }
+  }
+
+  toggleChosen(cid: T.CreatureID) {
+    const chosen = this.state.chosen_creatures || [];
+    this.setState({
+      chosen_creatures: LD.includes(chosen, cid) ? LD.without(chosen, cid) : [...chosen, cid],
+    });
+  }
+
+  executeCreaturesTargetedAbility() {
+    if (!this.state.chosen_creatures) { return; }
+    this.props.ptui.executeCombatCreaturesTargetedAbility(
+      this.props.dispatch, this.state.chosen_creatures);
+    this.clearTargets();
+  }
+
   executePointTargetedAbility() {
     if (!this.state.targeting_point) { return; }
     this.props.ptui.executeCombatPointTargetedAbility(
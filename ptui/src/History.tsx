@@ -9,30 +9,70 @@ import * as T from './PTTypes';
 
 interface HistoryDerivedProps {
   snapshots: Array<T.Snapshot>;
+  branches: Array<T.BranchInfo>;
+  current_branch: number;
+  undone: number;
   creatures: I.Map<T.CreatureID, T.Creature>;
 }
 export const History = ReactRedux.connect(
   Comp.createDeepEqualSelector(
-    [(ptui: M.PTUI) => ptui.app.snapshots, ptui => ptui.app.current_game.creatures],
-    (snapshots, creatures) => ({ snapshots, creatures })),
+    [(ptui: M.PTUI) => ptui.app.snapshots, ptui => ptui.app.branches,
+    ptui => ptui.app.current_branch, ptui => ptui.app.undone,
+    ptui => ptui.app.current_game.creatures],
+    (snapshots, branches, current_branch, undone, creatures) =>
+      ({ snapshots, branches, current_branch, undone, creatures })),
   (dispatch: M.Dispatch) => ({ dispatch }),
 )(function History(props: HistoryDerivedProps & M.DispatchProps): JSX.Element {
-  const { snapshots, creatures, dispatch } = props;
+  const { snapshots, branches, current_branch, undone, creatures, dispatch } = props;
   console.log("[EXPENSIVE:History.render]");
+  return <div>
+    <div>
+      <button onClick={() => dispatch(M.sendCommand({ t: "Undo" }))}>Undo</button>
+      <button disabled={undone === 0} onClick={() => dispatch(M.sendCommand({ t: "Redo" }))}>
+        Redo
+      </button>
+    </div>
+    <Branches branches={branches} current_branch={current_branch} dispatch={dispatch} />
+    {
+      snapshots.map(
+        ({ logs }, snapshot_index) =>
+          logs.map((log: T.GameLog, log_index) =>
+            <div style={{ display: "flex", flexDirection: "row", justifyContent: "space-between" }}
+              key={snapshot_index.toString() + "-" + log_index.toString()}>
+              <GameLog log={log} creatures={creatures} />
+              <button className="material-icons"
+                onClick={() =>
+                  dispatch(M.sendCommand({ t: "Rollback", snapshot_index, log_index }))}
+              >history</button>
+            </div>)
+      )
+    }
+  </div>;
+});
+
+// A list of the branches of history, to switch between them.
+function Branches(
+  props: { branches: Array<T.BranchInfo>; current_branch: number } & M.DispatchProps
+): JSX.Element | null {
+  const { branches, current_branch, dispatch } = props;
+  if (branches.length < 2) { return null; }
   return <div>{
-    snapshots.map(
-      ({ logs }, snapshot_index) =>
-        logs.map((log: T.GameLog, log_index) =>
-          <div style={{ display: "flex", flexDirection: "row", justifyContent: "space-between" }}
-            key={snapshot_index.toString() + "-" + log_index.toString()}>
-            <GameLog log={log} creatures={creatures} />
-            <button className="material-icons"
-              onClick={() => dispatch(M.sendCommand({ t: "Rollback", snapshot_index, log_index }))}
-            >history</button>
-          </div>)
-    )
+    branches.map(({ parent, log_count }, branch) =>
+      <div key={branch}
+        style={{ display: "flex", flexDirection: "row", justifyContent: "space-between" }}>
+        <div>
+          Branch {branch}{parent
+            ? ` (from branch ${parent.branch} at ${parent.snapshot_idx}/${parent.log_idx})`
+            : ""}: {log_count} logs
+        </div>
+        {branch === current_branch
+          ? <strong>current</strong>
+          : <button onClick={() => dispatch(M.sendCommand({ t: "SwitchBranch", branch }))}>
+            Switch
+          </button>}
+      </div>)
   }</div>;
-});
+}
 
 
 export function GameLog(props: { log: T.GameLog; creatures: I.Map<T.CreatureID, T.Creature> }):
@@ -139,6 +179,24 @@ export function GameLog(props: { log: T.GameLog; creatures: I.Map<T.CreatureID,
       return creature_log(creatures, log.creature_id, log.log);
     case "Rollback":
       return <div>Rolled back to {log.snapshot_index}/{log.log_index}</div>;
+    case "SwitchBranch":
+      return <div>Switched to branch {log.branch}</div>;
+    case "SetVettingPolicy":
+      return <div>Set player command approval to {log.policy}</div>;
+    case "EditLog":
+      return <div>
+        Edited log {log.snapshot_index}/{log.log_index}:
+        <GameLog log={log.log} creatures={creatures} />
+        {log.invalidated.map((inv, idx) =>
+          <div key={idx} style={{ color: "red" }}>
+            ✗ {inv.snapshot_index}/{inv.log_index}: {inv.error}
+            <GameLog log={inv.log} creatures={creatures} />
+          </div>)}
+      </div>;
+    case "Undo":
+      return <div>Undid a command</div>;
+    case "Redo":
+      return <div>Redid a command</div>;
     case "LoadModule":
       return <div>Loaded module {log.name}</div>;
   }
@@ -10,7 +10,10 @@ import * as T from './PTTypes';
 export type Action =
   | { type: "ResetState" }
   | { type: "RefreshApp"; app: T.App }
-  | { type: "RefreshGame"; game: T.Game; logs: Array<T.GameLog> }
+  | {
+    type: "ApplyLogs";
+    logs: Array<T.GameLog>; pending: I.Map<T.PlayerID, Array<T.GameLog>>; game: T.Game;
+  }
   | { type: "DisplayError"; error: string }
   | { type: "ClearError" }
 
@@ -50,8 +53,16 @@ export function update(ptui: PTUI, action: Action): PTUI {
       return new PTUI(ptui.rpi_url, ptui.app);
     case "RefreshApp":
       return new PTUI(ptui.rpi_url, action.app, ptui.state);
-    case "RefreshGame":
-      return update(ptui, { type: "RefreshApp", app: { ...ptui.app, current_game: action.game } });
+    case "ApplyLogs":
+      return update(ptui, {
+        type: "RefreshApp",
+        app: {
+          ...ptui.app,
+          snapshots: appendLogs(ptui.app.snapshots, action.logs),
+          pending: action.pending,
+          current_game: action.game,
+        },
+      });
     case "ActivateGridObjects":
       return ptui.updateGridState(grid =>
         ({ ...grid, active_objects: { objects: action.objects, coords: action.coords } }));
@@ -204,10 +215,18 @@ export type SecondaryFocus =
   | { t: "Item"; item_id: T.ItemID }
   ;
 
+/// The token that identifies us to the RPI server, given as the page's `token` query parameter.
+/// It's only needed when the server has been started with credentials.
+const auth_token = new URLSearchParams(window.location.search).get("token");
+
 export function decodeFetch<J>(
   url: string, init: RequestInit | undefined,
   decoder: JD.Decoder<J>): Promise<J> {
-  const p: Promise<Response> = fetch(url, init);
+  const headers = new Headers(init ? init.headers : undefined);
+  if (auth_token) {
+    headers.set("authorization", `Bearer ${auth_token}`);
+  }
+  const p: Promise<Response> = fetch(url, { ...init, headers });
   const p2: Promise<any> = p.then(response => response.json());
   return p2.then(json => {
     try {
@@ -245,7 +264,68 @@ export function ptfetch<J, R>(
   return p5;
 }
 
-const default_state = {
+function appendLogs(snapshots: Array<T.Snapshot>, logs: Array<T.GameLog>): Array<T.Snapshot> {
+  if (snapshots.length === 0) { return snapshots; }
+  const last = snapshots[snapshots.length - 1];
+  return [...snapshots.slice(0, -1), { ...last, logs: last.logs.concat(logs) }];
+}
+
+/// Apply a JSON Patch from the server to some JSON, in place. The server only sends `add`,
+/// `remove` and `replace` operations.
+function applyPatch(doc: any, patch: Array<{ op: string; path: string; value?: any }>): any {
+  for (const op of patch) {
+    const keys = op.path.split("/").slice(1).map(k => k.replace(/~1/g, "/").replace(/~0/g, "~"));
+    const last = keys.pop();
+    if (last === undefined) {
+      doc = op.value;
+      continue;
+    }
+    const parent = keys.reduce((obj, key) => obj[key], doc);
+    if (op.op === "remove") {
+      delete parent[last];
+    } else {
+      parent[last] = op.value;
+    }
+  }
+  return doc;
+}
+
+/// The stream of changes to the app that we're following. See `follow`.
+let app_events: EventSource | undefined;
+
+/// Keep the app up to date by following the changes the server pushes out, instead of downloading
+/// the whole thing again. The first event is the whole app. With a `player_id`, only that player's
+/// view of the app is followed.
+export function follow(
+  dispatch: (action: Action) => void, rpi_url: string, player_id?: T.PlayerID
+) {
+  if (app_events) { app_events.close(); }
+  const prefix = player_id ? `${rpi_url}/player/${encodeURIComponent(player_id)}` : rpi_url;
+  // Event streams can't have headers, so the token goes in the URL.
+  const token = auth_token ? `?token=${encodeURIComponent(auth_token)}` : "";
+  // The game as the server last described it, before decoding, for patches to apply to.
+  let game_json: any;
+  app_events = new EventSource(`${prefix}/events${token}`);
+  app_events.onmessage = msg => {
+    const data = JSON.parse(msg.data);
+    const event = T.decodeAppEvent.decodeAny(data);
+    switch (event.t) {
+      case "App":
+        game_json = data.App.current_game;
+        dispatch({ type: "RefreshApp", app: event.app });
+        return;
+      case "Logs":
+        game_json = applyPatch(game_json, data.Logs.patch);
+        dispatch({
+          type: "ApplyLogs", logs: event.logs, pending: event.pending,
+          game: T.decodeGame.decodeAny(game_json),
+        });
+        return;
+    }
+  };
+}
+
+export const default_state = {
   grid: {
     highlight_color: "#FF0000",
     object_visibility: { t: "AllPlayers" } as T.Visibility,
@@ -264,22 +344,6 @@ export class PTUI {
     this.rpi_url = rpi_url;
   }
 
-  startPoll(dispatch: Dispatch) {
-    function poll(rpi_url: string, app: T.App) {
-      const num_snaps = app.snapshots.length;
-      const snaps = app.snapshots[num_snaps - 1];
-      const num_logs = snaps ? snaps.logs.length : 0;
-      const url = `${rpi_url}/poll/${num_snaps}/${num_logs}`;
-      return ptfetch(dispatch, url, undefined, T.decodeApp,
-        app => {
-          dispatch({ type: "RefreshApp", app });
-          poll(rpi_url, app);
-        }
-      );
-    }
-    poll(this.rpi_url, this.app);
-  }
-
   updateState(updater: (state: PTUIState) => PTUIState): PTUI {
     return new PTUI(this.rpi_url, this.app, updater(this.state));
   }
@@ -335,18 +399,15 @@ export class PTUI {
     : Promise<T.RustResult<Array<T.GameLog>, string>> {
     const json = T.encodeGameCommand(cmd);
     console.log("[sendCommand:JSON]", json);
+    const pid = this.state.player_id;
     const rpi_result = decodeFetch(
-      this.rpi_url,
+      pid ? `${this.rpi_url}/player/${encodeURIComponent(pid)}` : this.rpi_url,
       {
         method: "POST",
         body: JSON.stringify(json),
         headers: { "content-type": "application/json" },
       },
-      T.decodeRustResult(
-        JD.map(
-          ([_, logs]) => logs,
-          T.decodeSendCommandResult),
-        JD.string())
+      T.decodeRustResult(T.decodeSendCommandResult, JD.string())
     );
     return rpi_result;
   }
@@ -426,6 +487,18 @@ export class PTUI {
     dispatch({ type: "ClearPotentialTargets" });
   }
 
+  executeCombatCreaturesTargetedAbility(dispatch: Dispatch, creature_ids: Array<T.CreatureID>) {
+    const opts = this.state.grid.target_options;
+    if (!opts) { throw new Error(`Can't execute an ability if we haven't selected it first.`); }
+    const { ability_id, options } = opts;
+    if (options.t !== "VolumePlacements") {
+      throw new Error(`This function only works for abilities that use VolumePlacements`);
+    }
+    const target: T.DecidedTarget = { t: "Creatures", creature_ids };
+    this.sendCommand(dispatch, { t: "CombatAct", ability_id, target });
+    dispatch({ type: "ClearPotentialTargets" });
+  }
+
   // Utility functions for interacting with the model
   // TODO: Consider making Game, Combat, Folder classes and moving these methods to those classes.
   // But I'm not sure it'd really matter -- if I find myself really needing to increase isolation
@@ -589,19 +662,22 @@ export const sendCommand = (cmd: T.GameCommand): ThunkAction<void> =>
     const ptui = getState();
     const json = T.encodeGameCommand(cmd);
     console.log("[sendCommand:JSON]", json);
+    // Commands from players go through their own endpoint, so the GM can vet them.
+    const pid = ptui.state.player_id;
+    const url = pid ? `${ptui.rpi_url}/player/${encodeURIComponent(pid)}` : ptui.rpi_url;
     ptfetch(
       dispatch,
-      ptui.rpi_url,
+      url,
       {
         method: "POST",
         body: JSON.stringify(json),
         headers: { "content-type": "application/json" },
       },
       T.decodeRustResult(T.decodeSendCommandResult, JD.string()),
-      (x: T.RustResult<[T.Game, Array<T.GameLog>], string>) => {
+      (x: T.RustResult<Array<T.GameLog>, string>) => {
         switch (x.t) {
           case "Ok":
-            dispatch({ type: "RefreshGame", game: x.result[0], logs: x.result[1] });
+            // The new game arrives through `follow`.
             return;
           case "Err":
             throw { _pt_error: 'RPI', message: x.error };
@@ -616,6 +692,30 @@ export const sendCommands = (cmds: Array<T.GameCommand>): ThunkAction<void> =>
     }
   };
 
+/// Start playing as the given player, switching over to their view of the app.
+export const selectPlayer = (pid: T.PlayerID): ThunkAction<void> =>
+  (dispatch, getState) => {
+    dispatch({ type: "SetPlayerID", pid });
+    follow(dispatch, getState().rpi_url, pid);
+  };
+
+/// Register a new player, and then start playing as them.
+export const registerPlayer = (pid: T.PlayerID): ThunkAction<void> =>
+  (dispatch, getState) => {
+    // Players register themselves through their own endpoint.
+    dispatch({ type: "SetPlayerID", pid });
+    getState().sendCommandWithResult({ t: "RegisterPlayer", player_id: pid }).then(result => {
+      switch (result.t) {
+        case "Ok":
+          selectPlayer(pid)(dispatch, getState, undefined);
+          return;
+        case "Err":
+          dispatch({ type: "DisplayError", error: result.error });
+          return;
+      }
+    });
+  };
+
 export const newGame: ThunkAction<void> =
   (dispatch, getState) => {
     const ptui = getState();
@@ -690,6 +790,6 @@ export function getCreaturePos(scene: T.Scene, creature_id: T.CreatureID): T.Poi
 }
 
 export function getCreatureDetails(creature: T.Creature): T.CreatureCreation {
-  const { name, class_, portrait_url, note, bio, initiative, size, icon_url } = creature;
-  return { name, class_, portrait_url, note, bio, initiative, size, icon_url };
+  const { name, class_, portrait_url, note, bio, initiative, size, icon_url, sight } = creature;
+  return { name, class_, portrait_url, note, bio, initiative, size, icon_url, sight };
 }
@@ -3,6 +3,8 @@ import * as LD from "lodash";
 import * as JD from "type-safe-json-decoder";
 import { Decoder } from "type-safe-json-decoder";
 
+import * as DiceNotation from "./Dice";
+
 export type AbilityID = string;
 export type ClassID = string;
 export type CreatureID = string;
@@ -45,11 +47,19 @@ export class Point3 implements I.ValueObject {
 
 export interface App {
   snapshots: Array<Snapshot>;
+  branches: Array<BranchInfo>;
+  current_branch: number;
+  undone: number;
+  // The logs of commands from each player that are waiting for the GM's approval.
+  pending: I.Map<PlayerID, Array<GameLog>>;
   current_game: Game;
 }
 
 export interface Snapshot { snapshot: {}; logs: Array<GameLog>; }
 
+export interface BranchInfo { parent: BranchPoint | undefined; log_count: number; }
+export interface BranchPoint { branch: number; snapshot_idx: number; log_idx: number; }
+
 export interface Game {
   current_combat: Combat | undefined;
   creatures: I.Map<CreatureID, Creature>;
@@ -59,6 +69,7 @@ export interface Game {
   abilities: { [index: string]: Ability };
   campaign: Folder;
   players: I.Map<PlayerID, Player>;
+  vetting_policy: VettingPolicy;
 }
 
 export interface Combat {
@@ -85,11 +96,18 @@ export type CreatureTarget =
   | { t: "Melee" }
   | { t: "Range"; distance: Distance }
   | { t: "Actor" }
-  | { t: "SomeCreaturesInVolumeInRange"; volume: Volume; maximum: number; range: Distance }
-  | { t: "AllCreaturesInVolumeInRange"; volume: Volume; range: Distance }
+  | {
+    t: "SomeCreaturesInVolumeInRange"; volume: Volume; maximum: number; range: Distance;
+    propagation: Propagation;
+  }
+  | {
+    t: "AllCreaturesInVolumeInRange"; volume: Volume; range: Distance; propagation: Propagation;
+  }
   | { t: "LineFromActor"; distance: Distance }
   ;
 
+export type Propagation = 'Burst' | 'WrapAround';
+
 export interface SceneTarget { t: "RangedVolume"; volume: Volume; range: Distance; }
 
 export type DecidedTarget =
@@ -172,11 +190,20 @@ export type GameCommand =
   | { t: "AttributeCheck"; creature_id: CreatureID; check: AttributeCheck }
   | { t: "SetPlayerScene"; player_id: PlayerID; scene_id: SceneID | undefined }
   | { t: "Rollback"; snapshot_index: number; log_index: number }
+  | { t: "SwitchBranch"; branch: number }
+  | { t: "SetVettingPolicy"; policy: VettingPolicy }
+  | { t: "ApprovePendingCommand"; player_id: PlayerID }
+  | { t: "RejectPendingCommand"; player_id: PlayerID }
+  | { t: "EditPendingCommand"; player_id: PlayerID; command: GameCommand }
+  | { t: "Undo" }
+  | { t: "Redo" }
   | { t: "LoadModule"; source: ModuleSource; name: string; path: FolderPath }
   ;
 
 export type ModuleSource = 'Module' | 'SavedGame';
 
+export type VettingPolicy = 'Never' | 'ActionsOnNPCs' | 'AllCommands';
+
 export interface CreatureCreation {
   name: string;
   class_: string;
@@ -186,6 +213,7 @@ export interface CreatureCreation {
   bio: string;
   initiative: Dice;
   size: AABB;
+  sight: number;
 }
 
 export interface Class {
@@ -258,9 +286,24 @@ export type GameLog =
   | { t: "CreatureLog"; creature_id: CreatureID; log: CreatureLog }
   | { t: "StopCombat" }
   | { t: "Rollback"; snapshot_index: number; log_index: number }
+  | { t: "SwitchBranch"; branch: number }
+  | { t: "SetVettingPolicy"; policy: VettingPolicy }
+  | { t: "Undo" }
+  | { t: "Redo" }
+  | {
+    t: "EditLog"; snapshot_index: number; log_index: number; log: GameLog;
+    invalidated: Array<InvalidatedLog>;
+  }
   | { t: "LoadModule"; source: ModuleSource; name: string; path: FolderPath } // `module` is left out
   ;
 
+export interface InvalidatedLog {
+  snapshot_index: number;
+  log_index: number;
+  log: GameLog;
+  error: string;
+}
+
 export type CombatLog =
   | { t: "ConsumeMovement"; distance: Distance }
   | { t: "ChangeCreatureInitiative"; creature_id: CreatureID; init: number }
@@ -333,6 +376,7 @@ export class Creature {
     public initiative: Dice,
     public inventory: I.Map<ItemID, number>,
     public size: AABB,
+    public sight: number,
   ) { }
 
   dynamic_conditions(): I.Map<ConditionID, AppliedCondition> {
@@ -376,6 +420,7 @@ export interface SceneCreation {
   background_image_url: string;
   background_image_offset: [number, number] | undefined;
   background_image_scale: [number, number];
+  fog_of_war: boolean;
 }
 
 export interface Scene {
@@ -393,6 +438,7 @@ export interface Scene {
   background_image_offset: [number, number] | undefined;
   background_image_scale: [number, number];
   volume_conditions: I.Map<ConditionID, VolumeCondition>;
+  fog_of_war: boolean;
   focused_creatures: I.List<CreatureID>;
 }
 
@@ -411,11 +457,17 @@ export type Dice =
   | { t: "Flat"; val: number }
   | { t: "Expr"; num: number; size: number }
   | { t: "Plus"; left: Dice; right: Dice }
-  | { t: "BestOf"; num: number; dice: Dice };
+  | { t: "BestOf"; num: number; dice: Dice }
+  | { t: "WorstOf"; num: number; dice: Dice }
+  | { t: "KeepHighest"; num: number; size: number; keep: number }
+  | { t: "KeepLowest"; num: number; size: number; keep: number }
+  | { t: "Exploding"; num: number; size: number }
+  | { t: "Reroll"; num: number; size: number; threshold: number };
 
 export type PotentialTargets =
   | { t: "CreatureIDs"; cids: Array<CreatureID> }
   | { t: "Points"; points: Array<Point3> }
+  | { t: "VolumePlacements"; placements: Array<[Point3, Array<CreatureID>]> }
   ;
 
 export type RustResult<T, E> =
@@ -440,13 +492,15 @@ export const decodePotentialTargets = sum<PotentialTargets>("PotentialTargets",
   CreatureIDs: JD.map((cids): PotentialTargets =>
     ({ t: "CreatureIDs", cids }), JD.array(JD.string())),
   Points: JD.map((points): PotentialTargets => ({ t: "Points", points }), JD.array(decodePoint3)),
+  VolumePlacements: JD.map((placements): PotentialTargets => ({ t: "VolumePlacements", placements }),
+    JD.array(JD.tuple(decodePoint3, JD.array(JD.string())))),
 });
 
 const decodeDiceLazy = JD.lazy(() => decodeDice);
 const decodeConditionLazy = JD.lazy(() => decodeCondition);
 const decodeEffectLazy = JD.lazy(() => decodeEffect);
 
-const decodeDice: Decoder<Dice> = sum<Dice>("Dice", {}, {
+const decodeStructuredDice: Decoder<Dice> = sum<Dice>("Dice", {}, {
   BestOf: JD.map(
     ([num, dice]): Dice => ({ t: "BestOf", num, dice }),
     JD.tuple(JD.number(), decodeDiceLazy)),
@@ -459,6 +513,11 @@ const decodeDice: Decoder<Dice> = sum<Dice>("Dice", {}, {
     JD.tuple(decodeDiceLazy, decodeDiceLazy)),
 });
 
+// Dice are serialized in their textual notation, e.g. "2d6+1d4-2", but the structured form is
+// still accepted.
+const decodeDice: Decoder<Dice> =
+  JD.oneOf(JD.map(DiceNotation.parse, JD.string()), decodeStructuredDice);
+
 const decodeDuration: Decoder<Duration> =
   sum<Duration>("Duration", { Interminate: { t: "Interminate" } },
     {
@@ -532,6 +591,14 @@ function objectBig<T, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O>(
   _cons: (
     a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L, m: M, n: N, o: O,
   ) => T): Decoder<T>;
+function objectBig<T, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P>(
+  _ad: JD.EntryDecoder<A>, _bd: JD.EntryDecoder<B>, _cd: JD.EntryDecoder<C>, _dd: JD.EntryDecoder<D>,
+  _ed: JD.EntryDecoder<E>, _fd: JD.EntryDecoder<F>, _gd: JD.EntryDecoder<G>, _hd: JD.EntryDecoder<H>,
+  _id: JD.EntryDecoder<I>, _jd: JD.EntryDecoder<J>, _kd: JD.EntryDecoder<K>, _ld: JD.EntryDecoder<L>,
+  _md: JD.EntryDecoder<M>, _nd: JD.EntryDecoder<N>, _od: JD.EntryDecoder<O>, _pd: JD.EntryDecoder<P>,
+  _cons: (
+    a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L, m: M, n: N, o: O,
+    p: P) => T): Decoder<T>;
 function objectBig<T, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R>(
   _ad: JD.EntryDecoder<A>, _bd: JD.EntryDecoder<B>, _cd: JD.EntryDecoder<C>, _dd: JD.EntryDecoder<D>,
   _ed: JD.EntryDecoder<E>, _fd: JD.EntryDecoder<F>, _gd: JD.EntryDecoder<G>, _hd: JD.EntryDecoder<H>,
@@ -550,6 +617,15 @@ function objectBig<T, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S>(
   _cons: (
     a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L, m: M, n: N, o: O,
     p: P, q: Q, r: R, s: S) => T): Decoder<T>;
+function objectBig<T, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, U>(
+  _ad: JD.EntryDecoder<A>, _bd: JD.EntryDecoder<B>, _cd: JD.EntryDecoder<C>, _dd: JD.EntryDecoder<D>,
+  _ed: JD.EntryDecoder<E>, _fd: JD.EntryDecoder<F>, _gd: JD.EntryDecoder<G>, _hd: JD.EntryDecoder<H>,
+  _id: JD.EntryDecoder<I>, _jd: JD.EntryDecoder<J>, _kd: JD.EntryDecoder<K>, _ld: JD.EntryDecoder<L>,
+  _md: JD.EntryDecoder<M>, _nd: JD.EntryDecoder<N>, _od: JD.EntryDecoder<O>, _pd: JD.EntryDecoder<P>,
+  _qd: JD.EntryDecoder<Q>, _rd: JD.EntryDecoder<R>, _sd: JD.EntryDecoder<S>, _ud: JD.EntryDecoder<U>,
+  _cons: (
+    a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L, m: M, n: N, o: O,
+    p: P, q: Q, r: R, s: S, u: U) => T): Decoder<T>;
 function objectBig<T>(...args: Array<any>): Decoder<T> {
   return JD.object.apply(undefined, args);
 }
@@ -574,14 +650,15 @@ export const decodeCreature: Decoder<Creature> = objectBig(
   ["initiative", decodeDice],
   ["inventory", JD.map(I.Map, JD.dict(JD.number()))],
   ["size", decodeAABB],
+  ["sight", JD.number()],
   (
     id, name, speed, max_energy, cur_energy, abilities, class_, max_health, cur_health,
     own_conditions, volume_conditions, note, bio, portrait_url, icon_url, attributes, initiative,
-    inventory, size) =>
+    inventory, size, sight) =>
     new Creature(
       id, name, speed, max_energy, cur_energy, abilities, class_, max_health, cur_health,
       own_conditions, volume_conditions, note, bio, portrait_url, icon_url, attributes, initiative,
-      inventory, size
+      inventory, size, sight
     )
 );
 
@@ -594,8 +671,9 @@ const decodeCreatureCreation: Decoder<CreatureCreation> = JD.object(
   ["bio", JD.string()],
   ["initiative", decodeDice],
   ["size", decodeAABB],
-  (name, class_, portrait_url, icon_url, note, bio, initiative, size) =>
-    ({ name, class_, portrait_url, icon_url, note, bio, initiative, size })
+  ["sight", JD.number()],
+  (name, class_, portrait_url, icon_url, note, bio, initiative, size, sight) =>
+    ({ name, class_, portrait_url, icon_url, note, bio, initiative, size, sight })
 );
 
 export const decodeVisibility: Decoder<Visibility> = JD.map((x): Visibility => {
@@ -615,8 +693,9 @@ const decodeSceneCreation: Decoder<SceneCreation> = JD.object(
   ["background_image_url", JD.string()],
   ["background_image_scale", JD.tuple(JD.number(), JD.number())],
   ["background_image_offset", maybe(JD.tuple(JD.number(), JD.number()))],
-  (name, background_image_url, background_image_scale, background_image_offset) =>
-    ({ name, background_image_url, background_image_scale, background_image_offset })
+  ["fog_of_war", JD.boolean()],
+  (name, background_image_url, background_image_scale, background_image_offset, fog_of_war) =>
+    ({ name, background_image_url, background_image_scale, background_image_offset, fog_of_war })
 );
 
 const decodeVolume: Decoder<Volume> = sum("Volume", {},
@@ -667,15 +746,16 @@ export const decodeScene: Decoder<Scene> =
     ["background_image_offset", maybe(JD.tuple(JD.number(), JD.number()))],
     ["background_image_scale", JD.tuple(JD.number(), JD.number())],
     ["volume_conditions", JD.map(I.Map, JD.dict(decodeVolumeCondition))],
+    ["fog_of_war", JD.boolean()],
     ["focused_creatures",
       JD.map(I.List as ((arr: Array<string>) => I.List<CreatureID>), JD.array(JD.string()))],
     (
       id, name, terrain, highlights, annotations, scene_hotspots, related_scenes, creatures,
       attribute_checks, inventory, background_image_url, background_image_offset,
-      background_image_scale, volume_conditions, focused_creatures): Scene => ({
+      background_image_scale, volume_conditions, fog_of_war, focused_creatures): Scene => ({
         id, name, terrain, highlights, annotations, scene_hotspots, related_scenes, creatures,
         attribute_checks, inventory, background_image_url, background_image_offset,
-        background_image_scale, volume_conditions, focused_creatures,
+        background_image_scale, volume_conditions, fog_of_war, focused_creatures,
       }));
 
 function _mkFolderItem(t: string): Decoder<FolderItemID> {
@@ -728,6 +808,11 @@ export const decodeInventoryOwner: Decoder<InventoryOwner> = sum<InventoryOwner>
 const decodeModuleSource: Decoder<ModuleSource> =
   JD.oneOf(JD.equal('Module' as ModuleSource), JD.equal('SavedGame' as ModuleSource));
 
+const decodeVettingPolicy: Decoder<VettingPolicy> =
+  JD.oneOf(
+    JD.equal('Never' as VettingPolicy), JD.equal('ActionsOnNPCs' as VettingPolicy),
+    JD.equal('AllCommands' as VettingPolicy));
+
 const decodeCreatureLog: Decoder<CreatureLog> =
   sum<CreatureLog>("CreatureLog", {}, {
     Damage: JD.map(
@@ -767,8 +852,12 @@ const decodeCombatLog: Decoder<CombatLog> =
         JD.array(JD.tuple(JD.string(), JD.number()))),
     });
 
+const decodeGameLogLazy: Decoder<GameLog> = JD.lazy(() => decodeGameLog);
+
 export const decodeGameLog: Decoder<GameLog> =
-  sum<GameLog>("GameLog", { StopCombat: { t: "StopCombat" } }, {
+  sum<GameLog>("GameLog", {
+    StopCombat: { t: "StopCombat" }, Undo: { t: "Undo" }, Redo: { t: "Redo" },
+  }, {
     SetActiveScene: JD.map((scene_id): GameLog => ({ t: "SetActiveScene", scene_id }), JD.string()),
     RegisterPlayer: JD.map(
       (player_id): GameLog => ({ t: "RegisterPlayer", player_id }),
@@ -935,11 +1024,24 @@ export const decodeGameLog: Decoder<GameLog> =
     Rollback: JD.map(
       ([snapshot_index, log_index]): GameLog => ({ t: "Rollback", snapshot_index, log_index }),
       JD.tuple(JD.number(), JD.number())),
+    SwitchBranch: JD.map((branch): GameLog => ({ t: "SwitchBranch", branch }), JD.number()),
+    SetVettingPolicy: JD.map(
+      (policy): GameLog => ({ t: "SetVettingPolicy", policy }), decodeVettingPolicy),
     LoadModule: JD.object(
       ["name", JD.string()], ["path", decodeFolderPath], ["source", decodeModuleSource],
       (name, path, source): GameLog => ({ t: "LoadModule", name, path, source })),
+    EditLog: JD.object(
+      ["snapshot_idx", JD.number()], ["log_idx", JD.number()], ["log", decodeGameLogLazy],
+      ["invalidated", JD.array(JD.lazy(() => decodeInvalidatedLog))],
+      (snapshot_index, log_index, log, invalidated): GameLog =>
+        ({ t: "EditLog", snapshot_index, log_index, log, invalidated })),
   });
 
+const decodeInvalidatedLog: Decoder<InvalidatedLog> = JD.object(
+  ["snapshot_idx", JD.number()], ["log_idx", JD.number()], ["log", decodeGameLog],
+  ["error", JD.string()],
+  (snapshot_index, log_index, log, error) => ({ snapshot_index, log_index, log, error }));
+
 const decodePlayer: Decoder<Player> = JD.object(
   ["player_id", JD.string()],
   ["scene", maybe(JD.string())],
@@ -988,6 +1090,9 @@ const decodeFolder: Decoder<Folder> = JD.object(
   (data, children) => ({ data, children })
 );
 
+const decodePropagation: Decoder<Propagation> =
+  JD.oneOf(JD.equal('Burst' as Propagation), JD.equal('WrapAround' as Propagation));
+
 const decodeCreatureTarget: Decoder<CreatureTarget> = sum<CreatureTarget>("TargetSpec",
   {
     Actor: { t: "Actor" },
@@ -999,12 +1104,15 @@ const decodeCreatureTarget: Decoder<CreatureTarget> = sum<CreatureTarget>("Targe
     Range: JD.map((distance): CreatureTarget => ({ t: "Range", distance }), JD.number()),
     SomeCreaturesInVolumeInRange: JD.object(
       ["volume", decodeVolume], ["maximum", JD.number()], ["range", JD.number()],
-      (volume, maximum, range): CreatureTarget =>
-        ({ t: "SomeCreaturesInVolumeInRange", volume, maximum, range })),
+      ["propagation", decodePropagation],
+      (volume, maximum, range, propagation): CreatureTarget =>
+        ({ t: "SomeCreaturesInVolumeInRange", volume, maximum, range, propagation })),
     AllCreaturesInVolumeInRange: JD.object(
       ["volume", decodeVolume],
       ["range", JD.number()],
-      (volume, range): CreatureTarget => ({ t: "AllCreaturesInVolumeInRange", volume, range })),
+      ["propagation", decodePropagation],
+      (volume, range, propagation): CreatureTarget =>
+        ({ t: "AllCreaturesInVolumeInRange", volume, range, propagation })),
     LineFromActor: JD.object(
       ["distance", JD.number()],
       (distance): CreatureTarget => ({ t: "LineFromActor", distance })),
@@ -1044,21 +1152,57 @@ const decodeGame: Decoder<Game> = JD.object(
   ["abilities", JD.dict(decodeAbility)],
   ["campaign", decodeFolder],
   ["players", JD.map(I.Map, JD.dict(decodePlayer))],
-  (current_combat, creatures, classes, items, scenes, abilities, campaign, players) =>
-    ({ current_combat, creatures, classes, items, scenes, abilities, campaign, players })
+  ["vetting_policy", decodeVettingPolicy],
+  (current_combat, creatures, classes, items, scenes, abilities, campaign, players,
+    vetting_policy) =>
+    ({
+      current_combat, creatures, classes, items, scenes, abilities, campaign, players,
+      vetting_policy,
+    })
+);
+
+const decodeBranchPoint: Decoder<BranchPoint> = JD.object(
+  ["branch", JD.number()],
+  ["snapshot_idx", JD.number()],
+  ["log_idx", JD.number()],
+  (branch, snapshot_idx, log_idx) => ({ branch, snapshot_idx, log_idx })
+);
+
+const decodeBranchInfo: Decoder<BranchInfo> = JD.object(
+  ["parent", maybe(decodeBranchPoint)],
+  ["log_count", JD.number()],
+  (parent, log_count) => ({ parent, log_count })
 );
 
 export const decodeApp: Decoder<App> = JD.object(
   ["snapshots", JD.array(JD.map(
     ls => ({ snapshot: {}, logs: ls }),
     JD.at([1], JD.array(decodeGameLog))))],
+  ["branches", JD.array(decodeBranchInfo)],
+  ["current_branch", JD.number()],
+  ["undone", JD.number()],
+  ["pending", JD.map(I.Map, JD.dict(JD.array(decodeGameLog)))],
   ["current_game", decodeGame],
-  (snapshots, current_game) => ({ snapshots, current_game })
+  (snapshots, branches, current_branch, undone, pending, current_game) =>
+    ({ snapshots, branches, current_branch, undone, pending, current_game })
 );
 
-export const decodeSendCommandResult: Decoder<[Game, Array<GameLog>]> = JD.tuple(
-  decodeGame,
-  JD.array(decodeGameLog));
+export const decodeSendCommandResult: Decoder<Array<GameLog>> = JD.array(decodeGameLog);
+
+// Pushed from the server as the app changes; see ptrpi's `events` module. `Logs` events also
+// have a patch for the game, which has to be applied before decoding it.
+export type AppEvent =
+  | { t: "App"; app: App }
+  | { t: "Logs"; cursor: string; logs: Array<GameLog>; pending: I.Map<PlayerID, Array<GameLog>> };
+
+export const decodeAppEvent: Decoder<AppEvent> = sum<AppEvent>("AppEvent", {}, {
+  App: JD.map((app): AppEvent => ({ t: "App", app }), decodeApp),
+  Logs: JD.object(
+    ["cursor", JD.string()],
+    ["logs", JD.array(decodeGameLog)],
+    ["pending", JD.map(I.Map, JD.dict(JD.array(decodeGameLog)))],
+    (cursor, logs, pending): AppEvent => ({ t: "Logs", cursor, logs, pending })),
+});
 
 
 export function decodeRustResult<T, E>(decode_ok: Decoder<T>, decode_err: Decoder<E>
@@ -1224,6 +1368,20 @@ export function encodeGameCommand(cmd: GameCommand): object | string {
       return { SetPlayerScene: [cmd.player_id, cmd.scene_id] };
     case "Rollback":
       return { Rollback: [cmd.snapshot_index, cmd.log_index] };
+    case "SwitchBranch":
+      return { SwitchBranch: cmd.branch };
+    case "SetVettingPolicy":
+      return { SetVettingPolicy: cmd.policy };
+    case "ApprovePendingCommand":
+      return { ApprovePendingCommand: cmd.player_id };
+    case "RejectPendingCommand":
+      return { RejectPendingCommand: cmd.player_id };
+    case "EditPendingCommand":
+      return { EditPendingCommand: [cmd.player_id, encodeGameCommand(cmd.command)] };
+    case "Undo":
+      return "Undo";
+    case "Redo":
+      return "Redo";
     case "LoadModule":
       return {
         LoadModule: {
@@ -1256,6 +1414,7 @@ function encodeCreatureCreation(cc: CreatureCreation): object {
     bio: cc.bio,
     initiative: encodeDice(cc.initiative),
     size: encodeAABB(cc.size),
+    sight: cc.sight,
   };
 }
 
@@ -1305,13 +1464,8 @@ function encodeNote(note: Note): object {
   return note;
 }
 
-function encodeDice(d: Dice): object {
-  switch (d.t) {
-    case "Flat": return { Flat: d.val };
-    case "Expr": return { Expr: { num: d.num, size: d.size } };
-    case "Plus": return { Plus: [encodeDice(d.left), encodeDice(d.right)] };
-    case "BestOf": return { BestOf: [d.num, encodeDice(d.dice)] };
-  }
+function encodeDice(d: Dice): string {
+  return DiceNotation.format(d);
 }
 
 function encodeDecidedTarget(dt: DecidedTarget): object | string {
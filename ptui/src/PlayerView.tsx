@@ -4,6 +4,7 @@ import * as React from "react";
 
 import * as CV from "./CommonView";
 import * as Grid from './Grid';
+import * as History from './History';
 import * as M from './Model';
 import * as T from './PTTypes';
 
@@ -31,7 +32,7 @@ export const PlayerMain = M.connectRedux(
               <Menu compact={true}>
                 {ptui.app.current_game.players.keySeq().toArray().map(pid =>
                   <Menu.Item key={pid}
-                    onClick={() => dispatch({ type: "SetPlayerID", pid })}
+                    onClick={() => dispatch(M.selectPlayer(pid))}
                     name={pid} />)
                 }
               </Menu>
@@ -45,8 +46,7 @@ export const PlayerMain = M.connectRedux(
     }
 
     function registerPlayer(name: string) {
-      ptui.sendCommand(dispatch, { t: "RegisterPlayer", player_id: name });
-      dispatch({ type: "SetPlayerID", pid: name });
+      dispatch(M.registerPlayer(name));
     }
   });
 
@@ -59,7 +59,10 @@ export const PlayerGameView = M.connectRedux((
   const combat = ptui.app.current_game.current_combat;
   const tabs = [
     <CV.Tab key="Creatures" name="Creatures">
-      <PlayerCreatures player={player} />
+      <div>
+        <PendingCommand player={player} />
+        <PlayerCreatures player={player} />
+      </div>
     </CV.Tab>,
     <CV.Tab key="Notes" name="Notes">
       <PlayerNote player_id={player.player_id} />
@@ -115,6 +118,19 @@ function creatureMenuActions(
   }
 }
 
+/** Show what this player's last command will do, if it's waiting for the GM to approve it. */
+const PendingCommand = M.connectRedux((props: { player: T.Player } & M.ReduxProps)
+  : JSX.Element | null => {
+  const { player, ptui } = props;
+  const logs = ptui.app.pending.get(player.player_id);
+  if (!logs) { return null; }
+  return <div>
+    <strong>Waiting for the GM to approve:</strong>
+    {logs.map((log, idx) =>
+      <History.GameLog key={idx} log={log} creatures={ptui.app.current_game.creatures} />)}
+  </div>;
+});
+
 const PlayerCreatures = M.connectRedux((props: { player: T.Player } & M.ReduxProps): JSX.Element => {
   const cids = props.player.creatures;
   const creatures = props.ptui.getCreatures(cids);
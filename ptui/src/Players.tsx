@@ -7,6 +7,7 @@ import { Button, List, Table } from 'semantic-ui-react';
 import * as Campaign from './Campaign';
 import * as CV from './CommonView';
 import * as Comp from './Component';
+import * as History from './History';
 import * as M from './Model';
 import * as T from './PTTypes';
 
@@ -18,7 +19,13 @@ interface RuntimePlayerData {
   scene_name: string;
 }
 
-interface PlayersDerivedProps { gm_scene?: T.Scene; player_creatures: Array<RuntimePlayerData>; }
+interface PlayersDerivedProps {
+  gm_scene?: T.Scene;
+  player_creatures: Array<RuntimePlayerData>;
+  pending: I.Map<T.PlayerID, Array<T.GameLog>>;
+  vetting_policy: T.VettingPolicy;
+  creatures: I.Map<T.CreatureID, T.Creature>;
+}
 export const Players = ReactRedux.connect(
   Comp.createDeepEqualSelector(
     [(ptui: M.PTUI) => ptui.app,
@@ -27,6 +34,9 @@ export const Players = ReactRedux.connect(
     ],
     (app, players, gm_scene) => ({
       gm_scene,
+      pending: app.pending,
+      vetting_policy: app.current_game.vetting_policy,
+      creatures: app.current_game.creatures,
       player_creatures: players.valueSeq().toArray().map(
         player => {
           const scene = player.scene ? app.current_game.scenes.get(player.scene) : undefined;
@@ -41,13 +51,27 @@ export const Players = ReactRedux.connect(
   (dispatch: M.Dispatch) => ({ dispatch }),
 )(
   function Players(props: PlayersDerivedProps & M.DispatchProps): JSX.Element {
-    const { gm_scene, player_creatures, dispatch } = props;
-    return <Table celled={true}>
+    const { gm_scene, player_creatures, pending, vetting_policy, creatures, dispatch } = props;
+    const policies: Array<[T.VettingPolicy, string]> =
+      [["Never", "Never"], ["ActionsOnNPCs", "Actions on NPCs"], ["AllCommands", "Everything"]];
+    return <div>
+    <div>
+      Approve player commands:{" "}
+      <Button.Group size="small">
+        {policies.map(([policy, name]) =>
+          <Button key={policy} active={policy === vetting_policy}
+            onClick={() => dispatch(M.sendCommand({ t: "SetVettingPolicy", policy }))}>
+            {name}
+          </Button>)}
+      </Button.Group>
+    </div>
+    <Table celled={true}>
       <Table.Header>
         <Table.Row>
           <Table.HeaderCell>Player</Table.HeaderCell>
           <Table.HeaderCell>Creatures</Table.HeaderCell>
           <Table.HeaderCell>Scene</Table.HeaderCell>
+          <Table.HeaderCell>Pending</Table.HeaderCell>
           <Table.HeaderCell>Actions</Table.HeaderCell>
         </Table.Row>
       </Table.Header>
@@ -76,6 +100,9 @@ export const Players = ReactRedux.connect(
               <Table.Cell>
                 {playa.scene_name}
               </Table.Cell>
+              <Table.Cell>
+                {pendingCommand(playa.id)}
+              </Table.Cell>
               <Table.Cell>
                 <Button.Group vertical={true}>
                   {sceneButtons}
@@ -91,7 +118,26 @@ export const Players = ReactRedux.connect(
           })
         }
       </Table.Body>
-    </Table>;
+    </Table>
+    </div>;
+
+    function pendingCommand(player_id: T.PlayerID): JSX.Element | null {
+      const logs = pending.get(player_id);
+      if (!logs) { return null; }
+      return <div>
+        {logs.map((log, idx) => <History.GameLog key={idx} log={log} creatures={creatures} />)}
+        <Button.Group size="small">
+          <Button
+            onClick={() => dispatch(M.sendCommand({ t: "ApprovePendingCommand", player_id }))}>
+            Approve
+          </Button>
+          <Button
+            onClick={() => dispatch(M.sendCommand({ t: "RejectPendingCommand", player_id }))}>
+            Reject
+          </Button>
+        </Button.Group>
+      </div>;
+    }
 
     function setSceneButton(player_id: T.PlayerID, text: string, scene_id: T.SceneID | undefined)
       : JSX.Element {
@@ -15,8 +15,17 @@ function getInnerComponent(component_name: string): JSX.Element {
 
 function PT_renderMain(rpi_url: string, component_name: string, id: string) {
   const el = document.getElementById(id);
+  // When the server requires credentials, players can't see the whole game, so they say who they
+  // are with a `player` query parameter (along with their `token`).
+  const query = new URLSearchParams(window.location.search);
+  const player_id = query.get("player") || undefined;
+  // Games other than the one the server started with are in their own sessions.
+  const session = query.get("session");
+  if (session) {
+    rpi_url = `${rpi_url}/sessions/${encodeURIComponent(session)}`;
+  }
   ReactDOM.render(
-    <CV.Main rpi_url={rpi_url}>
+    <CV.Main rpi_url={rpi_url} player_id={component_name === "Player" ? player_id : undefined}>
       {getInnerComponent(component_name)}
     </CV.Main>,
     el);
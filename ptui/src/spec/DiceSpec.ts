@@ -29,15 +29,44 @@ describe("dice parsing", () => {
     expect(D.parse("1d20-2"))
       .toEqual({ t: "Plus", left: { t: "Expr", num: 1, size: 20 }, right: { t: "Flat", val: -2 } });
   });
-  // it("parses a plus with the flat on the left", () => {
-  //   expect(D.parse("1+1d20"))
-  //     .toEqual({ t: "Plus", left: { t: "Flat", val: 1 }, right: { t: "Expr", num: 1, size: 20 } });
-  // });
+  it("parses a plus with the flat on the left", () => {
+    expect(D.parse("1+1d20"))
+      .toEqual({ t: "Plus", left: { t: "Flat", val: 1 }, right: { t: "Expr", num: 1, size: 20 } });
+  });
+  it("parses sums left-associatively", () => {
+    expect(D.parse("2d6+1d4-2"))
+      .toEqual({
+        t: "Plus",
+        left: { t: "Plus", left: { t: "Expr", num: 2, size: 6 }, right: { t: "Expr", num: 1, size: 4 } },
+        right: { t: "Flat", val: -2 },
+      });
+  });
+  it("parses a best-of", () => {
+    expect(D.parse("best 2 of (1d20+3)"))
+      .toEqual({
+        t: "BestOf",
+        num: 2,
+        dice: { t: "Plus", left: { t: "Expr", num: 1, size: 20 }, right: { t: "Flat", val: 3 } },
+      });
+  });
   it("parses a BestOf", () => {
     expect(D.parse("BestOf(5, 1d20)"))
       .toEqual({ t: "BestOf", num: 5, dice: { t: "Expr", num: 1, size: 20 } });
   });
 
+  it("parses keep-highest and drop-lowest", () => {
+    expect(D.parse("4d6kh3")).toEqual({ t: "KeepHighest", num: 4, size: 6, keep: 3 });
+    expect(D.parse("4d6dl1")).toEqual({ t: "KeepHighest", num: 4, size: 6, keep: 3 });
+  });
+  it("parses exploding and reroll dice", () => {
+    expect(D.parse("2d6!")).toEqual({ t: "Exploding", num: 2, size: 6 });
+    expect(D.parse("2d6r1")).toEqual({ t: "Reroll", num: 2, size: 6, threshold: 1 });
+  });
+  it("parses a worst-of", () => {
+    expect(D.parse("worst 2 of (1d20)"))
+      .toEqual({ t: "WorstOf", num: 2, dice: { t: "Expr", num: 1, size: 20 } });
+  });
+
   it("ignores whitespace", () => {
     expect(D.parse(" BestOf ( 5 ,1 d 20 ) "))
       .toEqual({ t: "BestOf", num: 5, dice: { t: "Expr", num: 1, size: 20 } });
@@ -64,6 +93,6 @@ describe("Dice formatting", () => {
   })
   it("formats a BestOf", () => {
     expect(D.format({ t: "BestOf", num: 20, dice: { t: "Expr", num: 50, size: 100 } }))
-      .toEqual("BestOf(20, 50d100)");
+      .toEqual("best 20 of (50d100)");
   })
 });
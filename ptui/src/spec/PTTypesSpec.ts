@@ -77,6 +77,7 @@ export function test(): boolean {
     background_image_offset: undefined,
     background_image_scale: [0, 0],
     volume_conditions: I.Map(),
+    fog_of_war: false,
     focused_creatures: I.List(),
   };
   assertEq<T.Scene>(
@@ -134,6 +135,7 @@ describe("PTTypes", () => {
       size: { x: 1, y: 1, z: 1 },
       inventory: {},
       bio: "",
+      sight: 1000,
     };
     const creature = T.decodeCreature.decodeAny(sample);
     expect(creature.initiative).toEqual({